@@ -0,0 +1,146 @@
+// Generate the keysym name tables, the KnownKeysym enum and the Unicode names of the
+// characters of the keysyms from src/ffi/keysyms.rs
+
+use std::collections::HashSet;
+use std::env;
+use std::fs::File;
+use std::io::{Read, Write};
+use std::path::Path;
+
+const KNOWN_KEYSYM_DOC: &'static str = r#"/// A keysym known to this version of the crate
+///
+/// One variant per keysym of `keysyms`, named after its canonical name (names
+/// starting with a digit, like `3270_Duplicate`, get a `_` prefix), plus `Other` for
+/// the keysyms without one. This makes it possible to `match` on keysyms without
+/// importing the constants:
+///
+/// ```ignore
+/// match KnownKeysym::from_raw(event.keysym) {
+///     KnownKeysym::Escape => close(),
+///     KnownKeysym::Return | KnownKeysym::KP_Enter => validate(),
+///     _ => {}
+/// }
+/// ```
+///
+/// New variants are added when the keysym list is updated, and a keysym previously
+/// seen as `Other` then gets its variant: always keep a wildcard arm.
+"#;
+
+fn main() {
+    println!("cargo:rerun-if-changed=src/ffi/keysyms.rs");
+
+    let mut source = String::new();
+    File::open("src/ffi/keysyms.rs")
+        .and_then(|mut f| f.read_to_string(&mut source))
+        .expect("Could not read src/ffi/keysyms.rs");
+
+    // lines are like `pub const XKB_KEY_a :u32 = 0x0061;  /* ... */`
+    let mut keysyms: Vec<(String, u32)> = Vec::new();
+    // the comments of the keysyms of characters are like `/* U+0061 LATIN SMALL LETTER A */`,
+    // approximate ones have the name in parentheses
+    let mut unicode_names: Vec<(u32, String)> = Vec::new();
+    for line in source.lines() {
+        if let Some(start) = line.find("/* U+") {
+            let comment = &line[start + "/* U+".len()..];
+            let comment = comment.split("*/").next().unwrap_or("").trim();
+            let mut parts = comment.splitn(2, ' ');
+            let codepoint = parts.next().and_then(|code| u32::from_str_radix(code, 16).ok());
+            if let (Some(codepoint), Some(name)) = (codepoint, parts.next()) {
+                unicode_names.push((codepoint, name.trim().to_owned()));
+            }
+        }
+        if !line.starts_with("pub const XKB_KEY_") {
+            continue;
+        }
+        let mut parts = line["pub const XKB_KEY_".len()..].splitn(2, ':');
+        let name = parts.next().unwrap_or("").trim();
+        let value = parts
+            .next()
+            .and_then(|rest| rest.split('=').nth(1))
+            .and_then(|rest| rest.split(';').next())
+            .map(|value| value.trim())
+            .map(|value| if value.starts_with("0x") { &value[2..] } else { value })
+            .and_then(|value| u32::from_str_radix(value, 16).ok());
+        if let Some(value) = value {
+            keysyms.push((name.to_owned(), value));
+        }
+    }
+
+    let mut by_name = keysyms.clone();
+    by_name.sort();
+    by_name.dedup_by(|a, b| a.0 == b.0);
+
+    // the first name of a keysym is the canonical one, the next are aliases
+    let mut seen = HashSet::new();
+    let mut by_value: Vec<(u32, String)> = keysyms
+        .iter()
+        .filter(|&&(_, value)| seen.insert(value))
+        .map(|&(ref name, value)| (value, name.clone()))
+        .collect();
+    by_value.sort();
+
+    let out = Path::new(&env::var("OUT_DIR").unwrap()).join("keysym_names.rs");
+    let mut out = File::create(out).unwrap();
+    writeln!(out, "static KEYSYMS_BY_NAME: &'static [(&'static str, u32)] = &[").unwrap();
+    for &(ref name, value) in &by_name {
+        writeln!(out, "    ({:?}, 0x{:x}),", name, value).unwrap();
+    }
+    writeln!(out, "];").unwrap();
+    writeln!(out, "static KEYSYMS_BY_VALUE: &'static [(u32, &'static str)] = &[").unwrap();
+    for &(value, ref name) in &by_value {
+        writeln!(out, "    (0x{:x}, {:?}),", value, name).unwrap();
+    }
+    writeln!(out, "];").unwrap();
+
+    unicode_names.sort();
+    unicode_names.dedup_by(|a, b| a.0 == b.0);
+    let out = Path::new(&env::var("OUT_DIR").unwrap()).join("unicode_names.rs");
+    let mut out = File::create(out).unwrap();
+    writeln!(out, "static UNICODE_NAMES: &'static [(u32, &'static str)] = &[").unwrap();
+    for &(codepoint, ref name) in &unicode_names {
+        writeln!(out, "    (0x{:x}, {:?}),", codepoint, name).unwrap();
+    }
+    writeln!(out, "];").unwrap();
+
+    // variants use the canonical name, prefixed when it is not a valid identifier
+    let variants: Vec<(u32, String)> = by_value
+        .iter()
+        .map(|&(value, ref name)| {
+            if name.starts_with(|c: char| c.is_digit(10)) {
+                (value, format!("_{}", name))
+            } else {
+                (value, name.clone())
+            }
+        })
+        .collect();
+
+    let out = Path::new(&env::var("OUT_DIR").unwrap()).join("known_keysym.rs");
+    let mut out = File::create(out).unwrap();
+    out.write_all(KNOWN_KEYSYM_DOC.as_bytes()).unwrap();
+    writeln!(out, "#[allow(non_camel_case_types)]").unwrap();
+    writeln!(out, "#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]").unwrap();
+    writeln!(out, "pub enum KnownKeysym {{").unwrap();
+    for &(value, ref variant) in &variants {
+        writeln!(out, "    /// Keysym `0x{:x}`", value).unwrap();
+        writeln!(out, "    {},", variant).unwrap();
+    }
+    writeln!(out, "    /// A keysym without a variant, like most Unicode keysyms").unwrap();
+    writeln!(out, "    Other(u32),").unwrap();
+    writeln!(out, "}}").unwrap();
+    writeln!(out, "fn known_from_raw(keysym: u32) -> KnownKeysym {{").unwrap();
+    writeln!(out, "    match keysym {{").unwrap();
+    for &(value, ref variant) in &variants {
+        writeln!(out, "        0x{:x} => KnownKeysym::{},", value, variant).unwrap();
+    }
+    writeln!(out, "        other => KnownKeysym::Other(other),").unwrap();
+    writeln!(out, "    }}").unwrap();
+    writeln!(out, "}}").unwrap();
+    writeln!(out, "fn known_to_raw(keysym: KnownKeysym) -> u32 {{").unwrap();
+    writeln!(out, "    match keysym {{").unwrap();
+    for &(value, ref variant) in &variants {
+        writeln!(out, "        KnownKeysym::{} => 0x{:x},", variant, value).unwrap();
+    }
+    writeln!(out, "        KnownKeysym::Other(other) => other,").unwrap();
+    writeln!(out, "    }}").unwrap();
+    writeln!(out, "}}").unwrap();
+}
@@ -29,29 +29,60 @@ fn shell_surface_implementation() -> wl_shell_surface::Implementation<()> {
 
 fn kbd_implementation() -> MappedKeyboardImplementation<()> {
     MappedKeyboardImplementation {
-        enter: |_, _, _, _, _, mods, _, keysyms| {
+        enter: |_, _, _, _, _, _, mods, _, keysyms| {
             println!(
                 "Gained focus while {} keys pressed and modifiers are {:?}.",
                 keysyms.len(),
                 mods
             );
         },
-        leave: |_, _, _, _, _| {
+        leave: |_, _, _, _, _, _| {
             println!("Lost focus.");
         },
-        key: |_, _, _, _, _, _, _, sym, state, utf8| {
-            println!("Key {:?}: {:x}.", state, sym);
-            if let Some(txt) = utf8 {
+        key: |_, _, _, identity, event| {
+            println!(
+                "Key {:?} on keyboard {}: {:x} ({:?}) in layout {}.",
+                event.state, identity.id, event.keysym, event.kind, event.layout
+            );
+            if let Some(txt) = event.utf8 {
                 println!("Received text \"{}\".", txt,);
             }
         },
-        repeat_info: |_, _, _, rate, delay| {
+        repeat_info: |_, _, _, _, rate, delay| {
             println!(
                 "Received repeat info: start repeating every {}ms after an initial delay of {}ms",
                 rate,
                 delay
             );
         },
+        compose: |_, _, _, _, event| {
+            println!("Compose event: {:?}", event);
+        },
+        shortcut: |_, _, _, _, _, _, shortcut| {
+            println!("Shortcut {:?} triggered.", shortcut);
+        },
+        chord: |_, _, _, _, _, _, chord| {
+            println!("Chord {:?} detected.", chord.keysyms);
+        },
+        gesture: |_, _, _, _, _, keysym, gesture| {
+            println!("Gesture {:?} on key {:x}.", gesture, keysym);
+        },
+        keymap_changed: |_, _, _, _, change| {
+            println!("Keymap changed, layouts are now {:?}.", change.layouts);
+        },
+        input_mode: |_, _, _, _, mode| {
+            println!("Input mode changed: {:?}.", mode);
+        },
+        stuck_modifiers: |_, _, _, _, _, stuck| {
+            println!("Modifiers released while unfocused: {:?}.", stuck);
+        },
+        layout_indicator: |_, _, _, _, indicator| {
+            println!("Active layout: {} ({}).", indicator.short_name, indicator.name);
+        },
+        layers: Vec::new(),
+        error: |_, _, _, _, error| {
+            println!("Keyboard error: {:?}.", error);
+        },
     }
 }
 
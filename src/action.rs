@@ -0,0 +1,166 @@
+use ffi::keysyms;
+use mapped_keyboard::ModifiersState;
+
+/// A semantic action triggered by a key or a conventional shortcut
+///
+/// This is an optional convenience for simple applications, see `Action::from_key()`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Action {
+    /// Copy the selection
+    Copy,
+    /// Cut the selection
+    Cut,
+    /// Paste the clipboard
+    Paste,
+    /// Undo the last edit
+    Undo,
+    /// Redo the last undone edit
+    Redo,
+    /// Select everything
+    SelectAll,
+    /// Search
+    Find,
+    /// Save the document
+    Save,
+    /// Create a new document
+    New,
+    /// Open a document
+    Open,
+    /// Close the document or window
+    Close,
+    /// Raise the volume
+    VolumeUp,
+    /// Lower the volume
+    VolumeDown,
+    /// Mute the sound
+    Mute,
+    /// Mute the microphone
+    MicMute,
+    /// Start or pause playback
+    PlayPause,
+    /// Pause playback
+    Pause,
+    /// Stop playback
+    Stop,
+    /// Go to the next track
+    NextTrack,
+    /// Go to the previous track
+    PreviousTrack,
+    /// Go back, like in a browser
+    Back,
+    /// Go forward, like in a browser
+    Forward,
+    /// Reload the page
+    Refresh,
+    /// Go to the home page
+    HomePage,
+    /// Raise the brightness of the screen
+    BrightnessUp,
+    /// Lower the brightness of the screen
+    BrightnessDown,
+    /// Show help
+    Help,
+    /// Cancel the current operation
+    Cancel,
+}
+
+// actions of dedicated keys, regardless of the modifiers
+const KEY_ACTIONS: &'static [(u32, Action)] = &[
+    (keysyms::XKB_KEY_XF86Copy, Action::Copy),
+    (keysyms::XKB_KEY_XF86Cut, Action::Cut),
+    (keysyms::XKB_KEY_XF86Paste, Action::Paste),
+    (keysyms::XKB_KEY_Undo, Action::Undo),
+    (keysyms::XKB_KEY_Redo, Action::Redo),
+    (keysyms::XKB_KEY_XF86Select, Action::SelectAll),
+    (keysyms::XKB_KEY_Find, Action::Find),
+    (keysyms::XKB_KEY_XF86Search, Action::Find),
+    (keysyms::XKB_KEY_XF86Save, Action::Save),
+    (keysyms::XKB_KEY_XF86New, Action::New),
+    (keysyms::XKB_KEY_XF86Open, Action::Open),
+    (keysyms::XKB_KEY_XF86Close, Action::Close),
+    (keysyms::XKB_KEY_XF86AudioRaiseVolume, Action::VolumeUp),
+    (keysyms::XKB_KEY_XF86AudioLowerVolume, Action::VolumeDown),
+    (keysyms::XKB_KEY_XF86AudioMute, Action::Mute),
+    (keysyms::XKB_KEY_XF86AudioMicMute, Action::MicMute),
+    (keysyms::XKB_KEY_XF86AudioPlay, Action::PlayPause),
+    (keysyms::XKB_KEY_XF86AudioPause, Action::Pause),
+    (keysyms::XKB_KEY_XF86AudioStop, Action::Stop),
+    (keysyms::XKB_KEY_XF86AudioNext, Action::NextTrack),
+    (keysyms::XKB_KEY_XF86AudioPrev, Action::PreviousTrack),
+    (keysyms::XKB_KEY_XF86Back, Action::Back),
+    (keysyms::XKB_KEY_XF86Forward, Action::Forward),
+    (keysyms::XKB_KEY_XF86Reload, Action::Refresh),
+    (keysyms::XKB_KEY_XF86Refresh, Action::Refresh),
+    (keysyms::XKB_KEY_XF86HomePage, Action::HomePage),
+    (keysyms::XKB_KEY_XF86MonBrightnessUp, Action::BrightnessUp),
+    (keysyms::XKB_KEY_XF86MonBrightnessDown, Action::BrightnessDown),
+    (keysyms::XKB_KEY_Help, Action::Help),
+    (keysyms::XKB_KEY_Cancel, Action::Cancel),
+];
+
+// actions of the conventional Ctrl shortcuts, by lowercase latin letter
+const CTRL_ACTIONS: &'static [(u32, Action)] = &[
+    (keysyms::XKB_KEY_c, Action::Copy),
+    (keysyms::XKB_KEY_x, Action::Cut),
+    (keysyms::XKB_KEY_v, Action::Paste),
+    (keysyms::XKB_KEY_z, Action::Undo),
+    (keysyms::XKB_KEY_y, Action::Redo),
+    (keysyms::XKB_KEY_a, Action::SelectAll),
+    (keysyms::XKB_KEY_f, Action::Find),
+    (keysyms::XKB_KEY_s, Action::Save),
+    (keysyms::XKB_KEY_n, Action::New),
+    (keysyms::XKB_KEY_o, Action::Open),
+    (keysyms::XKB_KEY_w, Action::Close),
+];
+
+impl Action {
+    /// The action of a key press, if any
+    ///
+    /// Dedicated keys, like the XF86 multimedia keys or `Undo`, always trigger their
+    /// action. Other keys trigger the platform-conventional shortcuts:
+    ///
+    /// - `Ctrl+C`, `Ctrl+X`, `Ctrl+V`, `Ctrl+Z`, `Ctrl+A`, `Ctrl+F`, `Ctrl+S`,
+    ///   `Ctrl+N`, `Ctrl+O` and `Ctrl+W`
+    /// - `Ctrl+Y` and `Ctrl+Shift+Z` for redo
+    /// - `Ctrl+Insert`, `Shift+Delete` and `Shift+Insert` for copy, cut and paste
+    ///
+    /// Shortcuts are matched on the latin keysym, so they do not trigger with
+    /// non-latin layouts. Use `Shortcut` with `ShortcutMatching::AnyLayout` for that.
+    pub fn from_key(keysym: u32, mods: &ModifiersState) -> Option<Action> {
+        if let Some(&(_, action)) = KEY_ACTIONS.iter().find(|&&(sym, _)| sym == keysym) {
+            return Some(action);
+        }
+        if mods.alt() || mods.logo() {
+            return None;
+        }
+        if mods.ctrl() {
+            if mods.shift() {
+                return match keysym {
+                    keysyms::XKB_KEY_z | keysyms::XKB_KEY_Z => Some(Action::Redo),
+                    _ => None,
+                };
+            }
+            if keysym == keysyms::XKB_KEY_Insert {
+                return Some(Action::Copy);
+            }
+            // caps lock makes the letters uppercase
+            let lower = if keysym >= keysyms::XKB_KEY_A && keysym <= keysyms::XKB_KEY_Z {
+                keysym + (keysyms::XKB_KEY_a - keysyms::XKB_KEY_A)
+            } else {
+                keysym
+            };
+            return CTRL_ACTIONS
+                .iter()
+                .find(|&&(sym, _)| sym == lower)
+                .map(|&(_, action)| action);
+        }
+        if mods.shift() {
+            return match keysym {
+                keysyms::XKB_KEY_Delete => Some(Action::Cut),
+                keysyms::XKB_KEY_Insert => Some(Action::Paste),
+                _ => None,
+            };
+        }
+        None
+    }
+}
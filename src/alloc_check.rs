@@ -0,0 +1,112 @@
+//! Allocation counting for the `alloc-assertions` feature
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+use std::thread;
+
+/// A global allocator counting the allocations of each thread
+///
+/// With the `alloc-assertions` feature, the keyboards assert that updating the
+/// modifiers and looking up the keysym and layout of a key do not allocate. The
+/// allocations are only counted if this is the global allocator of the program,
+/// typically of its tests or benchmarks:
+///
+/// ```ignore
+/// #[global_allocator]
+/// static ALLOCATOR: wayland_kbd::CountingAllocator = wayland_kbd::CountingAllocator;
+/// ```
+///
+/// It delegates to the system allocator. The allocations of a `tracing` subscriber
+/// are counted too, so do not combine these assertions with the `tracing` feature.
+pub struct CountingAllocator;
+
+thread_local!(static ALLOCATIONS: Cell<u64> = Cell::new(0));
+
+fn count() {
+    // the counter of an exiting thread is gone, its allocations do not matter
+    let _ = ALLOCATIONS.try_with(|count| count.set(count.get() + 1));
+}
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        count();
+        System.alloc(layout)
+    }
+
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        count();
+        System.alloc_zeroed(layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        count();
+        System.realloc(ptr, layout, new_size)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+/// The number of allocations made by the current thread
+///
+/// It is always 0 if `CountingAllocator` is not the global allocator.
+pub fn thread_allocations() -> u64 {
+    ALLOCATIONS.try_with(|count| count.get()).unwrap_or(0)
+}
+
+/// Panics when dropped if the current thread allocated since its creation
+pub struct NoAlloc {
+    section: &'static str,
+    start: u64,
+}
+
+impl NoAlloc {
+    pub fn new(section: &'static str) -> NoAlloc {
+        NoAlloc {
+            section: section,
+            start: thread_allocations(),
+        }
+    }
+}
+
+impl Drop for NoAlloc {
+    fn drop(&mut self) {
+        let allocations = thread_allocations() - self.start;
+        if allocations > 0 && !thread::panicking() {
+            panic!("{} allocated {} times", self.section, allocations);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // for the whole test binary, so the modifiers updates of the other tests are checked too
+    #[global_allocator]
+    static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+    #[test]
+    fn counts_allocations() {
+        let before = thread_allocations();
+        let buffer = vec![0u8; 16];
+        assert_eq!(buffer.len(), 16);
+        assert!(thread_allocations() > before);
+    }
+
+    #[test]
+    fn no_alloc_without_allocation() {
+        let buffer = vec![0u8; 16];
+        let _no_alloc = NoAlloc::new("test section");
+        assert_eq!(buffer.iter().sum::<u8>(), 0);
+    }
+
+    #[test]
+    #[should_panic(expected = "test section allocated 1 times")]
+    fn no_alloc_fires() {
+        let _no_alloc = NoAlloc::new("test section");
+        let buffer = vec![0u8; 16];
+        assert_eq!(buffer.len(), 16);
+    }
+}
@@ -0,0 +1,120 @@
+#[cfg(feature = "compose")]
+use compose::ComposeMachine;
+use ffi;
+
+/// The optional features of the loaded libxkbcommon
+///
+/// Some functions of libxkbcommon were added in later versions. They are loaded when
+/// present, and the crate falls back to what older versions provide otherwise.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct XkbCapabilities {
+    /// Consumed modifiers can be computed like GTK does (libxkbcommon 0.7)
+    pub consumed_mode: bool,
+    /// Keysyms can be converted to lowercase and uppercase (libxkbcommon 0.8)
+    pub keysym_case: bool,
+    /// Unicode characters can be converted to keysyms (libxkbcommon 1.0)
+    pub utf32_to_keysym: bool,
+    /// The modifiers selecting each shift level can be listed (libxkbcommon 1.0)
+    pub mods_for_level: bool,
+    /// The sequences of compose tables can be listed (libxkbcommon 1.6), which
+    /// requires the `compose` feature
+    pub compose_iteration: bool,
+    /// Keymaps can be compiled to the second text format (libxkbcommon 1.11)
+    pub keymap_format_v2: bool,
+}
+
+/// Get the optional features of the loaded libxkbcommon
+///
+/// Everything is `false` if libxkbcommon could not be loaded.
+pub fn xkb_capabilities() -> XkbCapabilities {
+    if ffi::XKBCOMMON_OPTION.is_none() {
+        return XkbCapabilities::default();
+    }
+    XkbCapabilities {
+        consumed_mode: ffi::XKBCOMMON_CONSUMED_MODE_OPTION.is_some(),
+        keysym_case: ffi::XKBCOMMON_KEYSYM_CASE_OPTION.is_some(),
+        utf32_to_keysym: ffi::XKBCOMMON_UTF32_OPTION.is_some(),
+        mods_for_level: ffi::XKBCOMMON_MODS_FOR_LEVEL_OPTION.is_some(),
+        compose_iteration: compose_iteration_available(),
+        keymap_format_v2: ffi::XKBCOMMON_KEYMAP_FORMATS_OPTION.is_some(),
+    }
+}
+
+/// The optional subsystems available at runtime
+///
+/// Obtained from `features()`, so that applications can adapt their UI, like hiding
+/// a layout picker when the registry is not available.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Features {
+    /// libxkbcommon could be loaded, which is required to interpret key events
+    pub xkbcommon: bool,
+    /// A compose table can be loaded for the current locale, for dead keys and the
+    /// Compose key, which requires the `compose` feature
+    pub compose: bool,
+    /// All keysyms can be named, including Unicode ones, which requires libxkbcommon
+    ///
+    /// The other keysyms can always be named with the built-in table.
+    pub keysym_names: bool,
+    /// The models, layouts and options of the system can be listed with `Registry`,
+    /// which requires the `registry` feature and libxkbregistry
+    pub registry: bool,
+    /// The crate was built with the `tracing` feature
+    pub tracing: bool,
+    /// The crate was built with the `serde` feature
+    pub serde: bool,
+    /// The crate was built with the `terminal` feature
+    pub terminal: bool,
+    /// The crate was built with the `unicode-info` feature
+    pub unicode_info: bool,
+    /// The optional features of the loaded libxkbcommon
+    pub xkb: XkbCapabilities,
+}
+
+/// Probe the optional subsystems available at runtime
+///
+/// This loads the libraries if they were not already, and the compose table of the
+/// current locale, so it is better not called for each frame.
+pub fn features() -> Features {
+    let xkbcommon = ffi::XKBCOMMON_OPTION.is_some();
+    Features {
+        xkbcommon: xkbcommon,
+        compose: xkbcommon && compose_available(),
+        keysym_names: xkbcommon,
+        registry: registry_available(),
+        tracing: cfg!(feature = "tracing"),
+        serde: cfg!(feature = "serde"),
+        terminal: cfg!(feature = "terminal"),
+        unicode_info: cfg!(feature = "unicode-info"),
+        xkb: xkb_capabilities(),
+    }
+}
+
+#[cfg(feature = "registry")]
+fn registry_available() -> bool {
+    ffi::registry::XKBREGISTRY_OPTION.is_some()
+}
+
+#[cfg(not(feature = "registry"))]
+fn registry_available() -> bool {
+    false
+}
+
+#[cfg(feature = "compose")]
+fn compose_available() -> bool {
+    ComposeMachine::new().is_some()
+}
+
+#[cfg(not(feature = "compose"))]
+fn compose_available() -> bool {
+    false
+}
+
+#[cfg(feature = "compose")]
+fn compose_iteration_available() -> bool {
+    ffi::XKBCOMMON_COMPOSE_ITER_OPTION.is_some()
+}
+
+#[cfg(not(feature = "compose"))]
+fn compose_iteration_available() -> bool {
+    false
+}
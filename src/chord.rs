@@ -0,0 +1,121 @@
+use mapped_keyboard::{duration_ms, is_modifier_keysym, HeldKey};
+use std::time::Duration;
+
+/// A set of keys to be held simultaneously
+///
+/// A chord is detected when its keys are the only non-modifier keys held down,
+/// and they have all been pressed within `window` of each other. It is then
+/// notified through the `chord` callback, when its last key is pressed.
+///
+/// The `key` callback is still invoked for every key of the chord.
+#[derive(Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Chord {
+    /// The keysyms of the keys making up the chord, in any order
+    pub keysyms: Vec<u32>,
+    /// The maximum delay between the first and the last key press of the chord
+    pub window: Duration,
+}
+
+impl Chord {
+    // whether the key press at `time` triggers the chord
+    pub(crate) fn matches(&self, held: &[HeldKey], time: u32) -> bool {
+        if self.keysyms.is_empty() {
+            return false;
+        }
+        let window_ms = duration_ms(self.window);
+        let mut count = 0;
+        for key in held.iter().filter(|k| !is_modifier_keysym(k.keysym)) {
+            if !self.keysyms.contains(&key.keysym) {
+                return false;
+            }
+            match key.time {
+                Some(t) if u64::from(time.wrapping_sub(t)) <= window_ms => {}
+                // keys held since before the focus was gained or pressed too long ago
+                _ => return false,
+            }
+            count += 1;
+        }
+        count == self.keysyms.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ffi::keysyms;
+
+    fn a_s() -> Chord {
+        Chord {
+            keysyms: vec![keysyms::XKB_KEY_a, keysyms::XKB_KEY_s],
+            window: Duration::from_millis(50),
+        }
+    }
+
+    fn key(keysym: u32, time: Option<u32>) -> HeldKey {
+        HeldKey {
+            keycode: 0,
+            keysym: keysym,
+            time: time,
+        }
+    }
+
+    #[test]
+    fn any_order() {
+        let chord = a_s();
+        let a_first = [key(keysyms::XKB_KEY_a, Some(100)), key(keysyms::XKB_KEY_s, Some(120))];
+        assert!(chord.matches(&a_first, 120));
+        let s_first = [key(keysyms::XKB_KEY_s, Some(100)), key(keysyms::XKB_KEY_a, Some(120))];
+        assert!(chord.matches(&s_first, 120));
+    }
+
+    #[test]
+    fn window() {
+        let chord = a_s();
+        let at_limit = [key(keysyms::XKB_KEY_a, Some(100)), key(keysyms::XKB_KEY_s, Some(150))];
+        assert!(chord.matches(&at_limit, 150));
+        let too_late = [key(keysyms::XKB_KEY_a, Some(100)), key(keysyms::XKB_KEY_s, Some(151))];
+        assert!(!chord.matches(&too_late, 151));
+        // the timestamps wrap around
+        let wrapping = [
+            key(keysyms::XKB_KEY_a, Some(u32::max_value() - 10)),
+            key(keysyms::XKB_KEY_s, Some(10)),
+        ];
+        assert!(chord.matches(&wrapping, 10));
+    }
+
+    #[test]
+    fn only_chord_keys() {
+        let chord = a_s();
+        let incomplete = [key(keysyms::XKB_KEY_a, Some(100))];
+        assert!(!chord.matches(&incomplete, 100));
+        let extra = [
+            key(keysyms::XKB_KEY_a, Some(100)),
+            key(keysyms::XKB_KEY_d, Some(110)),
+            key(keysyms::XKB_KEY_s, Some(120)),
+        ];
+        assert!(!chord.matches(&extra, 120));
+        // modifiers may be held
+        let modifier = [
+            key(keysyms::XKB_KEY_Shift_L, None),
+            key(keysyms::XKB_KEY_a, Some(100)),
+            key(keysyms::XKB_KEY_s, Some(120)),
+        ];
+        assert!(chord.matches(&modifier, 120));
+    }
+
+    #[test]
+    fn held_before_focus() {
+        let chord = a_s();
+        let held = [key(keysyms::XKB_KEY_a, None), key(keysyms::XKB_KEY_s, Some(120))];
+        assert!(!chord.matches(&held, 120));
+    }
+
+    #[test]
+    fn empty() {
+        let chord = Chord {
+            keysyms: Vec::new(),
+            window: Duration::from_millis(50),
+        };
+        assert!(!chord.matches(&[], 0));
+    }
+}
@@ -0,0 +1,360 @@
+use ffi::keysyms;
+use mapped_keyboard::is_modifier_keysym;
+
+// evdev keycodes of the keys of the numeric keypad
+const KEYPAD_KEYCODES: &'static [u32] = &[
+    55,  // KEY_KPASTERISK
+    71,  // KEY_KP7
+    72,  // KEY_KP8
+    73,  // KEY_KP9
+    74,  // KEY_KPMINUS
+    75,  // KEY_KP4
+    76,  // KEY_KP5
+    77,  // KEY_KP6
+    78,  // KEY_KPPLUS
+    79,  // KEY_KP1
+    80,  // KEY_KP2
+    81,  // KEY_KP3
+    82,  // KEY_KP0
+    83,  // KEY_KPDOT
+    95,  // KEY_KPJPCOMMA
+    96,  // KEY_KPENTER
+    98,  // KEY_KPSLASH
+    117, // KEY_KPEQUAL
+    118, // KEY_KPPLUSMINUS
+    121, // KEY_KPCOMMA
+    179, // KEY_KPLEFTPAREN
+    180, // KEY_KPRIGHTPAREN
+];
+
+/// A broad classification of keys
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum KeyKind {
+    /// The key produces printable text
+    Printable,
+    /// The key is a non-printable special key, like Escape, Return, Tab or Delete,
+    /// or produces a control character
+    Control,
+    /// The key moves the cursor, like the arrows, Home, End or Page Up
+    Navigation,
+    /// The key is a function key, like F1
+    Function,
+    /// The key is a modifier, like Shift, Control or Caps Lock
+    Modifier,
+    /// The key is a multimedia, browser or launcher key (the XF86 keysyms)
+    Media,
+    /// Any other key, like dead keys
+    Other,
+}
+
+impl KeyKind {
+    /// Classify a key from its keysym and the text it produced
+    pub fn classify(keysym: u32, utf8: Option<&str>) -> KeyKind {
+        if is_modifier_keysym(keysym) {
+            return KeyKind::Modifier;
+        }
+        if keysym >= 0x1008_0001 && keysym <= 0x1008_ffff {
+            return KeyKind::Media;
+        }
+        if keysym >= keysyms::XKB_KEY_F1 && keysym <= keysyms::XKB_KEY_F35 {
+            return KeyKind::Function;
+        }
+        if (keysym >= keysyms::XKB_KEY_Home && keysym <= keysyms::XKB_KEY_Begin)
+            || (keysym >= keysyms::XKB_KEY_KP_Home && keysym <= keysyms::XKB_KEY_KP_Begin)
+        {
+            return KeyKind::Navigation;
+        }
+        if let Some(c) = utf8.and_then(|txt| txt.chars().next()) {
+            return if c.is_control() {
+                KeyKind::Control
+            } else {
+                KeyKind::Printable
+            };
+        }
+        // the remaining keysyms of the 0xff00 page are the special keys
+        if keysym >= 0xff00 && keysym <= 0xffff {
+            return KeyKind::Control;
+        }
+        KeyKind::Other
+    }
+
+    /// Whether this kind of key produces printable text
+    pub fn is_printable(&self) -> bool {
+        *self == KeyKind::Printable
+    }
+}
+
+/// Whether a key is on the numeric keypad
+///
+/// This is true if either the physical key is a keypad key or the keysym is a
+/// keypad keysym, like `KP_Enter` or `KP_1`.
+pub fn is_keypad(keycode: u32, keysym: u32) -> bool {
+    (keysym >= keysyms::XKB_KEY_KP_Space && keysym <= keysyms::XKB_KEY_KP_Equal)
+        || KEYPAD_KEYCODES.contains(&keycode)
+}
+
+/// A navigation or editing key
+///
+/// Keypad variants, like `KP_Home`, map to the same values as the main keys.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum NavKey {
+    /// The Up arrow
+    Up,
+    /// The Down arrow
+    Down,
+    /// The Left arrow
+    Left,
+    /// The Right arrow
+    Right,
+    /// Home
+    Home,
+    /// End
+    End,
+    /// Page Up
+    PageUp,
+    /// Page Down
+    PageDown,
+    /// Insert
+    Insert,
+    /// Delete
+    Delete,
+    /// Backspace
+    Backspace,
+}
+
+impl NavKey {
+    /// Get the navigation key of a keysym, if any
+    pub fn from_keysym(keysym: u32) -> Option<NavKey> {
+        match keysym {
+            keysyms::XKB_KEY_Up | keysyms::XKB_KEY_KP_Up => Some(NavKey::Up),
+            keysyms::XKB_KEY_Down | keysyms::XKB_KEY_KP_Down => Some(NavKey::Down),
+            keysyms::XKB_KEY_Left | keysyms::XKB_KEY_KP_Left => Some(NavKey::Left),
+            keysyms::XKB_KEY_Right | keysyms::XKB_KEY_KP_Right => Some(NavKey::Right),
+            keysyms::XKB_KEY_Home | keysyms::XKB_KEY_KP_Home => Some(NavKey::Home),
+            keysyms::XKB_KEY_End | keysyms::XKB_KEY_KP_End => Some(NavKey::End),
+            keysyms::XKB_KEY_Page_Up | keysyms::XKB_KEY_KP_Page_Up => Some(NavKey::PageUp),
+            keysyms::XKB_KEY_Page_Down | keysyms::XKB_KEY_KP_Page_Down => Some(NavKey::PageDown),
+            keysyms::XKB_KEY_Insert | keysyms::XKB_KEY_KP_Insert => Some(NavKey::Insert),
+            keysyms::XKB_KEY_Delete | keysyms::XKB_KEY_KP_Delete => Some(NavKey::Delete),
+            keysyms::XKB_KEY_BackSpace => Some(NavKey::Backspace),
+            _ => None,
+        }
+    }
+}
+
+/// One of the keys ending a line
+///
+/// Terminal emulators and spreadsheets may treat them differently, other
+/// applications usually treat them all as Return, see `KeyboardOptions::normalize_enter`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum EnterKey {
+    /// The main Return key
+    Return,
+    /// The Enter key of the numeric keypad, `KP_Enter`
+    KeypadEnter,
+    /// `ISO_Enter`, produced by some layouts
+    IsoEnter,
+}
+
+impl EnterKey {
+    /// Get the enter key of a keysym, if any
+    pub fn from_keysym(keysym: u32) -> Option<EnterKey> {
+        match keysym {
+            keysyms::XKB_KEY_Return => Some(EnterKey::Return),
+            keysyms::XKB_KEY_KP_Enter => Some(EnterKey::KeypadEnter),
+            keysyms::XKB_KEY_ISO_Enter => Some(EnterKey::IsoEnter),
+            _ => None,
+        }
+    }
+
+    /// The keysym of this enter key
+    pub fn keysym(&self) -> u32 {
+        match *self {
+            EnterKey::Return => keysyms::XKB_KEY_Return,
+            EnterKey::KeypadEnter => keysyms::XKB_KEY_KP_Enter,
+            EnterKey::IsoEnter => keysyms::XKB_KEY_ISO_Enter,
+        }
+    }
+}
+
+/// Whether a keysym is Return, `KP_Enter` or `ISO_Enter`
+pub fn is_enter(keysym: u32) -> bool {
+    EnterKey::from_keysym(keysym).is_some()
+}
+
+/// Replace `KP_Enter` and `ISO_Enter` by Return, leaving other keysyms unchanged
+pub fn normalize_enter(keysym: u32) -> u32 {
+    if is_enter(keysym) {
+        keysyms::XKB_KEY_Return
+    } else {
+        keysym
+    }
+}
+
+/// A key of Japanese or Korean input methods
+///
+/// Some keysyms are shared by both, like `Kanji_Bangou` and `Hangul_Codeinput`,
+/// their variant has a neutral name.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum InputMethodKey {
+    /// Kanji conversion
+    Kanji,
+    /// Cancel the conversion
+    Muhenkan,
+    /// Start or stop the conversion
+    Henkan,
+    /// Switch to Romaji
+    Romaji,
+    /// Switch to Hiragana
+    Hiragana,
+    /// Switch to Katakana
+    Katakana,
+    /// Toggle between Hiragana and Katakana
+    HiraganaKatakana,
+    /// Switch to full width characters
+    Zenkaku,
+    /// Switch to half width characters
+    Hankaku,
+    /// Toggle between full and half width characters
+    ZenkakuHankaku,
+    /// Add to the dictionary
+    Touroku,
+    /// Delete from the dictionary
+    Massyo,
+    /// Kana lock
+    KanaLock,
+    /// Kana shift
+    KanaShift,
+    /// Alphanumeric shift
+    EisuShift,
+    /// Alphanumeric toggle
+    EisuToggle,
+    /// Toggle Hangul input
+    Hangul,
+    /// Start Hangul input
+    HangulStart,
+    /// End Hangul input
+    HangulEnd,
+    /// Start the Hangul to Hanja conversion
+    HangulHanja,
+    /// Hangul Jamo mode
+    HangulJamo,
+    /// Hangul Romaja mode
+    HangulRomaja,
+    /// Code input mode, `Kanji_Bangou` or `Hangul_Codeinput`
+    CodeInput,
+    /// Hangul Jeonja mode
+    HangulJeonja,
+    /// Hangul Banja mode
+    HangulBanja,
+    /// Hangul pre Hanja conversion
+    HangulPreHanja,
+    /// Hangul post Hanja conversion
+    HangulPostHanja,
+    /// Select the single candidate
+    SingleCandidate,
+    /// Show all the candidates, also `Zen_Koho`
+    MultipleCandidate,
+    /// Select the previous candidate, also `Mae_Koho`
+    PreviousCandidate,
+    /// Hangul special symbols
+    HangulSpecial,
+}
+
+impl InputMethodKey {
+    /// Get the input method key of a keysym, if any
+    pub fn from_keysym(keysym: u32) -> Option<InputMethodKey> {
+        match keysym {
+            keysyms::XKB_KEY_Kanji => Some(InputMethodKey::Kanji),
+            keysyms::XKB_KEY_Muhenkan => Some(InputMethodKey::Muhenkan),
+            keysyms::XKB_KEY_Henkan_Mode => Some(InputMethodKey::Henkan),
+            keysyms::XKB_KEY_Romaji => Some(InputMethodKey::Romaji),
+            keysyms::XKB_KEY_Hiragana => Some(InputMethodKey::Hiragana),
+            keysyms::XKB_KEY_Katakana => Some(InputMethodKey::Katakana),
+            keysyms::XKB_KEY_Hiragana_Katakana => Some(InputMethodKey::HiraganaKatakana),
+            keysyms::XKB_KEY_Zenkaku => Some(InputMethodKey::Zenkaku),
+            keysyms::XKB_KEY_Hankaku => Some(InputMethodKey::Hankaku),
+            keysyms::XKB_KEY_Zenkaku_Hankaku => Some(InputMethodKey::ZenkakuHankaku),
+            keysyms::XKB_KEY_Touroku => Some(InputMethodKey::Touroku),
+            keysyms::XKB_KEY_Massyo => Some(InputMethodKey::Massyo),
+            keysyms::XKB_KEY_Kana_Lock => Some(InputMethodKey::KanaLock),
+            keysyms::XKB_KEY_Kana_Shift => Some(InputMethodKey::KanaShift),
+            keysyms::XKB_KEY_Eisu_Shift => Some(InputMethodKey::EisuShift),
+            keysyms::XKB_KEY_Eisu_toggle => Some(InputMethodKey::EisuToggle),
+            keysyms::XKB_KEY_Hangul => Some(InputMethodKey::Hangul),
+            keysyms::XKB_KEY_Hangul_Start => Some(InputMethodKey::HangulStart),
+            keysyms::XKB_KEY_Hangul_End => Some(InputMethodKey::HangulEnd),
+            keysyms::XKB_KEY_Hangul_Hanja => Some(InputMethodKey::HangulHanja),
+            keysyms::XKB_KEY_Hangul_Jamo => Some(InputMethodKey::HangulJamo),
+            keysyms::XKB_KEY_Hangul_Romaja => Some(InputMethodKey::HangulRomaja),
+            keysyms::XKB_KEY_Codeinput => Some(InputMethodKey::CodeInput),
+            keysyms::XKB_KEY_Hangul_Jeonja => Some(InputMethodKey::HangulJeonja),
+            keysyms::XKB_KEY_Hangul_Banja => Some(InputMethodKey::HangulBanja),
+            keysyms::XKB_KEY_Hangul_PreHanja => Some(InputMethodKey::HangulPreHanja),
+            keysyms::XKB_KEY_Hangul_PostHanja => Some(InputMethodKey::HangulPostHanja),
+            keysyms::XKB_KEY_SingleCandidate => Some(InputMethodKey::SingleCandidate),
+            keysyms::XKB_KEY_MultipleCandidate => Some(InputMethodKey::MultipleCandidate),
+            keysyms::XKB_KEY_PreviousCandidate => Some(InputMethodKey::PreviousCandidate),
+            keysyms::XKB_KEY_Hangul_Special => Some(InputMethodKey::HangulSpecial),
+            _ => None,
+        }
+    }
+}
+
+/// The input modes of Korean and Japanese keyboards
+///
+/// They are toggled by dedicated keys, like Caps Lock, but the keymap does not track
+/// them: the keyboard does, from the key presses it receives while it has focus. Input
+/// methods may change modes on their own, see `KeyboardHandle::set_input_mode()`.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct InputModeState {
+    /// The Hangul mode, toggled by the `Hangul` key
+    pub hangul: bool,
+    /// The Kana lock, toggled by the `Kana_Lock` key
+    pub kana: bool,
+    /// The Eisu (alphanumeric) mode, toggled by the `Eisu_toggle` key
+    pub eisu: bool,
+}
+
+impl InputModeState {
+    /// Apply a key press, returns whether a mode changed
+    pub(crate) fn feed(&mut self, keysym: u32) -> bool {
+        let mode = match InputMethodKey::from_keysym(keysym) {
+            Some(InputMethodKey::Hangul) => &mut self.hangul,
+            Some(InputMethodKey::KanaLock) => &mut self.kana,
+            Some(InputMethodKey::EisuToggle) => &mut self.eisu,
+            _ => return false,
+        };
+        *mode = !*mode;
+        true
+    }
+}
+
+/// The dot of a braille dot keysym, like `braille_dot_1`, from 1 to 10
+///
+/// Braille keyboards send these for each dot key, the dots pressed together
+/// make a braille pattern.
+pub fn braille_dot(keysym: u32) -> Option<u8> {
+    if keysym >= keysyms::XKB_KEY_braille_dot_1 && keysym <= keysyms::XKB_KEY_braille_dot_10 {
+        Some((keysym - keysyms::XKB_KEY_braille_dot_1 + 1) as u8)
+    } else {
+        None
+    }
+}
+
+/// The dots of a braille pattern keysym, like `braille_dots_135`
+///
+/// Bit `n` is set if dot `n + 1` is raised, like in the Unicode braille patterns.
+/// Returns `Some(0)` for `braille_blank`.
+pub fn braille_pattern(keysym: u32) -> Option<u8> {
+    if keysym >= keysyms::XKB_KEY_braille_blank && keysym <= keysyms::XKB_KEY_braille_blank + 0xff {
+        Some((keysym - keysyms::XKB_KEY_braille_blank) as u8)
+    } else {
+        None
+    }
+}
+
+/// The keysym of a braille pattern, see `braille_pattern()`
+pub fn braille_pattern_keysym(dots: u8) -> u32 {
+    keysyms::XKB_KEY_braille_blank + u32::from(dots)
+}
@@ -0,0 +1,496 @@
+use ffi::{self, keysyms};
+#[cfg(feature = "compose")]
+use ffi::{XKBCOMMON_COMPOSE_HANDLE as COMPOSE, XKBCOMMON_COMPOSE_ITER_OPTION};
+#[cfg(feature = "compose")]
+use ffi::XKBCOMMON_HANDLE as XKBH;
+use keymap;
+#[cfg(feature = "compose")]
+use keymap::keysym_name;
+use std::env;
+use std::char;
+use std::ffi::{CStr, CString};
+#[cfg(feature = "compose")]
+use std::os::raw::c_char;
+use std::os::unix::ffi::OsStringExt;
+#[cfg(feature = "compose")]
+use std::path::PathBuf;
+#[cfg(feature = "compose")]
+use std::ptr;
+#[cfg(feature = "compose")]
+use std::slice;
+
+/// A sequence of the compose table
+///
+/// Additional sequences can be provided in `KeyboardOptions::compose_sequences`.
+#[derive(Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct ComposeSequence {
+    /// The keysyms to type, in order
+    pub keysyms: Vec<u32>,
+    /// The keysym produced by the sequence, if any
+    pub keysym: Option<u32>,
+    /// The text produced by the sequence, if any
+    pub utf8: Option<String>,
+}
+
+#[cfg(feature = "compose")]
+/// List all the sequences of a compose table
+///
+/// Returns `None` if the loaded libxkbcommon is too old to support it (before 1.6).
+pub unsafe fn table_sequences(table: *mut ffi::xkb_compose_table) -> Option<Vec<ComposeSequence>> {
+    let lib = match *XKBCOMMON_COMPOSE_ITER_OPTION {
+        Some(ref lib) => lib,
+        None => return None,
+    };
+    let iter = (lib.xkb_compose_table_iterator_new)(table);
+    if iter.is_null() {
+        return None;
+    }
+    let mut sequences = Vec::new();
+    loop {
+        let entry = (lib.xkb_compose_table_iterator_next)(iter);
+        if entry.is_null() {
+            break;
+        }
+        let mut len = 0;
+        let syms = (lib.xkb_compose_table_entry_sequence)(entry, &mut len);
+        let keysyms = if syms.is_null() {
+            Vec::new()
+        } else {
+            slice::from_raw_parts(syms, len).to_vec()
+        };
+        let keysym = match (lib.xkb_compose_table_entry_keysym)(entry) {
+            // XKB_KEY_NoSymbol
+            0 => None,
+            sym => Some(sym),
+        };
+        let utf8 = (lib.xkb_compose_table_entry_utf8)(entry);
+        let utf8 = if utf8.is_null() || *utf8 == 0 {
+            None
+        } else {
+            Some(CStr::from_ptr(utf8).to_string_lossy().into_owned())
+        };
+        sequences.push(ComposeSequence {
+            keysyms: keysyms,
+            keysym: keysym,
+            utf8: utf8,
+        });
+    }
+    (lib.xkb_compose_table_iterator_free)(iter);
+    Some(sequences)
+}
+
+/// The locale of the compose table, from the environment
+pub fn locale() -> CString {
+    let locale = env::var_os("LC_ALL")
+        .or_else(|| env::var_os("LC_CTYPE"))
+        .or_else(|| env::var_os("LANG"))
+        .unwrap_or_else(|| "C".into());
+    // environment variables cannot contain NUL bytes, but fall back to "C" rather than panic
+    CString::new(locale.into_vec()).unwrap_or_else(|_| CString::new("C").unwrap_or_default())
+}
+
+#[cfg(feature = "compose")]
+/// The compose file libxkbcommon would load for the user, if any
+///
+/// This mirrors the lookup of `xkb_compose_table_new_from_locale()`.
+fn user_compose_file() -> Option<PathBuf> {
+    if let Some(path) = env::var_os("XCOMPOSEFILE") {
+        return Some(PathBuf::from(path));
+    }
+    let xdg_config = env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")));
+    if let Some(path) = xdg_config.map(|dir| dir.join("XCompose")) {
+        if path.exists() {
+            return Some(path);
+        }
+    }
+    if let Some(path) = env::var_os("HOME").map(|home| PathBuf::from(home).join(".XCompose")) {
+        if path.exists() {
+            return Some(path);
+        }
+    }
+    None
+}
+
+#[cfg(feature = "compose")]
+fn quote(text: &str) -> String {
+    let mut quoted = String::with_capacity(text.len() + 2);
+    quoted.push('"');
+    for c in text.chars() {
+        if c == '"' || c == '\\' {
+            quoted.push('\\');
+        }
+        quoted.push(c);
+    }
+    quoted.push('"');
+    quoted
+}
+
+#[cfg(feature = "compose")]
+/// Generate a compose file with extra sequences on top of the default ones
+///
+/// The default ones are the user's, if `user_file` is set and the user has a compose
+/// file, else the ones of the locale. The extra sequences override the default ones
+/// they conflict with. Sequences containing keysyms without a name, or producing
+/// nothing, are skipped.
+pub fn compose_file(extra: &[ComposeSequence], user_file: bool) -> String {
+    let user_file = if user_file { user_compose_file() } else { None };
+    let include = match user_file {
+        Some(path) => quote(&path.to_string_lossy()),
+        None => "\"%L\"".to_owned(),
+    };
+    let mut file = format!("include {}\n", include);
+    'sequences: for sequence in extra {
+        if sequence.keysyms.is_empty() || (sequence.keysym.is_none() && sequence.utf8.is_none()) {
+            continue;
+        }
+        let mut line = String::new();
+        for &sym in &sequence.keysyms {
+            match keysym_name(sym) {
+                Some(name) => line.push_str(&format!("<{}> ", name)),
+                None => continue 'sequences,
+            }
+        }
+        line.push(':');
+        if let Some(ref text) = sequence.utf8 {
+            line.push(' ');
+            line.push_str(&quote(text));
+        }
+        if let Some(sym) = sequence.keysym {
+            match keysym_name(sym) {
+                Some(name) => {
+                    line.push(' ');
+                    line.push_str(&name);
+                }
+                None if sequence.utf8.is_none() => continue 'sequences,
+                None => {}
+            }
+        }
+        file.push_str(&line);
+        file.push('\n');
+    }
+    file
+}
+
+// the spacing forms of the accents of the dead keys
+const DEAD_KEY_ACCENTS: &'static [(u32, char)] = &[
+    (keysyms::XKB_KEY_dead_grave, '`'),
+    (keysyms::XKB_KEY_dead_acute, '\u{b4}'),
+    (keysyms::XKB_KEY_dead_circumflex, '^'),
+    (keysyms::XKB_KEY_dead_tilde, '~'),
+    (keysyms::XKB_KEY_dead_macron, '\u{af}'),
+    (keysyms::XKB_KEY_dead_breve, '\u{2d8}'),
+    (keysyms::XKB_KEY_dead_abovedot, '\u{2d9}'),
+    (keysyms::XKB_KEY_dead_diaeresis, '\u{a8}'),
+    (keysyms::XKB_KEY_dead_abovering, '\u{2da}'),
+    (keysyms::XKB_KEY_dead_doubleacute, '\u{2dd}'),
+    (keysyms::XKB_KEY_dead_caron, '\u{2c7}'),
+    (keysyms::XKB_KEY_dead_cedilla, '\u{b8}'),
+    (keysyms::XKB_KEY_dead_ogonek, '\u{2db}'),
+    (keysyms::XKB_KEY_dead_iota, '\u{37a}'),
+    (keysyms::XKB_KEY_dead_voiced_sound, '\u{309b}'),
+    (keysyms::XKB_KEY_dead_semivoiced_sound, '\u{309c}'),
+    (keysyms::XKB_KEY_dead_stroke, '/'),
+    (keysyms::XKB_KEY_dead_currency, '\u{a4}'),
+    (keysyms::XKB_KEY_dead_lowline, '_'),
+    (keysyms::XKB_KEY_dead_greek, '\u{3bc}'),
+];
+
+/// The text displaying a pending compose sequence, like `"´"` for a dead acute
+///
+/// Dead keys are shown as their accent and the Compose key as a middle dot. Other
+/// keysyms are shown as the character they produce, if any.
+pub fn preedit(keysyms: &[u32]) -> String {
+    let mut text = String::new();
+    for &keysym in keysyms {
+        if keysym == keysyms::XKB_KEY_Multi_key {
+            text.push('\u{b7}');
+        } else if let Some(&(_, accent)) = DEAD_KEY_ACCENTS.iter().find(|&&(sym, _)| sym == keysym) {
+            text.push(accent);
+        } else if let Some(c) = keymap::keysym_char(keysym) {
+            text.push(c);
+        }
+    }
+    text
+}
+
+/// The status of a `ComposeMachine`
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum ComposeStatus {
+    /// No sequence is pending
+    Nothing,
+    /// A sequence is pending, see `ComposeMachine::pending()`
+    Composing,
+    /// A sequence was completed by the last keysym, see `ComposeMachine::utf8()`
+    Composed,
+    /// The last keysym did not continue the pending sequence, which was dropped
+    Cancelled,
+}
+
+#[cfg(feature = "serde")]
+serde_unit_enum!(ComposeStatus { Nothing, Composing, Composed, Cancelled });
+
+#[cfg(feature = "compose")]
+/// A compose engine, usable without a keyboard
+///
+/// The keyboards of this crate handle compose sequences themselves. This type runs
+/// the same engine on keysyms from any source, for input methods or tests.
+pub struct ComposeMachine {
+    xkb_context: *mut ffi::xkb_context,
+    xkb_compose_table: *mut ffi::xkb_compose_table,
+    xkb_compose_state: *mut ffi::xkb_compose_state,
+    keysyms: Vec<u32>,
+}
+
+#[cfg(feature = "compose")]
+unsafe impl Send for ComposeMachine {}
+
+#[cfg(feature = "compose")]
+impl ComposeMachine {
+    /// Load the compose table of the current locale
+    ///
+    /// The locale is taken from `LC_ALL`, `LC_CTYPE` or `LANG`. Returns `None` if
+    /// libxkbcommon is not available or has no compose table for the locale.
+    pub fn new() -> Option<ComposeMachine> {
+        ComposeMachine::with_sequences(&[])
+    }
+
+    /// Load the compose table of a locale, like `"en_US.UTF-8"`
+    ///
+    /// Unlike `new()`, this does not depend on the environment: the locale variables
+    /// and the user's compose file, like `~/.XCompose`, are ignored.
+    pub fn from_locale(locale: &str) -> Option<ComposeMachine> {
+        let locale = match CString::new(locale) {
+            Ok(locale) => locale,
+            Err(_) => return None,
+        };
+        ComposeMachine::load(&locale, &[], false)
+    }
+
+    /// Load the compose table of the current locale, with extra sequences on top
+    ///
+    /// The extra sequences override the ones of the table they conflict with.
+    pub fn with_sequences(extra: &[ComposeSequence]) -> Option<ComposeMachine> {
+        ComposeMachine::load(&locale(), extra, true)
+    }
+
+    fn load(locale: &CStr, extra: &[ComposeSequence], user_file: bool) -> Option<ComposeMachine> {
+        let xkbh = match ffi::XKBCOMMON_OPTION.as_ref() {
+            Some(xkbh) => xkbh,
+            None => return None,
+        };
+        unsafe {
+            let xkb_context =
+                xkb_acquire!(Context, (xkbh.xkb_context_new)(ffi::xkb_context_flags::XKB_CONTEXT_NO_FLAGS));
+            if xkb_context.is_null() {
+                return None;
+            }
+            let machine = ComposeMachine::with_context(xkb_context, locale, extra, user_file);
+            xkb_release!(Context, xkbh.xkb_context_unref, xkb_context);
+            machine
+        }
+    }
+
+    /// Load a compose table with an existing context
+    ///
+    /// Without `user_file`, the table is always built from a buffer including the file
+    /// of the locale, as libxkbcommon would otherwise look for the user's compose file.
+    pub(crate) unsafe fn with_context(xkb_context: *mut ffi::xkb_context, locale: &CStr,
+                                      extra: &[ComposeSequence], user_file: bool)
+                                      -> Option<ComposeMachine> {
+        if ffi::XKBCOMMON_COMPOSE_OPTION.is_none() {
+            return None;
+        }
+        let mut compose_table = ptr::null_mut();
+        if !extra.is_empty() || !user_file {
+            let file = compose_file(extra, user_file);
+            compose_table = xkb_acquire!(ComposeTable, (COMPOSE.xkb_compose_table_new_from_buffer)(
+                xkb_context,
+                file.as_ptr() as *const c_char,
+                file.len(),
+                locale.as_ptr(),
+                ffi::xkb_compose_format::XKB_COMPOSE_FORMAT_TEXT_V1,
+                ffi::xkb_compose_compile_flags::XKB_COMPOSE_COMPILE_NO_FLAGS,
+            ));
+        }
+        if compose_table.is_null() && user_file {
+            compose_table = xkb_acquire!(ComposeTable, (COMPOSE.xkb_compose_table_new_from_locale)(
+                xkb_context,
+                locale.as_ptr(),
+                ffi::xkb_compose_compile_flags::XKB_COMPOSE_COMPILE_NO_FLAGS,
+            ));
+        }
+        if compose_table.is_null() {
+            return None;
+        }
+
+        let compose_state = xkb_acquire!(ComposeState, (COMPOSE.xkb_compose_state_new)(
+            compose_table,
+            ffi::xkb_compose_state_flags::XKB_COMPOSE_STATE_NO_FLAGS,
+        ));
+        if compose_state.is_null() {
+            xkb_release!(ComposeTable, COMPOSE.xkb_compose_table_unref, compose_table);
+            return None;
+        }
+
+        Some(ComposeMachine {
+            xkb_context: xkb_acquire!(Context, (XKBH.xkb_context_ref)(xkb_context)),
+            xkb_compose_table: compose_table,
+            xkb_compose_state: compose_state,
+            keysyms: Vec::new(),
+        })
+    }
+
+    /// A machine with the same table and no pending sequence
+    pub(crate) fn fresh(&self) -> Option<ComposeMachine> {
+        unsafe {
+            let compose_state = xkb_acquire!(ComposeState, (COMPOSE.xkb_compose_state_new)(
+                self.xkb_compose_table,
+                ffi::xkb_compose_state_flags::XKB_COMPOSE_STATE_NO_FLAGS,
+            ));
+            if compose_state.is_null() {
+                return None;
+            }
+            Some(ComposeMachine {
+                xkb_context: xkb_acquire!(Context, (XKBH.xkb_context_ref)(self.xkb_context)),
+                xkb_compose_table: xkb_acquire!(
+                    ComposeTable,
+                    (COMPOSE.xkb_compose_table_ref)(self.xkb_compose_table)
+                ),
+                xkb_compose_state: compose_state,
+                keysyms: Vec::new(),
+            })
+        }
+    }
+
+    /// Feed a keysym to the machine
+    ///
+    /// Returns `false` if the keysym is ignored, like the ones of modifier keys, in
+    /// which case the status is unchanged.
+    pub fn feed(&mut self, keysym: u32) -> bool {
+        let result = unsafe { (COMPOSE.xkb_compose_state_feed)(self.xkb_compose_state, keysym) };
+        if result == ffi::xkb_compose_feed_result::XKB_COMPOSE_FEED_IGNORED {
+            return false;
+        }
+        if self.status() == ComposeStatus::Composing {
+            self.keysyms.push(keysym);
+        } else {
+            self.keysyms.clear();
+        }
+        true
+    }
+
+    /// The current status
+    pub fn status(&self) -> ComposeStatus {
+        match unsafe { (COMPOSE.xkb_compose_state_get_status)(self.xkb_compose_state) } {
+            ffi::xkb_compose_status::XKB_COMPOSE_NOTHING => ComposeStatus::Nothing,
+            ffi::xkb_compose_status::XKB_COMPOSE_COMPOSING => ComposeStatus::Composing,
+            ffi::xkb_compose_status::XKB_COMPOSE_COMPOSED => ComposeStatus::Composed,
+            ffi::xkb_compose_status::XKB_COMPOSE_CANCELLED => ComposeStatus::Cancelled,
+        }
+    }
+
+    /// The text of the completed sequence, if the status is `Composed`
+    pub fn utf8(&self) -> Option<String> {
+        let size =
+            unsafe { (COMPOSE.xkb_compose_state_get_utf8)(self.xkb_compose_state, ptr::null_mut(), 0) } + 1;
+        if size <= 1 {
+            return None;
+        };
+        let mut buffer = vec![0u8; size as usize];
+        unsafe {
+            (COMPOSE.xkb_compose_state_get_utf8)(
+                self.xkb_compose_state,
+                buffer.as_mut_ptr() as *mut _,
+                size as usize,
+            );
+        };
+        // remove the final `\0`
+        buffer.pop();
+        // libxkbcommon will always provide valid UTF8
+        Some(unsafe { String::from_utf8_unchecked(buffer) })
+    }
+
+    /// The keysym of the completed sequence, if the status is `Composed` and it has one
+    pub fn keysym(&self) -> Option<u32> {
+        match unsafe { (COMPOSE.xkb_compose_state_get_one_sym)(self.xkb_compose_state) } {
+            0 => None,
+            keysym => Some(keysym),
+        }
+    }
+
+    /// The keysyms of the pending sequence
+    pub fn pending(&self) -> &[u32] {
+        &self.keysyms
+    }
+
+    /// The display text of the pending sequence, like `"´"` after a dead acute key
+    pub fn preedit(&self) -> String {
+        preedit(&self.keysyms)
+    }
+
+    /// Drop the pending sequence
+    pub fn reset(&mut self) {
+        unsafe { (COMPOSE.xkb_compose_state_reset)(self.xkb_compose_state) };
+        self.keysyms.clear();
+    }
+
+    /// List the sequences of the compose table
+    ///
+    /// Returns `None` if the libxkbcommon in use is older than 1.6.
+    pub fn sequences(&self) -> Option<Vec<ComposeSequence>> {
+        unsafe { table_sequences(self.xkb_compose_table) }
+    }
+}
+
+#[cfg(feature = "compose")]
+impl Drop for ComposeMachine {
+    fn drop(&mut self) {
+        unsafe {
+            xkb_release!(ComposeState, COMPOSE.xkb_compose_state_unref, self.xkb_compose_state);
+            xkb_release!(ComposeTable, COMPOSE.xkb_compose_table_unref, self.xkb_compose_table);
+            xkb_release!(Context, XKBH.xkb_context_unref, self.xkb_context);
+        }
+    }
+}
+
+// Without the `compose` feature, no machine can exist and the keyboards never compose
+#[cfg(not(feature = "compose"))]
+pub enum ComposeMachine {}
+
+#[cfg(not(feature = "compose"))]
+impl ComposeMachine {
+    pub(crate) unsafe fn with_context(_: *mut ffi::xkb_context, _: &CStr, _: &[ComposeSequence], _: bool)
+                                      -> Option<ComposeMachine> {
+        None
+    }
+
+    pub(crate) fn fresh(&self) -> Option<ComposeMachine> {
+        match *self {}
+    }
+
+    pub(crate) fn feed(&mut self, _: u32) -> bool {
+        match *self {}
+    }
+
+    pub(crate) fn status(&self) -> ComposeStatus {
+        match *self {}
+    }
+
+    pub(crate) fn utf8(&self) -> Option<String> {
+        match *self {}
+    }
+
+    pub(crate) fn pending(&self) -> &[u32] {
+        match *self {}
+    }
+
+    pub(crate) fn reset(&mut self) {
+        match *self {}
+    }
+
+    pub(crate) fn sequences(&self) -> Option<Vec<ComposeSequence>> {
+        match *self {}
+    }
+}
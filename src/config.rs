@@ -0,0 +1,187 @@
+use mapped_keyboard::{KeyboardOptions, RMLVO};
+use remap::ModifierRemap;
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::path::Path;
+
+/// A keyboard setup chosen by the user, that can be saved and restored
+///
+/// The configuration is stored as a simple text file of `key = value` lines. Use
+/// `options()` and the `rmlvo` field to register a keyboard with it:
+///
+/// ```ignore
+/// let config = KeyboardConfig::load(path).unwrap_or_default();
+/// register_kbd_with_options(evqh, kbd, implem, idata, config.rmlvo.clone(), config.options())
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct KeyboardConfig {
+    /// The keymap to use instead of the one of the compositor
+    pub rmlvo: Option<RMLVO>,
+    /// Whether compose sequences are processed
+    pub compose: bool,
+    /// Key repeat rate and delay, overriding the ones of the compositor
+    pub repeat_info: Option<(i32, i32)>,
+    /// Modifier remappings applied on top of the keymap
+    pub modifier_remaps: Vec<ModifierRemap>,
+}
+
+impl Default for KeyboardConfig {
+    fn default() -> KeyboardConfig {
+        KeyboardConfig {
+            rmlvo: None,
+            compose: true,
+            repeat_info: None,
+            modifier_remaps: Vec::new(),
+        }
+    }
+}
+
+fn invalid(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+fn remap_name(remap: ModifierRemap) -> &'static str {
+    match remap {
+        ModifierRemap::CapsLockToControl => "CapsLockToControl",
+        ModifierRemap::CapsLockToEscape => "CapsLockToEscape",
+        ModifierRemap::SwapAltLogo => "SwapAltLogo",
+    }
+}
+
+fn parse_remap(name: &str) -> Option<ModifierRemap> {
+    match name {
+        "CapsLockToControl" => Some(ModifierRemap::CapsLockToControl),
+        "CapsLockToEscape" => Some(ModifierRemap::CapsLockToEscape),
+        "SwapAltLogo" => Some(ModifierRemap::SwapAltLogo),
+        _ => None,
+    }
+}
+
+impl KeyboardConfig {
+    /// Keyboard options reflecting this configuration, with defaults for the rest
+    pub fn options(&self) -> KeyboardOptions {
+        let mut options = KeyboardOptions::default();
+        self.apply(&mut options);
+        options
+    }
+
+    /// Set the fields of some keyboard options reflecting this configuration
+    pub fn apply(&self, options: &mut KeyboardOptions) {
+        options.compose = self.compose;
+        options.repeat_info = self.repeat_info;
+        options.modifier_remaps = self.modifier_remaps.clone();
+    }
+
+    /// Save the configuration to a file
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let mut file = File::create(path)?;
+        file.write_all(self.to_text().as_bytes())
+    }
+
+    /// Load a configuration previously saved with `save()`
+    ///
+    /// Unknown keys are ignored. Missing keys keep their default value.
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<KeyboardConfig> {
+        let mut text = String::new();
+        File::open(path)?.read_to_string(&mut text)?;
+        KeyboardConfig::from_text(&text)
+    }
+
+    fn to_text(&self) -> String {
+        let mut text = String::from("# wayland-kbd keyboard configuration\n");
+        if let Some(ref rmlvo) = self.rmlvo {
+            let fields = [
+                ("rules", &rmlvo.rules),
+                ("model", &rmlvo.model),
+                ("layout", &rmlvo.layout),
+                ("variant", &rmlvo.variant),
+                ("options", &rmlvo.options),
+            ];
+            for &(key, value) in &fields {
+                if let Some(ref value) = *value {
+                    text.push_str(&format!("{} = {}\n", key, value));
+                }
+            }
+        }
+        text.push_str(&format!("compose = {}\n", self.compose));
+        if let Some((rate, delay)) = self.repeat_info {
+            text.push_str(&format!("repeat_rate = {}\n", rate));
+            text.push_str(&format!("repeat_delay = {}\n", delay));
+        }
+        if !self.modifier_remaps.is_empty() {
+            let names: Vec<&str> = self.modifier_remaps.iter().map(|&r| remap_name(r)).collect();
+            text.push_str(&format!("modifier_remaps = {}\n", names.join(",")));
+        }
+        text
+    }
+
+    fn from_text(text: &str) -> io::Result<KeyboardConfig> {
+        let mut config = KeyboardConfig::default();
+        let mut rmlvo = RMLVO {
+            rules: None,
+            model: None,
+            layout: None,
+            variant: None,
+            options: None,
+        };
+        let mut rate = None;
+        let mut delay = None;
+        for line in text.lines() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let mut parts = line.splitn(2, '=');
+            let key = parts.next().unwrap_or("").trim();
+            let value = match parts.next() {
+                Some(value) => value.trim(),
+                None => return Err(invalid(format!("Invalid line: {}", line))),
+            };
+            match key {
+                "rules" => rmlvo.rules = Some(value.to_owned()),
+                "model" => rmlvo.model = Some(value.to_owned()),
+                "layout" => rmlvo.layout = Some(value.to_owned()),
+                "variant" => rmlvo.variant = Some(value.to_owned()),
+                "options" => rmlvo.options = Some(value.to_owned()),
+                "compose" => {
+                    config.compose = value
+                        .parse()
+                        .map_err(|_| invalid(format!("Invalid compose value: {}", value)))?
+                }
+                "repeat_rate" => {
+                    rate = Some(value
+                        .parse()
+                        .map_err(|_| invalid(format!("Invalid repeat rate: {}", value)))?)
+                }
+                "repeat_delay" => {
+                    delay = Some(value
+                        .parse()
+                        .map_err(|_| invalid(format!("Invalid repeat delay: {}", value)))?)
+                }
+                "modifier_remaps" => {
+                    let mut remaps = Vec::new();
+                    for name in value.split(',').map(|name| name.trim()).filter(|name| !name.is_empty()) {
+                        match parse_remap(name) {
+                            Some(remap) => remaps.push(remap),
+                            None => return Err(invalid(format!("Unknown remap: {}", name))),
+                        }
+                    }
+                    config.modifier_remaps = remaps;
+                }
+                // keys written by newer versions
+                _ => {}
+            }
+        }
+        if rmlvo.rules.is_some() || rmlvo.model.is_some() || rmlvo.layout.is_some() || rmlvo.variant.is_some()
+            || rmlvo.options.is_some()
+        {
+            config.rmlvo = Some(rmlvo);
+        }
+        config.repeat_info = match (rate, delay) {
+            (Some(rate), Some(delay)) => Some((rate, delay)),
+            (None, None) => None,
+            _ => return Err(invalid("Repeat rate and delay must be set together".to_owned())),
+        };
+        Ok(config)
+    }
+}
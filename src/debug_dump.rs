@@ -0,0 +1,87 @@
+use capabilities::XkbCapabilities;
+use mapped_keyboard::{ModifiersState, RMLVO};
+use std::fmt;
+
+/// A report of the state of a keyboard, for bug reports
+///
+/// It is obtained from `KeyboardHandle::debug_dump()`. Its `Display` implementation
+/// produces a human-readable text, while its fields give access to the raw values.
+#[derive(Clone, Debug)]
+pub struct DebugDump {
+    /// The libxkbcommon library in use
+    pub xkbcommon: String,
+    /// The optional features of the libxkbcommon library
+    pub capabilities: XkbCapabilities,
+    /// Whether a keymap is loaded
+    pub keymap_loaded: bool,
+    /// Whether the keymap is locked, ignoring the keymaps sent by the compositor
+    pub keymap_locked: bool,
+    /// The RMLVO the keymap was created from, if it was not provided by the compositor
+    pub rmlvo: Option<RMLVO>,
+    /// The names of the layouts of the keymap
+    pub layouts: Vec<String>,
+    /// The index of the active layout
+    pub active_layout: Option<u32>,
+    /// The effective modifiers
+    pub modifiers: ModifiersState,
+    /// Whether a compose table could be loaded
+    pub compose_available: bool,
+    /// The keysyms of the pending compose sequence
+    pub compose_pending: Vec<u32>,
+    /// The repeat rate and delay sent by the compositor, if any
+    pub repeat_info: Option<(i32, i32)>,
+}
+
+impl fmt::Display for DebugDump {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "wayland-kbd {}", env!("CARGO_PKG_VERSION"))?;
+        writeln!(f, "xkbcommon: {}", self.xkbcommon)?;
+        writeln!(f, "capabilities: {:?}", self.capabilities)?;
+        if !self.keymap_loaded {
+            writeln!(f, "keymap: none")?;
+        } else if self.keymap_locked {
+            writeln!(f, "keymap: locked")?;
+        } else {
+            writeln!(f, "keymap: from compositor")?;
+        }
+        if let Some(ref rmlvo) = self.rmlvo {
+            fn field(value: &Option<String>) -> &str {
+                value.as_ref().map_or("<default>", |s| &s[..])
+            }
+            writeln!(
+                f,
+                "rmlvo: rules={} model={} layout={} variant={} options={}",
+                field(&rmlvo.rules),
+                field(&rmlvo.model),
+                field(&rmlvo.layout),
+                field(&rmlvo.variant),
+                field(&rmlvo.options)
+            )?;
+        }
+        writeln!(f, "layouts:")?;
+        for (idx, name) in self.layouts.iter().enumerate() {
+            let active = if self.active_layout == Some(idx as u32) {
+                " (active)"
+            } else {
+                ""
+            };
+            writeln!(f, "  {}: {}{}", idx, name, active)?;
+        }
+        writeln!(f, "modifiers: {:?}", self.modifiers)?;
+        if !self.compose_available {
+            writeln!(f, "compose: unavailable")?;
+        } else if self.compose_pending.is_empty() {
+            writeln!(f, "compose: idle")?;
+        } else {
+            write!(f, "compose: pending")?;
+            for keysym in &self.compose_pending {
+                write!(f, " {:#x}", keysym)?;
+            }
+            writeln!(f)?;
+        }
+        match self.repeat_info {
+            Some((rate, delay)) => writeln!(f, "repeat: rate={} delay={}ms", rate, delay),
+            None => writeln!(f, "repeat: unknown"),
+        }
+    }
+}
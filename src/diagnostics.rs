@@ -0,0 +1,171 @@
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// An opt-in collector of statistics about the keyboard input
+///
+/// Create one, put a clone of it in `KeyboardOptions::diagnostics` when registering
+/// the keyboard, and query it at any time using `snapshot()`.
+///
+/// This handle is cheap to clone, and all clones share the same statistics.
+#[derive(Clone, Debug)]
+pub struct Diagnostics {
+    inner: Arc<Mutex<DiagnosticsData>>,
+}
+
+#[derive(Debug)]
+struct DiagnosticsData {
+    key_events: u64,
+    recent_events: VecDeque<Instant>,
+    translations: u64,
+    total_translation_latency: Duration,
+    max_translation_latency: Duration,
+    last_translation_latency: Duration,
+    compose_composed: u64,
+    compose_cancelled: u64,
+    stale_repeats: u64,
+    overflowed_repeats: u64,
+}
+
+/// The statistics collected by a `Diagnostics` at some point in time
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct DiagnosticsSnapshot {
+    /// The number of key events received since the collector was created or reset
+    pub key_events: u64,
+    /// The number of key events received during the last second
+    pub events_per_second: u32,
+    /// The time spent translating the last key event into keysym and text
+    pub last_translation_latency: Duration,
+    /// The mean time spent translating a key event into keysym and text
+    pub mean_translation_latency: Duration,
+    /// The longest time spent translating a key event into keysym and text
+    pub max_translation_latency: Duration,
+    /// The number of compose sequences that were completed
+    pub compose_sequences_composed: u64,
+    /// The number of compose sequences that were cancelled
+    pub compose_sequences_cancelled: u64,
+    /// The number of key repeats dropped because they were polled too late
+    ///
+    /// See `RepeatPolicy::coalesce`.
+    pub stale_repeats_dropped: u64,
+    /// The number of key repeats dropped because they exceeded the queue bound
+    ///
+    /// See `KeyboardOptions::max_queued_events`.
+    pub overflowed_repeats_dropped: u64,
+}
+
+impl Diagnostics {
+    /// Create a new collector, with all statistics at zero
+    pub fn new() -> Diagnostics {
+        Diagnostics {
+            inner: Arc::new(Mutex::new(DiagnosticsData {
+                key_events: 0,
+                recent_events: VecDeque::new(),
+                translations: 0,
+                total_translation_latency: Duration::from_secs(0),
+                max_translation_latency: Duration::from_secs(0),
+                last_translation_latency: Duration::from_secs(0),
+                compose_composed: 0,
+                compose_cancelled: 0,
+                stale_repeats: 0,
+                overflowed_repeats: 0,
+            })),
+        }
+    }
+
+    /// Get the current statistics
+    pub fn snapshot(&self) -> DiagnosticsSnapshot {
+        let mut data = match self.inner.lock() {
+            Ok(data) => data,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        data.prune(Instant::now());
+        let mean = if data.translations > 0 {
+            let total_ns = data.total_translation_latency.as_secs() * 1_000_000_000
+                + u64::from(data.total_translation_latency.subsec_nanos());
+            let mean_ns = total_ns / data.translations;
+            Duration::new(mean_ns / 1_000_000_000, (mean_ns % 1_000_000_000) as u32)
+        } else {
+            Duration::from_secs(0)
+        };
+        DiagnosticsSnapshot {
+            key_events: data.key_events,
+            events_per_second: data.recent_events.len() as u32,
+            last_translation_latency: data.last_translation_latency,
+            mean_translation_latency: mean,
+            max_translation_latency: data.max_translation_latency,
+            compose_sequences_composed: data.compose_composed,
+            compose_sequences_cancelled: data.compose_cancelled,
+            stale_repeats_dropped: data.stale_repeats,
+            overflowed_repeats_dropped: data.overflowed_repeats,
+        }
+    }
+
+    /// Reset all statistics to zero
+    pub fn reset(&self) {
+        self.with(|data| {
+            data.key_events = 0;
+            data.recent_events.clear();
+            data.translations = 0;
+            data.total_translation_latency = Duration::from_secs(0);
+            data.max_translation_latency = Duration::from_secs(0);
+            data.last_translation_latency = Duration::from_secs(0);
+            data.compose_composed = 0;
+            data.compose_cancelled = 0;
+            data.stale_repeats = 0;
+            data.overflowed_repeats = 0;
+        });
+    }
+
+    pub(crate) fn record_key(&self, translation_latency: Duration) {
+        self.with(|data| {
+            let now = Instant::now();
+            data.key_events += 1;
+            data.recent_events.push_back(now);
+            data.prune(now);
+            data.translations += 1;
+            data.total_translation_latency += translation_latency;
+            data.last_translation_latency = translation_latency;
+            if translation_latency > data.max_translation_latency {
+                data.max_translation_latency = translation_latency;
+            }
+        });
+    }
+
+    pub(crate) fn record_composed(&self) {
+        self.with(|data| data.compose_composed += 1);
+    }
+
+    pub(crate) fn record_cancelled(&self) {
+        self.with(|data| data.compose_cancelled += 1);
+    }
+
+    pub(crate) fn record_stale_repeats(&self, count: u32) {
+        self.with(|data| data.stale_repeats += u64::from(count));
+    }
+
+    pub(crate) fn record_overflowed_repeats(&self, count: usize) {
+        self.with(|data| data.overflowed_repeats += count as u64);
+    }
+
+    fn with<F: FnOnce(&mut DiagnosticsData)>(&self, f: F) {
+        // statistics are still meaningful if a panic occured while they were updated
+        let mut data = match self.inner.lock() {
+            Ok(data) => data,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        f(&mut data)
+    }
+}
+
+impl DiagnosticsData {
+    fn prune(&mut self, now: Instant) {
+        while let Some(&oldest) = self.recent_events.front() {
+            if now.duration_since(oldest) > Duration::from_secs(1) {
+                self.recent_events.pop_front();
+            } else {
+                break;
+            }
+        }
+    }
+}
@@ -2473,6 +2473,7 @@ pub const XKB_KEY_XF86ModeLock		:u32 = 0x1008FF01;	/* Mode Switch Lock */
 /* Backlight controls. */
 pub const XKB_KEY_XF86MonBrightnessUp   :u32 = 0x1008FF02;  /* Monitor/panel brightness */
 pub const XKB_KEY_XF86MonBrightnessDown :u32 = 0x1008FF03;  /* Monitor/panel brightness */
+pub const XKB_KEY_XF86MonBrightnessCycle	:u32 = 0x1008FF07;   /* Monitor/panel brightness cycle */
 pub const XKB_KEY_XF86KbdLightOnOff     :u32 = 0x1008FF04;  /* Keyboards may be lit     */
 pub const XKB_KEY_XF86KbdBrightnessUp   :u32 = 0x1008FF05;  /* Keyboards may be lit     */
 pub const XKB_KEY_XF86KbdBrightnessDown :u32 = 0x1008FF06;  /* Keyboards may be lit     */
@@ -2644,6 +2645,12 @@ pub const XKB_KEY_XF86TouchpadOn	:u32 = 0x1008FFB0;   /* The touchpad got switch
 pub const XKB_KEY_XF86TouchpadOff	:u32 = 0x1008FFB1;   /* The touchpad got switched off */
 
 pub const XKB_KEY_XF86AudioMicMute	:u32 = 0x1008FFB2;   /* Mute the Mic from the system */
+pub const XKB_KEY_XF86Keyboard		:u32 = 0x1008FFB3;   /* Toggle the keyboard */
+pub const XKB_KEY_XF86WWAN		:u32 = 0x1008FFB4;   /* Toggle WWAN (LTE, UMTS, etc.) radio */
+pub const XKB_KEY_XF86RFKill		:u32 = 0x1008FFB5;   /* Toggle radios on/off */
+pub const XKB_KEY_XF86AudioPreset	:u32 = 0x1008FFB6;   /* Select equalizer preset */
+pub const XKB_KEY_XF86RotationLockToggle	:u32 = 0x1008FFB7;   /* Toggle screen rotation lock */
+pub const XKB_KEY_XF86FullScreen	:u32 = 0x1008FFB8;   /* Toggle fullscreen */
 
 /* Keys for special action keys (hot keys) */
 /* Virtual terminals on some operating systems */
@@ -2666,6 +2673,149 @@ pub const XKB_KEY_XF86Next_VMode	:u32 = 0x1008FE22;   /* next video mode availab
 pub const XKB_KEY_XF86Prev_VMode	:u32 = 0x1008FE23;   /* prev. video mode available */
 pub const XKB_KEY_XF86LogWindowTree	:u32 = 0x1008FE24;   /* print window tree to log   */
 pub const XKB_KEY_XF86LogGrabInfo	:u32 = 0x1008FE25;   /* print all active grabs to log */
+
+/*
+ * Keys of the Linux input event codes, in the 0x10081000 range.
+ * The low bits are the evdev code of the key.
+ */
+
+pub const XKB_KEY_XF86BrightnessAuto	:u32 = 0x100810F4;
+pub const XKB_KEY_XF86DisplayOff	:u32 = 0x100810F5;
+pub const XKB_KEY_XF86Info		:u32 = 0x10081166;
+pub const XKB_KEY_XF86AspectRatio	:u32 = 0x10081177;
+pub const XKB_KEY_XF86DVD		:u32 = 0x10081185;
+pub const XKB_KEY_XF86Audio		:u32 = 0x10081188;
+pub const XKB_KEY_XF86ChannelUp		:u32 = 0x10081192;
+pub const XKB_KEY_XF86ChannelDown	:u32 = 0x10081193;
+pub const XKB_KEY_XF86Break		:u32 = 0x1008119B;
+pub const XKB_KEY_XF86VideoPhone	:u32 = 0x100811A0;
+pub const XKB_KEY_XF86ZoomReset		:u32 = 0x100811A4;
+pub const XKB_KEY_XF86Editor		:u32 = 0x100811A6;
+pub const XKB_KEY_XF86GraphicsEditor	:u32 = 0x100811A8;
+pub const XKB_KEY_XF86Presentation	:u32 = 0x100811A9;
+pub const XKB_KEY_XF86Database		:u32 = 0x100811AA;
+pub const XKB_KEY_XF86Voicemail		:u32 = 0x100811AC;
+pub const XKB_KEY_XF86Addressbook	:u32 = 0x100811AD;
+pub const XKB_KEY_XF86DisplayToggle	:u32 = 0x100811AF;
+pub const XKB_KEY_XF86SpellCheck	:u32 = 0x100811B0;
+pub const XKB_KEY_XF86ContextMenu	:u32 = 0x100811B6;
+pub const XKB_KEY_XF86MediaRepeat	:u32 = 0x100811B7;
+pub const XKB_KEY_XF8610ChannelsUp	:u32 = 0x100811B8;
+pub const XKB_KEY_XF8610ChannelsDown	:u32 = 0x100811B9;
+pub const XKB_KEY_XF86Images		:u32 = 0x100811BA;
+pub const XKB_KEY_XF86NotificationCenter	:u32 = 0x100811BC;
+pub const XKB_KEY_XF86PickupPhone	:u32 = 0x100811BD;
+pub const XKB_KEY_XF86HangupPhone	:u32 = 0x100811BE;
+pub const XKB_KEY_XF86Fn		:u32 = 0x100811D0;
+pub const XKB_KEY_XF86Fn_Esc		:u32 = 0x100811D1;
+pub const XKB_KEY_XF86FnRightShift	:u32 = 0x100811E5;
+pub const XKB_KEY_XF86Numeric0		:u32 = 0x10081200;
+pub const XKB_KEY_XF86Numeric1		:u32 = 0x10081201;
+pub const XKB_KEY_XF86Numeric2		:u32 = 0x10081202;
+pub const XKB_KEY_XF86Numeric3		:u32 = 0x10081203;
+pub const XKB_KEY_XF86Numeric4		:u32 = 0x10081204;
+pub const XKB_KEY_XF86Numeric5		:u32 = 0x10081205;
+pub const XKB_KEY_XF86Numeric6		:u32 = 0x10081206;
+pub const XKB_KEY_XF86Numeric7		:u32 = 0x10081207;
+pub const XKB_KEY_XF86Numeric8		:u32 = 0x10081208;
+pub const XKB_KEY_XF86Numeric9		:u32 = 0x10081209;
+pub const XKB_KEY_XF86NumericStar	:u32 = 0x1008120A;
+pub const XKB_KEY_XF86NumericPound	:u32 = 0x1008120B;
+pub const XKB_KEY_XF86NumericA		:u32 = 0x1008120C;
+pub const XKB_KEY_XF86NumericB		:u32 = 0x1008120D;
+pub const XKB_KEY_XF86NumericC		:u32 = 0x1008120E;
+pub const XKB_KEY_XF86NumericD		:u32 = 0x1008120F;
+pub const XKB_KEY_XF86CameraFocus	:u32 = 0x10081210;
+pub const XKB_KEY_XF86WPSButton		:u32 = 0x10081211;
+pub const XKB_KEY_XF86CameraZoomIn	:u32 = 0x10081215;
+pub const XKB_KEY_XF86CameraZoomOut	:u32 = 0x10081216;
+pub const XKB_KEY_XF86CameraUp		:u32 = 0x10081217;
+pub const XKB_KEY_XF86CameraDown	:u32 = 0x10081218;
+pub const XKB_KEY_XF86CameraLeft	:u32 = 0x10081219;
+pub const XKB_KEY_XF86CameraRight	:u32 = 0x1008121A;
+pub const XKB_KEY_XF86AttendantOn	:u32 = 0x1008121B;
+pub const XKB_KEY_XF86AttendantOff	:u32 = 0x1008121C;
+pub const XKB_KEY_XF86AttendantToggle	:u32 = 0x1008121D;
+pub const XKB_KEY_XF86LightsToggle	:u32 = 0x1008121E;
+pub const XKB_KEY_XF86ALSToggle		:u32 = 0x10081230;
+pub const XKB_KEY_XF86Buttonconfig	:u32 = 0x10081240;
+pub const XKB_KEY_XF86Taskmanager	:u32 = 0x10081241;
+pub const XKB_KEY_XF86Journal		:u32 = 0x10081242;
+pub const XKB_KEY_XF86ControlPanel	:u32 = 0x10081243;
+pub const XKB_KEY_XF86AppSelect		:u32 = 0x10081244;
+pub const XKB_KEY_XF86Screensaver	:u32 = 0x10081245;
+pub const XKB_KEY_XF86VoiceCommand	:u32 = 0x10081246;
+pub const XKB_KEY_XF86Assistant		:u32 = 0x10081247;
+pub const XKB_KEY_XF86BrightnessMin	:u32 = 0x10081250;
+pub const XKB_KEY_XF86BrightnessMax	:u32 = 0x10081251;
+pub const XKB_KEY_XF86KbdInputAssistPrev	:u32 = 0x10081260;
+pub const XKB_KEY_XF86KbdInputAssistNext	:u32 = 0x10081261;
+pub const XKB_KEY_XF86KbdInputAssistPrevgroup	:u32 = 0x10081262;
+pub const XKB_KEY_XF86KbdInputAssistNextgroup	:u32 = 0x10081263;
+pub const XKB_KEY_XF86KbdInputAssistAccept	:u32 = 0x10081264;
+pub const XKB_KEY_XF86KbdInputAssistCancel	:u32 = 0x10081265;
+pub const XKB_KEY_XF86RightUp		:u32 = 0x10081266;
+pub const XKB_KEY_XF86RightDown		:u32 = 0x10081267;
+pub const XKB_KEY_XF86LeftUp		:u32 = 0x10081268;
+pub const XKB_KEY_XF86LeftDown		:u32 = 0x10081269;
+pub const XKB_KEY_XF86RootMenu		:u32 = 0x1008126A;
+pub const XKB_KEY_XF86MediaTopMenu	:u32 = 0x1008126B;
+pub const XKB_KEY_XF86Numeric11		:u32 = 0x1008126C;
+pub const XKB_KEY_XF86Numeric12		:u32 = 0x1008126D;
+pub const XKB_KEY_XF86AudioDesc		:u32 = 0x1008126E;
+pub const XKB_KEY_XF863DMode		:u32 = 0x1008126F;
+pub const XKB_KEY_XF86NextFavorite	:u32 = 0x10081270;
+pub const XKB_KEY_XF86StopRecord	:u32 = 0x10081271;
+pub const XKB_KEY_XF86PauseRecord	:u32 = 0x10081272;
+pub const XKB_KEY_XF86VOD		:u32 = 0x10081273;
+pub const XKB_KEY_XF86Unmute		:u32 = 0x10081274;
+pub const XKB_KEY_XF86FastReverse	:u32 = 0x10081275;
+pub const XKB_KEY_XF86SlowReverse	:u32 = 0x10081276;
+pub const XKB_KEY_XF86Data		:u32 = 0x10081277;
+pub const XKB_KEY_XF86OnScreenKeyboard	:u32 = 0x10081278;
+pub const XKB_KEY_XF86PrivacyScreenToggle	:u32 = 0x10081279;
+pub const XKB_KEY_XF86SelectiveScreenshot	:u32 = 0x1008127A;
+pub const XKB_KEY_XF86Macro1		:u32 = 0x10081290;
+pub const XKB_KEY_XF86Macro2		:u32 = 0x10081291;
+pub const XKB_KEY_XF86Macro3		:u32 = 0x10081292;
+pub const XKB_KEY_XF86Macro4		:u32 = 0x10081293;
+pub const XKB_KEY_XF86Macro5		:u32 = 0x10081294;
+pub const XKB_KEY_XF86Macro6		:u32 = 0x10081295;
+pub const XKB_KEY_XF86Macro7		:u32 = 0x10081296;
+pub const XKB_KEY_XF86Macro8		:u32 = 0x10081297;
+pub const XKB_KEY_XF86Macro9		:u32 = 0x10081298;
+pub const XKB_KEY_XF86Macro10		:u32 = 0x10081299;
+pub const XKB_KEY_XF86Macro11		:u32 = 0x1008129A;
+pub const XKB_KEY_XF86Macro12		:u32 = 0x1008129B;
+pub const XKB_KEY_XF86Macro13		:u32 = 0x1008129C;
+pub const XKB_KEY_XF86Macro14		:u32 = 0x1008129D;
+pub const XKB_KEY_XF86Macro15		:u32 = 0x1008129E;
+pub const XKB_KEY_XF86Macro16		:u32 = 0x1008129F;
+pub const XKB_KEY_XF86Macro17		:u32 = 0x100812A0;
+pub const XKB_KEY_XF86Macro18		:u32 = 0x100812A1;
+pub const XKB_KEY_XF86Macro19		:u32 = 0x100812A2;
+pub const XKB_KEY_XF86Macro20		:u32 = 0x100812A3;
+pub const XKB_KEY_XF86Macro21		:u32 = 0x100812A4;
+pub const XKB_KEY_XF86Macro22		:u32 = 0x100812A5;
+pub const XKB_KEY_XF86Macro23		:u32 = 0x100812A6;
+pub const XKB_KEY_XF86Macro24		:u32 = 0x100812A7;
+pub const XKB_KEY_XF86Macro25		:u32 = 0x100812A8;
+pub const XKB_KEY_XF86Macro26		:u32 = 0x100812A9;
+pub const XKB_KEY_XF86Macro27		:u32 = 0x100812AA;
+pub const XKB_KEY_XF86Macro28		:u32 = 0x100812AB;
+pub const XKB_KEY_XF86Macro29		:u32 = 0x100812AC;
+pub const XKB_KEY_XF86Macro30		:u32 = 0x100812AD;
+pub const XKB_KEY_XF86MacroRecordStart	:u32 = 0x100812B0;
+pub const XKB_KEY_XF86MacroRecordStop	:u32 = 0x100812B1;
+pub const XKB_KEY_XF86MacroPresetCycle	:u32 = 0x100812B2;
+pub const XKB_KEY_XF86MacroPreset1	:u32 = 0x100812B3;
+pub const XKB_KEY_XF86MacroPreset2	:u32 = 0x100812B4;
+pub const XKB_KEY_XF86MacroPreset3	:u32 = 0x100812B5;
+pub const XKB_KEY_XF86KbdLcdMenu1	:u32 = 0x100812B8;
+pub const XKB_KEY_XF86KbdLcdMenu2	:u32 = 0x100812B9;
+pub const XKB_KEY_XF86KbdLcdMenu3	:u32 = 0x100812BA;
+pub const XKB_KEY_XF86KbdLcdMenu4	:u32 = 0x100812BB;
+pub const XKB_KEY_XF86KbdLcdMenu5	:u32 = 0x100812BC;
 /*
  * Copyright (c) 1991, Oracle and/or its affiliates. All rights reserved.
  *
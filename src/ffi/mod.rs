@@ -1,9 +1,13 @@
 #![allow(dead_code, non_camel_case_types)]
 #![cfg_attr(rustfmt, rustfmt_skip)]
 
+use std::env;
 use std::os::raw::{c_char, c_int, c_void, c_uint};
+use std::sync::{Mutex, MutexGuard};
 
 pub mod keysyms;
+#[cfg(feature = "registry")]
+pub mod registry;
 
 pub const XKB_MOD_NAME_SHIFT   : &'static [u8]  = b"Shift\0";
 pub const XKB_MOD_NAME_CAPS    : &'static [u8]  = b"Lock\0";
@@ -11,6 +15,8 @@ pub const XKB_MOD_NAME_CTRL    : &'static [u8]  = b"Control\0";
 pub const XKB_MOD_NAME_ALT     : &'static [u8]  = b"Mod1\0";
 pub const XKB_MOD_NAME_NUM     : &'static [u8]  = b"Mod2\0";
 pub const XKB_MOD_NAME_LOGO    : &'static [u8]  = b"Mod4\0";
+// the modifier of the AltGr key (LevelThree) in the usual keymaps
+pub const XKB_MOD_NAME_MOD5    : &'static [u8]  = b"Mod5\0";
 
 pub const XKB_LED_NAME_CAPS    : &'static [u8]  = b"Caps Lock\0";
 pub const XKB_LED_NAME_NUM     : &'static [u8]  = b"Num Lock\0";
@@ -21,6 +27,8 @@ pub struct xkb_keymap;
 pub struct xkb_state;
 pub struct xkb_compose_table;
 pub struct xkb_compose_state;
+pub struct xkb_compose_table_entry;
+pub struct xkb_compose_table_iterator;
 
 pub type xkb_keycode_t = u32;
 pub type xkb_keysym_t = u32;
@@ -100,6 +108,11 @@ pub enum xkb_keymap_format {
     XKB_KEYMAP_USE_ORIGINAL_FORMAT = 0,
     /** The current/classic XKB text format, as generated by xkbcomp -xkb. */
     XKB_KEYMAP_FORMAT_TEXT_V1 = 1,
+    /**
+     * Xkbcommon extensions of the classic XKB text format.
+     * @since 1.11.0
+     */
+    XKB_KEYMAP_FORMAT_TEXT_V2 = 2,
 }
 
 #[repr(C)]
@@ -111,6 +124,15 @@ pub enum xkb_key_direction {
     XKB_KEY_DOWN
 }
 
+#[repr(C)]
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum xkb_consumed_mode {
+    /** This is the mode defined in the XKB specification and used by libX11. */
+    XKB_CONSUMED_MODE_XKB,
+    /** This is the mode used by the GTK+ toolkit. */
+    XKB_CONSUMED_MODE_GTK
+}
+
 #[repr(C)]
 #[derive(Copy, Clone, Debug, PartialEq)]
 pub enum xkb_compose_compile_flags {
@@ -215,6 +237,21 @@ functions:
     fn xkb_keymap_ref(*mut xkb_keymap) -> *mut xkb_keymap,
     fn xkb_keymap_unref(*mut xkb_keymap) -> (),
     fn xkb_keymap_get_as_string(*mut xkb_keymap, xkb_keymap_format) -> *const c_char,
+    fn xkb_keymap_min_keycode(*mut xkb_keymap) -> xkb_keycode_t,
+    fn xkb_keymap_max_keycode(*mut xkb_keymap) -> xkb_keycode_t,
+    fn xkb_keymap_num_layouts(*mut xkb_keymap) -> xkb_layout_index_t,
+    fn xkb_keymap_num_layouts_for_key(*mut xkb_keymap, xkb_keycode_t) -> xkb_layout_index_t,
+    fn xkb_keymap_num_levels_for_key(*mut xkb_keymap, xkb_keycode_t, xkb_layout_index_t) -> xkb_level_index_t,
+    fn xkb_keymap_key_get_syms_by_level(*mut xkb_keymap,
+                                        xkb_keycode_t,
+                                        xkb_layout_index_t,
+                                        xkb_level_index_t,
+                                        *mut *const xkb_keysym_t
+                                       ) -> c_int,
+    fn xkb_keymap_mod_get_index(*mut xkb_keymap, *const c_char) -> xkb_mod_index_t,
+    fn xkb_keymap_key_get_name(*mut xkb_keymap, xkb_keycode_t) -> *const c_char,
+    fn xkb_keymap_key_repeats(*mut xkb_keymap, xkb_keycode_t) -> c_int,
+    fn xkb_keymap_layout_get_name(*mut xkb_keymap, xkb_layout_index_t) -> *const c_char,
 
     fn xkb_state_new(*mut xkb_keymap) -> *mut xkb_state,
     fn xkb_state_ref(*mut xkb_state) -> *mut xkb_state,
@@ -242,8 +279,133 @@ functions:
                              ) -> c_int,
     fn xkb_state_key_get_utf32(*mut xkb_state, xkb_keycode_t) -> u32,
     fn xkb_state_key_get_one_sym(*mut xkb_state, xkb_keycode_t) -> xkb_keysym_t,
+    fn xkb_state_key_get_layout(*mut xkb_state, xkb_keycode_t) -> xkb_layout_index_t,
+    fn xkb_state_key_get_level(*mut xkb_state, xkb_keycode_t, xkb_layout_index_t) -> xkb_level_index_t,
     fn xkb_state_mod_name_is_active(*mut xkb_state, *const c_char, xkb_state_component) -> c_int,
+    fn xkb_state_led_name_is_active(*mut xkb_state, *const c_char) -> c_int,
+    fn xkb_state_mod_index_is_consumed(*mut xkb_state, xkb_keycode_t, xkb_mod_index_t) -> c_int,
+    fn xkb_state_layout_index_is_active(*mut xkb_state, xkb_layout_index_t, xkb_state_component) -> c_int,
+    fn xkb_state_serialize_mods(*mut xkb_state, xkb_state_component) -> xkb_mod_mask_t,
+    fn xkb_state_serialize_layout(*mut xkb_state, xkb_state_component) -> xkb_layout_index_t,
+);
+
+pub const XKBCOMMON_LIBRARY: &'static str = "libxkbcommon.so";
+// tried in order, the unversioned name is usually only installed with the headers
+pub const XKBCOMMON_LIBRARIES: &'static [&'static str] = &["libxkbcommon.so.0", "libxkbcommon.so"];
+pub const XKBCOMMON_PATH_ENV: &'static str = "WAYLAND_KBD_XKB_PATH";
+
+// the libraries to try if they were chosen, and the outcome of the loading
+struct XkbCommonPath {
+    candidates: Option<Vec<String>>,
+    // set when the loading starts, after which the candidates cannot change
+    loaded: bool,
+    tried: Vec<String>,
+    path: Option<String>,
+}
+
+lazy_static!(
+    static ref XKBCOMMON_PATH: Mutex<XkbCommonPath> = {
+        Mutex::new(XkbCommonPath { candidates: None, loaded: false, tried: Vec::new(), path: None })
+    };
+);
+
+// the state stays consistent if a thread panicked while holding the lock
+fn xkbcommon_path_lock() -> MutexGuard<'static, XkbCommonPath> {
+    XKBCOMMON_PATH.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+/// Choose the libxkbcommon libraries to try, returns false if the loading was done already
+pub fn set_xkbcommon_paths(candidates: Vec<String>) -> bool {
+    let mut xkbcommon_path = xkbcommon_path_lock();
+    if xkbcommon_path.loaded {
+        return false;
+    }
+    xkbcommon_path.candidates = Some(candidates);
+    true
+}
+
+/// The libxkbcommon libraries to try, in order
+pub fn xkbcommon_candidates() -> Vec<String> {
+    candidates_of(&xkbcommon_path_lock())
+}
+
+fn candidates_of(xkbcommon_path: &XkbCommonPath) -> Vec<String> {
+    if let Some(ref candidates) = xkbcommon_path.candidates {
+        return candidates.clone();
+    }
+    match env::var(XKBCOMMON_PATH_ENV) {
+        Ok(ref paths) if !paths.is_empty() => {
+            paths.split(':').filter(|path| !path.is_empty()).map(|path| path.to_owned()).collect()
+        }
+        _ => XKBCOMMON_LIBRARIES.iter().map(|&path| path.to_owned()).collect(),
+    }
+}
+
+/// The libxkbcommon library that was loaded, or the first one to try if it was not
+pub fn xkbcommon_path() -> String {
+    {
+        let xkbcommon_path = xkbcommon_path_lock();
+        if let Some(ref path) = xkbcommon_path.path {
+            return path.clone();
+        }
+    }
+    xkbcommon_candidates().into_iter().next().unwrap_or_else(|| XKBCOMMON_LIBRARY.to_owned())
+}
+
+/// The libxkbcommon libraries that were tried, empty if the loading was not done yet
+pub fn xkbcommon_tried() -> Vec<String> {
+    xkbcommon_path_lock().tried.clone()
+}
+
+fn load_xkbcommon() -> Option<XkbCommon> {
+    // the candidates are taken under the same lock as `set_xkbcommon_paths()` checks,
+    // so that choosing other libraries fails once they are being loaded
+    let candidates = {
+        let mut xkbcommon_path = xkbcommon_path_lock();
+        xkbcommon_path.loaded = true;
+        candidates_of(&xkbcommon_path)
+    };
+    let mut tried = Vec::new();
+    let mut loaded = None;
+    for path in candidates {
+        tried.push(path.clone());
+        if let Ok(lib) = XkbCommon::open(&path) {
+            loaded = Some((lib, path));
+            break;
+        }
+    }
+    let mut xkbcommon_path = xkbcommon_path_lock();
+    xkbcommon_path.tried = tried;
+    match loaded {
+        Some((lib, path)) => {
+            xkbcommon_path.path = Some(path);
+            Some(lib)
+        }
+        None => None,
+    }
+}
+
+// the optional symbols are taken from the library providing the others
+fn loaded_xkbcommon_path() -> Option<String> {
+    if XKBCOMMON_OPTION.is_none() {
+        return None;
+    }
+    xkbcommon_path_lock().path.clone()
+}
+
+// Loaded separately so that it can be compiled out with the `compose` feature
+#[cfg(feature = "compose")]
+dlopen_external_library!(XkbCommonCompose,
+functions:
     fn xkb_compose_table_new_from_locale(*mut xkb_context, *const c_char, xkb_compose_compile_flags) -> *mut xkb_compose_table,
+    fn xkb_compose_table_new_from_buffer(*mut xkb_context,
+                                         *const c_char,
+                                         usize,
+                                         *const c_char,
+                                         xkb_compose_format,
+                                         xkb_compose_compile_flags
+                                        ) -> *mut xkb_compose_table,
+    fn xkb_compose_table_ref(*mut xkb_compose_table) -> *mut xkb_compose_table,
     fn xkb_compose_table_unref(*mut xkb_compose_table) -> (),
     fn xkb_compose_state_new(*mut xkb_compose_table, xkb_compose_state_flags) -> *mut xkb_compose_state,
     fn xkb_compose_state_unref(*mut xkb_compose_state) -> (),
@@ -254,11 +416,99 @@ functions:
     fn xkb_compose_state_get_one_sym(*mut xkb_compose_state) -> xkb_keysym_t,
 );
 
+// Added in libxkbcommon 1.6, loaded separately so that older versions still work
+#[cfg(feature = "compose")]
+dlopen_external_library!(XkbCommonComposeIter,
+functions:
+    fn xkb_compose_table_iterator_new(*mut xkb_compose_table) -> *mut xkb_compose_table_iterator,
+    fn xkb_compose_table_iterator_free(*mut xkb_compose_table_iterator) -> (),
+    fn xkb_compose_table_iterator_next(*mut xkb_compose_table_iterator) -> *mut xkb_compose_table_entry,
+    fn xkb_compose_table_entry_sequence(*mut xkb_compose_table_entry, *mut usize) -> *const xkb_keysym_t,
+    fn xkb_compose_table_entry_keysym(*mut xkb_compose_table_entry) -> xkb_keysym_t,
+    fn xkb_compose_table_entry_utf8(*mut xkb_compose_table_entry) -> *const c_char,
+);
+
+// Added in libxkbcommon 0.7
+dlopen_external_library!(XkbCommonConsumedMode,
+functions:
+    fn xkb_state_key_get_consumed_mods2(*mut xkb_state, xkb_keycode_t, xkb_consumed_mode) -> xkb_mod_mask_t,
+    fn xkb_state_mod_index_is_consumed2(*mut xkb_state,
+                                        xkb_keycode_t,
+                                        xkb_mod_index_t,
+                                        xkb_consumed_mode
+                                       ) -> c_int,
+);
+
+// Added in libxkbcommon 0.8
+dlopen_external_library!(XkbCommonKeysymCase,
+functions:
+    fn xkb_keysym_to_lower(xkb_keysym_t) -> xkb_keysym_t,
+    fn xkb_keysym_to_upper(xkb_keysym_t) -> xkb_keysym_t,
+);
+
+// Added in libxkbcommon 1.0
+dlopen_external_library!(XkbCommonUtf32,
+functions:
+    fn xkb_utf32_to_keysym(u32) -> xkb_keysym_t,
+);
+
+// Added in libxkbcommon 1.0
+dlopen_external_library!(XkbCommonModsForLevel,
+functions:
+    fn xkb_keymap_key_get_mods_for_level(*mut xkb_keymap,
+                                         xkb_keycode_t,
+                                         xkb_layout_index_t,
+                                         xkb_level_index_t,
+                                         *mut xkb_mod_mask_t,
+                                         usize
+                                        ) -> usize,
+);
+
+// Added in libxkbcommon 1.11
+dlopen_external_library!(XkbCommonKeymapFormats,
+functions:
+    fn xkb_keymap_new_from_names2(*mut xkb_context,
+                                  *const xkb_rule_names,
+                                  xkb_keymap_format,
+                                  xkb_keymap_compile_flags
+                                 ) -> *mut xkb_keymap,
+);
+
 lazy_static!(
     pub static ref XKBCOMMON_OPTION: Option<XkbCommon> = {
-        XkbCommon::open("libxkbcommon.so").ok()
+        load_xkbcommon()
     };
     pub static ref XKBCOMMON_HANDLE: &'static XkbCommon = {
-        XKBCOMMON_OPTION.as_ref().expect("Library libxkbcommon.so could not be loaded.")
+        XKBCOMMON_OPTION.as_ref().unwrap_or_else(|| {
+            panic!("Library libxkbcommon could not be loaded, tried: {}", xkbcommon_tried().join(", "))
+        })
+    };
+    pub static ref XKBCOMMON_CONSUMED_MODE_OPTION: Option<XkbCommonConsumedMode> = {
+        loaded_xkbcommon_path().and_then(|path| XkbCommonConsumedMode::open(&path).ok())
+    };
+    pub static ref XKBCOMMON_KEYSYM_CASE_OPTION: Option<XkbCommonKeysymCase> = {
+        loaded_xkbcommon_path().and_then(|path| XkbCommonKeysymCase::open(&path).ok())
+    };
+    pub static ref XKBCOMMON_UTF32_OPTION: Option<XkbCommonUtf32> = {
+        loaded_xkbcommon_path().and_then(|path| XkbCommonUtf32::open(&path).ok())
+    };
+    pub static ref XKBCOMMON_MODS_FOR_LEVEL_OPTION: Option<XkbCommonModsForLevel> = {
+        loaded_xkbcommon_path().and_then(|path| XkbCommonModsForLevel::open(&path).ok())
+    };
+    pub static ref XKBCOMMON_KEYMAP_FORMATS_OPTION: Option<XkbCommonKeymapFormats> = {
+        loaded_xkbcommon_path().and_then(|path| XkbCommonKeymapFormats::open(&path).ok())
+    };
+);
+
+#[cfg(feature = "compose")]
+lazy_static!(
+    pub static ref XKBCOMMON_COMPOSE_OPTION: Option<XkbCommonCompose> = {
+        loaded_xkbcommon_path().and_then(|path| XkbCommonCompose::open(&path).ok())
+    };
+    pub static ref XKBCOMMON_COMPOSE_HANDLE: &'static XkbCommonCompose = {
+        XKBCOMMON_COMPOSE_OPTION.as_ref().expect("Library libxkbcommon has no compose support")
+    };
+    pub static ref XKBCOMMON_COMPOSE_ITER_OPTION: Option<XkbCommonComposeIter> = {
+        loaded_xkbcommon_path().and_then(|path| XkbCommonComposeIter::open(&path).ok())
     };
 );
@@ -0,0 +1,54 @@
+#![allow(dead_code, non_camel_case_types)]
+#![cfg_attr(rustfmt, rustfmt_skip)]
+
+use std::os::raw::c_char;
+
+pub struct rxkb_context;
+pub struct rxkb_model;
+pub struct rxkb_layout;
+pub struct rxkb_option_group;
+pub struct rxkb_option;
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum rxkb_context_flags {
+    RXKB_CONTEXT_NO_FLAGS = 0,
+    RXKB_CONTEXT_NO_DEFAULT_INCLUDES = (1 << 0),
+    RXKB_CONTEXT_LOAD_EXOTIC_RULES = (1 << 1),
+}
+
+dlopen_external_library!(XkbRegistry,
+functions:
+    fn rxkb_context_new(rxkb_context_flags) -> *mut rxkb_context,
+    fn rxkb_context_parse(*mut rxkb_context, *const c_char) -> bool,
+    fn rxkb_context_parse_default_ruleset(*mut rxkb_context) -> bool,
+    fn rxkb_context_unref(*mut rxkb_context) -> *mut rxkb_context,
+    fn rxkb_model_first(*mut rxkb_context) -> *mut rxkb_model,
+    fn rxkb_model_next(*mut rxkb_model) -> *mut rxkb_model,
+    fn rxkb_model_get_name(*mut rxkb_model) -> *const c_char,
+    fn rxkb_model_get_description(*mut rxkb_model) -> *const c_char,
+    fn rxkb_model_get_vendor(*mut rxkb_model) -> *const c_char,
+    fn rxkb_layout_first(*mut rxkb_context) -> *mut rxkb_layout,
+    fn rxkb_layout_next(*mut rxkb_layout) -> *mut rxkb_layout,
+    fn rxkb_layout_get_name(*mut rxkb_layout) -> *const c_char,
+    fn rxkb_layout_get_variant(*mut rxkb_layout) -> *const c_char,
+    fn rxkb_layout_get_brief(*mut rxkb_layout) -> *const c_char,
+    fn rxkb_layout_get_description(*mut rxkb_layout) -> *const c_char,
+    fn rxkb_option_group_first(*mut rxkb_context) -> *mut rxkb_option_group,
+    fn rxkb_option_group_next(*mut rxkb_option_group) -> *mut rxkb_option_group,
+    fn rxkb_option_group_get_name(*mut rxkb_option_group) -> *const c_char,
+    fn rxkb_option_group_get_description(*mut rxkb_option_group) -> *const c_char,
+    fn rxkb_option_group_allows_multiple(*mut rxkb_option_group) -> bool,
+    fn rxkb_option_first(*mut rxkb_option_group) -> *mut rxkb_option,
+    fn rxkb_option_next(*mut rxkb_option) -> *mut rxkb_option,
+    fn rxkb_option_get_name(*mut rxkb_option) -> *const c_char,
+    fn rxkb_option_get_description(*mut rxkb_option) -> *const c_char,
+);
+
+pub const XKBREGISTRY_LIBRARY: &'static str = "libxkbregistry.so";
+
+lazy_static!(
+    pub static ref XKBREGISTRY_OPTION: Option<XkbRegistry> = {
+        XkbRegistry::open(XKBREGISTRY_LIBRARY).ok()
+    };
+);
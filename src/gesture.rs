@@ -0,0 +1,247 @@
+use mapped_keyboard::duration_ms;
+use std::time::{Duration, Instant};
+
+/// A gesture performed on a single key
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum KeyGesture {
+    /// The key was pressed and released within the tap timeout
+    Tap,
+    /// The key was held longer than the tap timeout
+    ///
+    /// This is notified when the key is released, or when another key is pressed
+    /// while it is still held, whichever comes first. Nothing else wakes the client up,
+    /// so applications wanting it as soon as the timeout expires poll for it with
+    /// `KeyboardHandle::poll_gestures()` at `KeyboardHandle::gesture_deadline()`.
+    Hold,
+    /// The key was tapped twice, the second press following the first release
+    /// within the double tap window
+    ///
+    /// The second tap is not reported as a `Tap`.
+    DoubleTap,
+}
+
+/// Configuration of the gestures detected on a key
+#[derive(Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct GestureConfig {
+    /// The keysym of the key
+    pub keysym: u32,
+    /// Presses shorter than this are taps, longer ones are holds
+    pub tap_timeout: Duration,
+    /// The maximum delay between the release of a tap and the press of the next
+    /// one for them to form a double tap
+    pub double_tap_window: Duration,
+}
+
+struct KeyTracker {
+    pressed: Option<(u32, u32)>,
+    // when the press was received, to detect holds without key events
+    pressed_at: Option<Instant>,
+    hold_reported: bool,
+    last_tap: Option<u32>,
+}
+
+/// Tracks the keys configured for gesture detection
+pub struct GestureState {
+    trackers: Vec<KeyTracker>,
+}
+
+impl GestureState {
+    pub fn new() -> GestureState {
+        GestureState {
+            trackers: Vec::new(),
+        }
+    }
+
+    /// Process a key event, returning the detected gestures as (keysym, gesture) pairs
+    pub fn feed(&mut self, configs: &[GestureConfig], keycode: u32, keysym: u32, pressed: bool, time: u32,
+                now: Instant)
+                -> Vec<(u32, KeyGesture)> {
+        let mut gestures = Vec::new();
+        if configs.is_empty() {
+            return gestures;
+        }
+        while self.trackers.len() < configs.len() {
+            self.trackers.push(KeyTracker {
+                pressed: None,
+                pressed_at: None,
+                hold_reported: false,
+                last_tap: None,
+            });
+        }
+
+        for (config, tracker) in configs.iter().zip(self.trackers.iter_mut()) {
+            let tap_timeout = duration_ms(config.tap_timeout);
+            // holds of other keys are reported when a key is pressed
+            if let Some((held_keycode, press_time)) = tracker.pressed {
+                if held_keycode != keycode && pressed && !tracker.hold_reported
+                    && u64::from(time.wrapping_sub(press_time)) >= tap_timeout
+                {
+                    tracker.hold_reported = true;
+                    gestures.push((config.keysym, KeyGesture::Hold));
+                }
+            }
+
+            if pressed && keysym == config.keysym {
+                tracker.pressed = Some((keycode, time));
+                tracker.pressed_at = Some(now);
+                tracker.hold_reported = false;
+            } else if !pressed {
+                let press_time = match tracker.pressed {
+                    Some((held_keycode, press_time)) if held_keycode == keycode => press_time,
+                    _ => continue,
+                };
+                tracker.pressed = None;
+                tracker.pressed_at = None;
+                if tracker.hold_reported {
+                    continue;
+                }
+                if u64::from(time.wrapping_sub(press_time)) >= tap_timeout {
+                    gestures.push((config.keysym, KeyGesture::Hold));
+                    continue;
+                }
+                let window = duration_ms(config.double_tap_window);
+                let double = match tracker.last_tap {
+                    Some(last) => u64::from(press_time.wrapping_sub(last)) <= window,
+                    None => false,
+                };
+                if double {
+                    tracker.last_tap = None;
+                    gestures.push((config.keysym, KeyGesture::DoubleTap));
+                } else {
+                    tracker.last_tap = Some(time);
+                    gestures.push((config.keysym, KeyGesture::Tap));
+                }
+            }
+        }
+
+        gestures
+    }
+
+    /// The time at which a held key becomes a hold, if any
+    pub fn hold_deadline(&self, configs: &[GestureConfig]) -> Option<Instant> {
+        configs
+            .iter()
+            .zip(self.trackers.iter())
+            .filter(|&(_, tracker)| !tracker.hold_reported)
+            .filter_map(|(config, tracker)| tracker.pressed_at.map(|at| at + config.tap_timeout))
+            .min()
+    }
+
+    /// Report the keys held longer than their tap timeout at `now`
+    ///
+    /// The holds reported here are not reported again by `feed()`.
+    pub fn poll_holds(&mut self, configs: &[GestureConfig], now: Instant) -> Vec<(u32, KeyGesture)> {
+        let mut gestures = Vec::new();
+        for (config, tracker) in configs.iter().zip(self.trackers.iter_mut()) {
+            let due = match tracker.pressed_at {
+                Some(at) => at + config.tap_timeout <= now,
+                None => false,
+            };
+            if due && !tracker.hold_reported {
+                tracker.hold_reported = true;
+                gestures.push((config.keysym, KeyGesture::Hold));
+            }
+        }
+        gestures
+    }
+
+    /// Forget about the keys currently held, as when losing focus
+    pub fn release_all(&mut self) {
+        for tracker in &mut self.trackers {
+            tracker.pressed = None;
+            tracker.pressed_at = None;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ffi::keysyms;
+
+    // evdev keycodes of the Space and A keys
+    const KEY_SPACE: u32 = 57;
+    const KEY_A: u32 = 30;
+
+    fn space() -> Vec<GestureConfig> {
+        vec![
+            GestureConfig {
+                keysym: keysyms::XKB_KEY_space,
+                tap_timeout: Duration::from_millis(200),
+                double_tap_window: Duration::from_millis(300),
+            },
+        ]
+    }
+
+    // feed a key event, the gestures only depending on the timestamps
+    fn feed(state: &mut GestureState, keycode: u32, pressed: bool, time: u32) -> Vec<(u32, KeyGesture)> {
+        let keysym = if keycode == KEY_SPACE { keysyms::XKB_KEY_space } else { keysyms::XKB_KEY_a };
+        state.feed(&space(), keycode, keysym, pressed, time, Instant::now())
+    }
+
+    #[test]
+    fn tap() {
+        let mut state = GestureState::new();
+        assert_eq!(feed(&mut state, KEY_SPACE, true, 1000), vec![]);
+        assert_eq!(feed(&mut state, KEY_SPACE, false, 1199), vec![(keysyms::XKB_KEY_space, KeyGesture::Tap)]);
+    }
+
+    #[test]
+    fn hold_on_release() {
+        let mut state = GestureState::new();
+        feed(&mut state, KEY_SPACE, true, 1000);
+        let released = feed(&mut state, KEY_SPACE, false, 1200);
+        assert_eq!(released, vec![(keysyms::XKB_KEY_space, KeyGesture::Hold)]);
+    }
+
+    #[test]
+    fn hold_on_other_key() {
+        let mut state = GestureState::new();
+        feed(&mut state, KEY_SPACE, true, 1000);
+        // too early to be a hold
+        assert_eq!(feed(&mut state, KEY_A, true, 1100), vec![]);
+        assert_eq!(feed(&mut state, KEY_A, false, 1150), vec![]);
+        assert_eq!(feed(&mut state, KEY_A, true, 1250), vec![(keysyms::XKB_KEY_space, KeyGesture::Hold)]);
+        // reported once
+        assert_eq!(feed(&mut state, KEY_A, false, 1300), vec![]);
+        assert_eq!(feed(&mut state, KEY_SPACE, false, 1400), vec![]);
+    }
+
+    #[test]
+    fn double_tap() {
+        let mut state = GestureState::new();
+        feed(&mut state, KEY_SPACE, true, 1000);
+        feed(&mut state, KEY_SPACE, false, 1100);
+        feed(&mut state, KEY_SPACE, true, 1400);
+        assert_eq!(
+            feed(&mut state, KEY_SPACE, false, 1450),
+            vec![(keysyms::XKB_KEY_space, KeyGesture::DoubleTap)]
+        );
+        // a third tap starts over
+        feed(&mut state, KEY_SPACE, true, 1500);
+        assert_eq!(feed(&mut state, KEY_SPACE, false, 1550), vec![(keysyms::XKB_KEY_space, KeyGesture::Tap)]);
+    }
+
+    #[test]
+    fn taps_outside_double_tap_window() {
+        let mut state = GestureState::new();
+        feed(&mut state, KEY_SPACE, true, 1000);
+        feed(&mut state, KEY_SPACE, false, 1100);
+        feed(&mut state, KEY_SPACE, true, 1401);
+        assert_eq!(feed(&mut state, KEY_SPACE, false, 1450), vec![(keysyms::XKB_KEY_space, KeyGesture::Tap)]);
+    }
+
+    #[test]
+    fn polled_hold() {
+        let mut state = GestureState::new();
+        let pressed_at = Instant::now();
+        state.feed(&space(), KEY_SPACE, keysyms::XKB_KEY_space, true, 1000, pressed_at);
+        let deadline = pressed_at + Duration::from_millis(200);
+        assert_eq!(state.hold_deadline(&space()), Some(deadline));
+        assert_eq!(state.poll_holds(&space(), pressed_at + Duration::from_millis(199)), vec![]);
+        assert_eq!(state.poll_holds(&space(), deadline), vec![(keysyms::XKB_KEY_space, KeyGesture::Hold)]);
+        assert_eq!(state.hold_deadline(&space()), None);
+        // not reported again on release
+        assert_eq!(feed(&mut state, KEY_SPACE, false, 1300), vec![]);
+    }
+}
@@ -0,0 +1,192 @@
+//! Segmentation of the text produced by keys into grapheme clusters
+//!
+//! This implements the rules of UAX #29 that matter for keyboard input: combining
+//! marks, zero width joiners, emoji modifiers, flags and Hangul syllables. Prepended
+//! concatenation marks and the Indic conjunct rules are not handled.
+
+// code points extending the previous grapheme cluster: combining marks, variation
+// selectors, emoji modifiers and tags
+const EXTEND: &'static [(u32, u32)] = &[
+    (0x0300, 0x036F),
+    (0x0483, 0x0489),
+    (0x0591, 0x05BD),
+    (0x05BF, 0x05BF),
+    (0x05C1, 0x05C2),
+    (0x05C4, 0x05C5),
+    (0x05C7, 0x05C7),
+    (0x0610, 0x061A),
+    (0x064B, 0x065F),
+    (0x0670, 0x0670),
+    (0x06D6, 0x06DC),
+    (0x06DF, 0x06E4),
+    (0x06E7, 0x06E8),
+    (0x06EA, 0x06ED),
+    (0x0900, 0x0903),
+    (0x093A, 0x093C),
+    (0x093E, 0x094F),
+    (0x0951, 0x0957),
+    (0x0962, 0x0963),
+    (0x0981, 0x0983),
+    (0x09BC, 0x09BC),
+    (0x09BE, 0x09CD),
+    (0x0E31, 0x0E31),
+    (0x0E34, 0x0E3A),
+    (0x0E47, 0x0E4E),
+    (0x1AB0, 0x1AFF),
+    (0x1DC0, 0x1DFF),
+    (0x200C, 0x200C),
+    (0x20D0, 0x20FF),
+    (0x302A, 0x302F),
+    (0x3099, 0x309A),
+    (0xFE00, 0xFE0F),
+    (0xFE20, 0xFE2F),
+    (0x1F3FB, 0x1F3FF),
+    (0xE0020, 0xE007F),
+    (0xE0100, 0xE01EF),
+];
+
+#[derive(Copy, Clone, PartialEq, Eq)]
+enum Class {
+    Cr,
+    Lf,
+    Control,
+    Extend,
+    Zwj,
+    RegionalIndicator,
+    L,
+    V,
+    T,
+    Lv,
+    Lvt,
+    Other,
+}
+
+fn within(c: u32, ranges: &[(u32, u32)]) -> bool {
+    ranges.iter().any(|&(start, end)| start <= c && c <= end)
+}
+
+fn class(c: char) -> Class {
+    let c = c as u32;
+    if c == 0x0D {
+        Class::Cr
+    } else if c == 0x0A {
+        Class::Lf
+    } else if within(c, &[(0x00, 0x1F), (0x7F, 0x9F)]) {
+        Class::Control
+    } else if c == 0x200D {
+        Class::Zwj
+    } else if within(c, &[(0x1F1E6, 0x1F1FF)]) {
+        Class::RegionalIndicator
+    } else if within(c, &[(0x1100, 0x115F), (0xA960, 0xA97C)]) {
+        Class::L
+    } else if within(c, &[(0x1160, 0x11A7), (0xD7B0, 0xD7C6)]) {
+        Class::V
+    } else if within(c, &[(0x11A8, 0x11FF), (0xD7CB, 0xD7FB)]) {
+        Class::T
+    } else if within(c, &[(0xAC00, 0xD7A3)]) {
+        // precomposed syllables, with a trailing consonant every 28 code points
+        if (c - 0xAC00) % 28 == 0 { Class::Lv } else { Class::Lvt }
+    } else if within(c, EXTEND) {
+        Class::Extend
+    } else {
+        Class::Other
+    }
+}
+
+// whether there is no boundary between two code points, `regional` counting the
+// regional indicators just before the second one
+fn joined(before: Class, after: Class, regional: usize) -> bool {
+    match (before, after) {
+        (Class::Cr, Class::Lf) => true,
+        (Class::Cr, _) | (Class::Lf, _) | (Class::Control, _) => false,
+        (_, Class::Cr) | (_, Class::Lf) | (_, Class::Control) => false,
+        (Class::L, Class::L) | (Class::L, Class::V) | (Class::L, Class::Lv) | (Class::L, Class::Lvt) => true,
+        (Class::Lv, Class::V) | (Class::Lv, Class::T) | (Class::V, Class::V) | (Class::V, Class::T) => true,
+        (Class::Lvt, Class::T) | (Class::T, Class::T) => true,
+        (_, Class::Extend) | (_, Class::Zwj) => true,
+        // emoji sequences, like a family, are joined by zero width joiners
+        (Class::Zwj, Class::Other) => true,
+        (Class::RegionalIndicator, Class::RegionalIndicator) => regional % 2 == 1,
+        _ => false,
+    }
+}
+
+/// Split a text into its grapheme clusters, the characters as perceived by the user
+pub fn graphemes(text: &str) -> Vec<&str> {
+    let mut clusters = Vec::new();
+    let mut start = 0;
+    let mut previous: Option<Class> = None;
+    let mut regional = 0;
+    for (index, c) in text.char_indices() {
+        let current = class(c);
+        if let Some(before) = previous {
+            if !joined(before, current, regional) {
+                clusters.push(&text[start..index]);
+                start = index;
+            }
+        }
+        regional = if current == Class::RegionalIndicator { regional + 1 } else { 0 };
+        previous = Some(current);
+    }
+    if start < text.len() {
+        clusters.push(&text[start..]);
+    }
+    clusters
+}
+
+/// Whether a text is a single grapheme cluster
+pub fn is_single_grapheme(text: &str) -> bool {
+    graphemes(text).len() == 1
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn combining_marks() {
+        assert_eq!(graphemes("e\u{301}"), vec!["e\u{301}"]);
+        assert_eq!(graphemes("e\u{301}a\u{308}\u{304}"), vec!["e\u{301}", "a\u{308}\u{304}"]);
+        assert!(is_single_grapheme("e\u{301}"));
+    }
+
+    #[test]
+    fn emoji_sequences() {
+        // woman, zero width joiner, laptop
+        assert_eq!(graphemes("\u{1F469}\u{200D}\u{1F4BB}"), vec!["\u{1F469}\u{200D}\u{1F4BB}"]);
+        // family of three, then a thumbs up with a skin tone
+        let family = "\u{1F468}\u{200D}\u{1F469}\u{200D}\u{1F467}";
+        let thumbs_up = "\u{1F44D}\u{1F3FD}";
+        assert_eq!(graphemes(&format!("{}{}", family, thumbs_up)), vec![family, thumbs_up]);
+    }
+
+    #[test]
+    fn flags() {
+        let france = "\u{1F1EB}\u{1F1F7}";
+        let japan = "\u{1F1EF}\u{1F1F5}";
+        assert_eq!(graphemes(france), vec![france]);
+        assert_eq!(graphemes(&format!("{}{}", france, japan)), vec![france, japan]);
+        // a lone regional indicator after a flag
+        assert_eq!(graphemes(&format!("{}\u{1F1EF}", france)), vec![france, "\u{1F1EF}"]);
+    }
+
+    #[test]
+    fn hangul() {
+        // conjoining jamo, L + V + T
+        assert_eq!(graphemes("\u{1100}\u{1161}\u{11A8}"), vec!["\u{1100}\u{1161}\u{11A8}"]);
+        // LV syllable + T, then an LVT syllable
+        assert_eq!(graphemes("\u{AC00}\u{11A8}\u{AC01}"), vec!["\u{AC00}\u{11A8}", "\u{AC01}"]);
+        // an LVT syllable takes no vowel
+        assert_eq!(graphemes("\u{AC01}\u{1161}"), vec!["\u{AC01}", "\u{1161}"]);
+    }
+
+    #[test]
+    fn several_clusters() {
+        assert_eq!(graphemes("ab"), vec!["a", "b"]);
+        assert_eq!(graphemes("\r\n"), vec!["\r\n"]);
+        assert!(graphemes("").is_empty());
+        assert!(!is_single_grapheme("ab"));
+        assert!(!is_single_grapheme("e\u{301}e"));
+        assert!(!is_single_grapheme(""));
+    }
+}
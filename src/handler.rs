@@ -0,0 +1,202 @@
+use mapped_keyboard::{register_kbd_with_options, ComposeEvent, KeyEvent, KeyboardGuard, KeyboardHandle,
+                      KeyboardOptions, MappedKeyboardError, MappedKeyboardImplementation, ModifiersState};
+use overflow::OverflowPolicy;
+use shared::Shared;
+use std::time::Instant;
+use wayland_client::{EventQueueHandle, Proxy, RequestResult};
+use wayland_client::protocol::wl_seat::{self, WlSeat};
+use wayland_client::protocol::wl_surface::WlSurface;
+
+/// An event of a `KeyboardHandler`
+pub enum KeyboardHandlerEvent {
+    /// The seat gained a keyboard, which was registered
+    Added,
+    /// The keyboard of the seat was removed, or it died
+    Removed,
+    /// A surface gained the keyboard focus
+    Enter {
+        /// The serial of the event
+        serial: u32,
+        /// The surface gaining the focus
+        surface: WlSurface,
+        /// The modifiers active when the surface gained the focus
+        modifiers: ModifiersState,
+    },
+    /// A surface lost the keyboard focus
+    Leave {
+        /// The serial of the event
+        serial: u32,
+        /// The surface losing the focus
+        surface: WlSurface,
+    },
+    /// A key was pressed or released, or a held key repeated
+    Key(KeyEvent),
+    /// The state of a compose sequence changed
+    Compose(ComposeEvent),
+}
+
+/// The keyboard of a seat, with its focus and key repeat, as a single object
+///
+/// Small applications usually need the same pieces: getting the keyboard of the seat
+/// when it has one, registering it, releasing it when it goes away, and generating the
+/// repeats of the held keys. This handler does all of it and queues simple events,
+/// which are retrieved with `poll()`:
+///
+/// ```ignore
+/// // in the implementation of the seat
+/// capabilities: |evqh, handler, seat, capabilities| {
+///     handler.seat_capabilities(evqh, seat, capabilities).unwrap();
+/// },
+/// name: |_, handler, _, name| handler.seat_name(name),
+///
+/// // in the event loop
+/// event_queue.dispatch()?;
+/// for event in handler.poll(Instant::now()) {
+///     // ...
+/// }
+/// ```
+///
+/// Use `next_deadline()` to wake up for the key repeats. Applications needing more
+/// than these events register their keyboards with `register_kbd_with_options()`.
+pub struct KeyboardHandler {
+    options: KeyboardOptions,
+    keyboard: Option<KeyboardGuard>,
+    events: Shared<PendingEvents>,
+}
+
+// the events waiting for `poll()`, bounded like `KeyboardOptions::max_queued_events`
+struct PendingEvents {
+    events: Vec<KeyboardHandlerEvent>,
+    max: Option<usize>,
+    overflow: OverflowPolicy,
+}
+
+impl PendingEvents {
+    fn push(&mut self, event: KeyboardHandlerEvent) {
+        self.events.push(event);
+        self.overflow.apply(&mut self.events, self.max);
+    }
+}
+
+impl KeyboardHandler {
+    /// A handler registering the keyboards with these options
+    pub fn new(options: KeyboardOptions) -> KeyboardHandler {
+        let events = PendingEvents {
+            events: Vec::new(),
+            max: options.max_queued_events,
+            overflow: options.overflow_policy,
+        };
+        KeyboardHandler {
+            options: options,
+            keyboard: None,
+            events: Shared::new(events),
+        }
+    }
+
+    /// Forward a `wl_seat.capabilities` event to the handler
+    ///
+    /// The keyboard of the seat is registered when the seat gains it, and released when
+    /// the seat loses it. Returns an error if the keyboard could not be registered.
+    pub fn seat_capabilities(&mut self, evqh: &mut EventQueueHandle, seat: &WlSeat,
+                             capabilities: wl_seat::Capability)
+                             -> Result<(), MappedKeyboardError> {
+        let has_keyboard = capabilities.contains(wl_seat::Capability::Keyboard);
+        if has_keyboard && self.keyboard.is_none() {
+            let keyboard = match seat.get_keyboard() {
+                RequestResult::Sent(keyboard) => keyboard,
+                // the seat is gone, it will not get a keyboard anymore
+                RequestResult::Destroyed => return Ok(()),
+            };
+            let handle = register_kbd_with_options(
+                evqh,
+                &keyboard,
+                handler_implementation(),
+                self.events.clone(),
+                None,
+                self.options.clone(),
+            )?;
+            self.keyboard = Some(KeyboardGuard::new(keyboard, handle));
+            self.push(KeyboardHandlerEvent::Added);
+        } else if !has_keyboard && self.keyboard.is_some() {
+            self.remove();
+        }
+        Ok(())
+    }
+
+    /// Forward a `wl_seat.name` event to the handler
+    pub fn seat_name(&mut self, name: String) {
+        if let Some(ref keyboard) = self.keyboard {
+            keyboard.handle().set_seat_name(Some(name.clone()));
+        }
+        self.options.seat_name = Some(name);
+    }
+
+    /// The handle of the keyboard, if the seat has one
+    pub fn handle(&self) -> Option<&KeyboardHandle> {
+        self.keyboard.as_ref().map(|keyboard| keyboard.handle())
+    }
+
+    /// The time at which `poll()` should be called next to generate key repeats
+    pub fn next_deadline(&self) -> Option<Instant> {
+        self.handle().and_then(|handle| handle.repeat_deadline())
+    }
+
+    /// The events received since the last call, followed by the key repeats due at `now`
+    pub fn poll(&mut self, now: Instant) -> Vec<KeyboardHandlerEvent> {
+        let alive = self.handle().map(|handle| handle.is_alive());
+        if alive == Some(false) {
+            self.remove();
+        }
+        let mut events = self.events
+            .with(|queue| ::std::mem::replace(&mut queue.events, Vec::new()))
+            .unwrap_or_default();
+        if let Some(handle) = self.handle() {
+            events.extend(handle.poll_repeat(now).into_iter().map(KeyboardHandlerEvent::Key));
+        }
+        self.options.overflow_policy.apply(&mut events, self.options.max_queued_events);
+        events
+    }
+
+    fn push(&self, event: KeyboardHandlerEvent) {
+        self.events.with(|queue| queue.push(event));
+    }
+
+    fn remove(&mut self) {
+        // dropping the guard releases the keyboard
+        self.keyboard = None;
+        self.push(KeyboardHandlerEvent::Removed);
+    }
+}
+
+fn handler_implementation() -> MappedKeyboardImplementation<Shared<PendingEvents>> {
+    MappedKeyboardImplementation {
+        enter: |_, events, _, _, serial, surface, modifiers, _, _| {
+            if let Some(surface) = surface.clone() {
+                events.with(|queue| {
+                    queue.push(KeyboardHandlerEvent::Enter {
+                        serial: serial,
+                        surface: surface,
+                        modifiers: modifiers,
+                    })
+                });
+            }
+        },
+        leave: |_, events, _, _, serial, surface| {
+            if let Some(surface) = surface.clone() {
+                events.with(|queue| {
+                    queue.push(KeyboardHandlerEvent::Leave {
+                        serial: serial,
+                        surface: surface,
+                    })
+                });
+            }
+        },
+        key: |_, events, _, _, event| {
+            events.with(|queue| queue.push(KeyboardHandlerEvent::Key(event)));
+        },
+        compose: |_, events, _, _, event| {
+            events.with(|queue| queue.push(KeyboardHandlerEvent::Compose(event)));
+        },
+        ..Default::default()
+    }
+}
@@ -0,0 +1,108 @@
+/// The active layout of a keyboard, as shown by panels and status bars
+///
+/// It is obtained from `KeyboardHandle::layout_indicator()`, and changes are notified
+/// through the `layout_indicator` callback.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct LayoutIndicator {
+    /// The index of the active layout
+    pub index: u32,
+    /// A short name for the layout, like "us" or "de"
+    pub short_name: String,
+    /// The name of the layout in the keymap, like "English (US)"
+    pub name: String,
+}
+
+#[cfg(feature = "serde")]
+serde_struct!(LayoutIndicator { index, short_name, name });
+
+// short names of the layout names used by xkeyboard-config, matched exactly first
+const LAYOUT_SHORT_NAMES: &'static [(&'static str, &'static str)] = &[
+    ("English (US)", "us"),
+    ("English (UK)", "gb"),
+    ("English (Australian)", "au"),
+    ("English (Canada)", "ca"),
+    ("French (Canada)", "ca"),
+    ("French (Switzerland)", "ch"),
+    ("German (Switzerland)", "ch"),
+    ("German (Austria)", "at"),
+    ("Belgian", "be"),
+    ("Portuguese (Brazil)", "br"),
+    ("Spanish (Latin American)", "latam"),
+    ("Irish", "ie"),
+];
+
+// short names of the languages, for the other layouts and their variants
+const LANGUAGE_SHORT_NAMES: &'static [(&'static str, &'static str)] = &[
+    ("Albanian", "al"),
+    ("Arabic", "ara"),
+    ("Armenian", "am"),
+    ("Belarusian", "by"),
+    ("Bulgarian", "bg"),
+    ("Croatian", "hr"),
+    ("Czech", "cz"),
+    ("Danish", "dk"),
+    ("Dutch", "nl"),
+    ("English", "us"),
+    ("Estonian", "ee"),
+    ("Finnish", "fi"),
+    ("French", "fr"),
+    ("Georgian", "ge"),
+    ("German", "de"),
+    ("Greek", "gr"),
+    ("Hebrew", "il"),
+    ("Hindi", "in"),
+    ("Hungarian", "hu"),
+    ("Icelandic", "is"),
+    ("Italian", "it"),
+    ("Japanese", "jp"),
+    ("Kazakh", "kz"),
+    ("Korean", "kr"),
+    ("Latvian", "lv"),
+    ("Lithuanian", "lt"),
+    ("Macedonian", "mk"),
+    ("Norwegian", "no"),
+    ("Persian", "ir"),
+    ("Polish", "pl"),
+    ("Portuguese", "pt"),
+    ("Romanian", "ro"),
+    ("Russian", "ru"),
+    ("Serbian", "rs"),
+    ("Slovak", "sk"),
+    ("Slovenian", "si"),
+    ("Spanish", "es"),
+    ("Swedish", "se"),
+    ("Thai", "th"),
+    ("Turkish", "tr"),
+    ("Ukrainian", "ua"),
+    ("Vietnamese", "vn"),
+];
+
+/// A short name for a layout name of the keymap, like "us" for "English (US)"
+///
+/// Unknown layouts are shortened to the first two letters of their name.
+pub fn short_name(name: &str) -> String {
+    if let Some(&(_, short)) = LAYOUT_SHORT_NAMES.iter().find(|&&(n, _)| n == name) {
+        return short.to_owned();
+    }
+    // variants are named like "German (no dead keys)"
+    let language = name.split(" (").next().unwrap_or(name);
+    if let Some(&(_, short)) = LANGUAGE_SHORT_NAMES.iter().find(|&&(n, _)| n == language) {
+        return short.to_owned();
+    }
+    language
+        .chars()
+        .filter(|c| c.is_alphabetic())
+        .take(2)
+        .flat_map(|c| c.to_lowercase())
+        .collect()
+}
+
+/// The short name of a layout of a RMLVO, like "de" for the second layout of "us,de"
+pub fn rmlvo_short_name(layouts: &str, index: u32) -> Option<String> {
+    layouts
+        .split(',')
+        .nth(index as usize)
+        .map(|layout| layout.trim())
+        .filter(|layout| !layout.is_empty())
+        .map(|layout| layout.to_owned())
+}
@@ -0,0 +1,517 @@
+use ffi::{self, keysyms, xkb_keymap, xkb_state};
+use ffi::XKBCOMMON_HANDLE as XKBH;
+use keysym_names;
+use mapped_keyboard::ModifiersState;
+use std::collections::{BTreeMap, HashMap};
+use std::ffi::CStr;
+use std::os::raw::c_char;
+use std::ptr;
+use std::slice;
+
+/// The largest raw keycode that can be translated
+///
+/// Raw keycodes are evdev keycodes, offset by 8 in XKB keymaps. libxkbcommon supports
+/// 32-bit keycodes, so this is far beyond the 8..255 range of X11. Larger keycodes
+/// produce no keysym.
+pub const MAX_KEYCODE: u32 = ffi::XKB_KEYCODE_MAX - 8;
+
+/// Convert a raw keycode to the XKB keycode, `XKB_KEYCODE_INVALID` if out of range
+pub fn to_xkb_keycode(keycode: u32) -> u32 {
+    if keycode > MAX_KEYCODE {
+        ffi::XKB_KEYCODE_INVALID
+    } else {
+        keycode + 8
+    }
+}
+
+/// The range of raw keycodes of a keymap, `None` if it has no key with a raw keycode
+pub unsafe fn keycode_range(keymap: *mut xkb_keymap) -> Option<(u32, u32)> {
+    let min = (XKBH.xkb_keymap_min_keycode)(keymap);
+    let max = (XKBH.xkb_keymap_max_keycode)(keymap);
+    // keycodes below 8 have no evdev equivalent
+    if max < 8 || max < min {
+        return None;
+    }
+    Some((::std::cmp::max(min, 8) - 8, max - 8))
+}
+
+/// The position of a keysym in a keymap
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct KeyLocation {
+    /// The raw keycode of the key, as in the `rawkey` argument of the `key` callback
+    pub keycode: u32,
+    /// The index of the layout
+    pub layout: u32,
+    /// The shift level within the layout
+    pub level: u32,
+}
+
+#[cfg(feature = "serde")]
+serde_struct!(KeyLocation { keycode, layout, level });
+
+/// How to type a character with the current keymap, see `KeyboardHandle::can_type()`
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct KeyCombo {
+    /// The keysym producing the character
+    pub keysym: u32,
+    /// The key to press, with the layout and shift level producing the keysym
+    pub location: KeyLocation,
+    /// The modifiers to hold while pressing the key
+    pub mods: ModifiersState,
+    /// Whether AltGr must be held, the `Mod5` modifier of the usual keymaps
+    pub alt_gr: bool,
+    /// The modifiers to hold, as a mask of the modifiers of the keymap
+    ///
+    /// This is the format of `SerializedState::mods_depressed`, for virtual input.
+    pub mods_mask: u32,
+    /// Whether the layout of the key is not the one it is translated with, so that
+    /// the layout must be switched first
+    pub other_layout: bool,
+    /// The keysym of the key without modifiers, to label it in shortcuts
+    ///
+    /// For example `XKB_KEY_7` for the slash of a German layout, typed with Shift+7.
+    /// It is 0 (`XKB_KEY_NoSymbol`) if the first shift level of the key has no keysym.
+    pub base_keysym: u32,
+}
+
+/// A description of a whole keymap, independent of xkbcommon
+///
+/// With the `serde` feature, it can be serialized, for example to be sent to
+/// a remote peer or stored for snapshot tests.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct KeymapDescription {
+    /// The names of the layouts
+    pub layouts: Vec<String>,
+    /// The keys, sorted by keycode
+    pub keys: Vec<KeyDescription>,
+    /// The names of all the keysyms appearing in `keys`
+    pub keysym_names: BTreeMap<u32, String>,
+}
+
+#[cfg(feature = "serde")]
+serde_struct!(KeymapDescription { layouts, keys, keysym_names });
+
+/// The keysyms produced by a key
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct KeyDescription {
+    /// The raw keycode of the key, as in the `rawkey` argument of the `key` callback
+    pub keycode: u32,
+    /// The keysyms of the key, indexed by layout then by shift level
+    ///
+    /// Most levels produce a single keysym, but some produce none or several.
+    pub layouts: Vec<Vec<Vec<u32>>>,
+}
+
+#[cfg(feature = "serde")]
+serde_struct!(KeyDescription { keycode, layouts });
+
+/// Iterate over all the levels of a keymap, with their keysyms
+///
+/// `f` is called for every level of every layout of every key, in this order.
+pub unsafe fn for_each_level<F: FnMut(KeyLocation, &[u32])>(keymap: *mut xkb_keymap, mut f: F) {
+    let min = (XKBH.xkb_keymap_min_keycode)(keymap);
+    let max = (XKBH.xkb_keymap_max_keycode)(keymap);
+    // keycodes below 8 have no evdev equivalent
+    for xkb_keycode in ::std::cmp::max(min, 8)..max.saturating_add(1) {
+        let layouts = (XKBH.xkb_keymap_num_layouts_for_key)(keymap, xkb_keycode);
+        for layout in 0..layouts {
+            let levels = (XKBH.xkb_keymap_num_levels_for_key)(keymap, xkb_keycode, layout);
+            for level in 0..levels {
+                let mut syms: *const ffi::xkb_keysym_t = ptr::null();
+                let count =
+                    (XKBH.xkb_keymap_key_get_syms_by_level)(keymap, xkb_keycode, layout, level, &mut syms);
+                let location = KeyLocation {
+                    keycode: xkb_keycode - 8,
+                    layout: layout,
+                    level: level,
+                };
+                if count <= 0 || syms.is_null() {
+                    f(location, &[]);
+                } else {
+                    f(location, slice::from_raw_parts(syms, count as usize));
+                }
+            }
+        }
+    }
+}
+
+/// The keysyms producible with the effective layout of a state, sorted and deduplicated
+///
+/// Each key is taken in the layout it is translated with, which is not the effective
+/// layout for keys that do not define it.
+pub unsafe fn layout_keysyms(keymap: *mut xkb_keymap, state: *mut xkb_state) -> Vec<u32> {
+    let mut keysyms = Vec::new();
+    let min = (XKBH.xkb_keymap_min_keycode)(keymap);
+    let max = (XKBH.xkb_keymap_max_keycode)(keymap);
+    for xkb_keycode in ::std::cmp::max(min, 8)..max.saturating_add(1) {
+        let layout = (XKBH.xkb_state_key_get_layout)(state, xkb_keycode);
+        if layout == ffi::XKB_LAYOUT_INVALID {
+            continue;
+        }
+        let levels = (XKBH.xkb_keymap_num_levels_for_key)(keymap, xkb_keycode, layout);
+        for level in 0..levels {
+            let mut syms: *const ffi::xkb_keysym_t = ptr::null();
+            let count =
+                (XKBH.xkb_keymap_key_get_syms_by_level)(keymap, xkb_keycode, layout, level, &mut syms);
+            if count > 0 && !syms.is_null() {
+                keysyms.extend_from_slice(slice::from_raw_parts(syms, count as usize));
+            }
+        }
+    }
+    keysyms.sort();
+    keysyms.dedup();
+    keysyms
+}
+
+/// The first keysym of a shift level of a key, `None` if it has none
+pub unsafe fn level_keysym(keymap: *mut xkb_keymap, xkb_keycode: u32, layout: u32, level: u32)
+                           -> Option<u32> {
+    let mut syms: *const ffi::xkb_keysym_t = ptr::null();
+    let count = (XKBH.xkb_keymap_key_get_syms_by_level)(keymap, xkb_keycode, layout, level, &mut syms);
+    if count <= 0 || syms.is_null() {
+        None
+    } else {
+        Some(*syms)
+    }
+}
+
+/// The masks of the modifiers selecting a shift level, `None` if they cannot be known
+///
+/// Before libxkbcommon 1.0, only the first level (no modifier) and the second one
+/// (Shift) are known.
+pub unsafe fn level_mods(keymap: *mut xkb_keymap, xkb_keycode: u32, layout: u32, level: u32)
+                         -> Option<Vec<u32>> {
+    if let Some(ref lib) = *ffi::XKBCOMMON_MODS_FOR_LEVEL_OPTION {
+        let mut masks = [0; 16];
+        let count = (lib.xkb_keymap_key_get_mods_for_level)(
+            keymap,
+            xkb_keycode,
+            layout,
+            level,
+            masks.as_mut_ptr(),
+            masks.len(),
+        );
+        return Some(masks[..::std::cmp::min(count, masks.len())].to_vec());
+    }
+    match level {
+        0 => Some(vec![0]),
+        1 => {
+            let name = ffi::XKB_MOD_NAME_SHIFT.as_ptr() as *const c_char;
+            let shift = (XKBH.xkb_keymap_mod_get_index)(keymap, name);
+            if shift < 32 {
+                Some(vec![1 << shift])
+            } else {
+                None
+            }
+        }
+        _ => None,
+    }
+}
+
+/// The keysyms that may produce a character, the most specific first
+///
+/// Latin-1 characters have keysyms of the same value, and any character can be
+/// produced by its Unicode keysym, but most keymaps use the legacy keysyms, which
+/// only libxkbcommon 1.0 and later can find.
+pub fn char_keysyms(c: char) -> Vec<u32> {
+    let mut candidates = Vec::new();
+    let special = match c {
+        '\u{8}' => Some(keysyms::XKB_KEY_BackSpace),
+        '\t' => Some(keysyms::XKB_KEY_Tab),
+        '\n' | '\r' => Some(keysyms::XKB_KEY_Return),
+        '\u{1b}' => Some(keysyms::XKB_KEY_Escape),
+        '\u{7f}' => Some(keysyms::XKB_KEY_Delete),
+        _ => None,
+    };
+    if let Some(keysym) = special {
+        return vec![keysym];
+    }
+    if let Some(ref lib) = *ffi::XKBCOMMON_UTF32_OPTION {
+        match unsafe { (lib.xkb_utf32_to_keysym)(c as u32) } {
+            // XKB_KEY_NoSymbol
+            0 => {}
+            keysym => candidates.push(keysym),
+        }
+    }
+    let code = c as u32;
+    if (code >= 0x20 && code <= 0x7e) || (code >= 0xa0 && code <= 0xff) {
+        candidates.push(code);
+    }
+    candidates.push(0x0100_0000 | code);
+    candidates.dedup();
+    candidates
+}
+
+/// The printable character of a keysym, if it has one
+///
+/// Returns `None` if libxkbcommon could not be loaded.
+pub fn keysym_char(keysym: u32) -> Option<char> {
+    let xkbh = match ffi::XKBCOMMON_OPTION.as_ref() {
+        Some(xkbh) => xkbh,
+        None => return None,
+    };
+    match ::std::char::from_u32(unsafe { (xkbh.xkb_keysym_to_utf32)(keysym) }) {
+        Some(c) if c != '\0' && !c.is_control() => Some(c),
+        _ => None,
+    }
+}
+
+/// A map from keysyms to the keys producing them
+pub struct ReverseIndex {
+    map: HashMap<u32, Vec<KeyLocation>>,
+}
+
+impl ReverseIndex {
+    pub unsafe fn build(keymap: *mut xkb_keymap) -> ReverseIndex {
+        let mut map: HashMap<u32, Vec<KeyLocation>> = HashMap::new();
+        for_each_level(keymap, |location, syms| {
+            for &sym in syms {
+                map.entry(sym).or_insert_with(Vec::new).push(location)
+            }
+        });
+        ReverseIndex { map: map }
+    }
+
+    /// The locations producing `keysym`, sorted by keycode, layout and level
+    pub fn lookup(&self, keysym: u32) -> &[KeyLocation] {
+        self.map.get(&keysym).map_or(&[], |v| &v[..])
+    }
+
+    /// Whether some location produces `keysym`
+    pub fn contains(&self, keysym: u32) -> bool {
+        self.map.contains_key(&keysym)
+    }
+}
+
+/// Extract the description of a keymap
+pub unsafe fn describe(keymap: *mut xkb_keymap, layouts: Vec<String>) -> KeymapDescription {
+    let mut keys: Vec<KeyDescription> = Vec::new();
+    let mut keysym_names = BTreeMap::new();
+    for_each_level(keymap, |location, syms| {
+        for &sym in syms {
+            if !keysym_names.contains_key(&sym) {
+                if let Some(name) = keysym_name(sym) {
+                    keysym_names.insert(sym, name);
+                }
+            }
+        }
+        if keys.last().map(|k| k.keycode) != Some(location.keycode) {
+            keys.push(KeyDescription {
+                keycode: location.keycode,
+                layouts: Vec::new(),
+            });
+        }
+        if let Some(key) = keys.last_mut() {
+            while key.layouts.len() <= location.layout as usize {
+                key.layouts.push(Vec::new());
+            }
+            key.layouts[location.layout as usize].push(syms.to_vec());
+        }
+    });
+    KeymapDescription {
+        layouts: layouts,
+        keys: keys,
+        keysym_names: keysym_names,
+    }
+}
+
+/// A summary of the differences between two keymaps
+///
+/// Keymaps compiled by the compositor do not carry their RMLVO, so changed options
+/// show up as changed keys.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct KeymapChange {
+    /// The names of the layouts of the new keymap
+    pub layouts: Vec<String>,
+    /// The layouts of the new keymap that were not in the previous one
+    pub added_layouts: Vec<String>,
+    /// The layouts of the previous keymap that are not in the new one
+    pub removed_layouts: Vec<String>,
+    /// The raw keycodes of the keys whose keysyms changed, sorted
+    pub changed_keys: Vec<u32>,
+}
+
+#[cfg(feature = "serde")]
+serde_struct!(KeymapChange { layouts, added_layouts, removed_layouts, changed_keys });
+
+impl KeymapChange {
+    /// Compute the changes from a keymap to another
+    pub fn between(previous: &KeymapDescription, new: &KeymapDescription) -> KeymapChange {
+        let mut keys: BTreeMap<u32, (Option<&KeyDescription>, Option<&KeyDescription>)> = BTreeMap::new();
+        for key in &previous.keys {
+            keys.entry(key.keycode).or_insert((None, None)).0 = Some(key);
+        }
+        for key in &new.keys {
+            keys.entry(key.keycode).or_insert((None, None)).1 = Some(key);
+        }
+        KeymapChange {
+            layouts: new.layouts.clone(),
+            added_layouts: new
+                .layouts
+                .iter()
+                .filter(|l| !previous.layouts.contains(l))
+                .cloned()
+                .collect(),
+            removed_layouts: previous
+                .layouts
+                .iter()
+                .filter(|l| !new.layouts.contains(l))
+                .cloned()
+                .collect(),
+            changed_keys: keys
+                .into_iter()
+                .filter(|&(_, (old, new))| old != new)
+                .map(|(keycode, _)| keycode)
+                .collect(),
+        }
+    }
+
+    /// Whether the keymaps are the same
+    pub fn is_empty(&self) -> bool {
+        self.added_layouts.is_empty() && self.removed_layouts.is_empty() && self.changed_keys.is_empty()
+    }
+}
+
+/// Get the name of a keysym, as used in keymaps
+///
+/// The built-in table is used first, libxkbcommon then names the other keysyms,
+/// like Unicode ones.
+pub fn keysym_name(keysym: u32) -> Option<String> {
+    if let Some(name) = keysym_names::keysym_name(keysym) {
+        return Some(name.to_owned());
+    }
+    let xkbh = match ffi::XKBCOMMON_OPTION.as_ref() {
+        Some(xkbh) => xkbh,
+        None => return None,
+    };
+    let mut buffer = [0u8; 64];
+    let len = unsafe { (xkbh.xkb_keysym_get_name)(keysym, buffer.as_mut_ptr() as *mut c_char, buffer.len()) };
+    if len <= 0 {
+        return None;
+    }
+    let len = ::std::cmp::min(len as usize, buffer.len() - 1);
+    String::from_utf8(buffer[..len].to_vec()).ok()
+}
+
+/// The legends of the keys of a layout, for on-screen keyboards
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LayoutLegends {
+    /// The name of the layout
+    pub name: String,
+    /// The keys of the layout, sorted by keycode
+    pub keys: Vec<KeyLegend>,
+}
+
+#[cfg(feature = "serde")]
+serde_struct!(LayoutLegends { name, keys });
+
+/// The legends of a key, at the usual shift levels
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct KeyLegend {
+    /// The raw keycode of the key, as in the `rawkey` argument of the `key` callback
+    pub keycode: u32,
+    /// The XKB name of the key, like `"AD01"`
+    pub name: Option<String>,
+    /// The legend without modifiers
+    pub base: LevelLegend,
+    /// The legend with Shift
+    pub shift: LevelLegend,
+    /// The legend with AltGr, if it differs from `base`
+    pub altgr: Option<LevelLegend>,
+    /// The legend with Shift and AltGr, if it differs from `shift`
+    pub shift_altgr: Option<LevelLegend>,
+}
+
+#[cfg(feature = "serde")]
+serde_struct!(KeyLegend { keycode, name, base, shift, altgr, shift_altgr });
+
+/// The legend of a key at one shift level
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LevelLegend {
+    /// The keysym produced at this level
+    pub keysym: u32,
+    /// The printable text produced at this level
+    ///
+    /// `None` for keys producing no text or control characters, like Return or
+    /// the arrows, which are better labelled from their keysym.
+    pub text: Option<String>,
+}
+
+#[cfg(feature = "serde")]
+serde_struct!(LevelLegend { keysym, text });
+
+unsafe fn level_legend(state: *mut xkb_state, xkb_keycode: u32) -> LevelLegend {
+    let keysym = (XKBH.xkb_state_key_get_one_sym)(state, xkb_keycode);
+    let mut buffer = [0u8; 64];
+    let buffer_ptr = buffer.as_mut_ptr() as *mut c_char;
+    let len = (XKBH.xkb_state_key_get_utf8)(state, xkb_keycode, buffer_ptr, buffer.len());
+    let text = match CStr::from_ptr(buffer.as_ptr() as *const c_char).to_str() {
+        Ok(text) if len > 0 && !text.chars().any(|c| c.is_control()) => Some(text.to_owned()),
+        _ => None,
+    };
+    LevelLegend {
+        keysym: keysym,
+        text: text,
+    }
+}
+
+/// Extract the legends of all keys, for every layout
+pub unsafe fn legends(keymap: *mut xkb_keymap, layout_names: Vec<String>) -> Vec<LayoutLegends> {
+    let shift = (XKBH.xkb_keymap_mod_get_index)(keymap, ffi::XKB_MOD_NAME_SHIFT.as_ptr() as *const c_char);
+    let shift = if shift < 32 { 1 << shift } else { 0 };
+    let altgr = (XKBH.xkb_keymap_mod_get_index)(keymap, ffi::XKB_MOD_NAME_MOD5.as_ptr() as *const c_char);
+    let altgr = if altgr < 32 { 1 << altgr } else { 0 };
+
+    let state = xkb_acquire!(State, (XKBH.xkb_state_new)(keymap));
+    if state.is_null() {
+        return Vec::new();
+    }
+    let min = ::std::cmp::max((XKBH.xkb_keymap_min_keycode)(keymap), 8);
+    let max = (XKBH.xkb_keymap_max_keycode)(keymap);
+    let mut layouts = Vec::new();
+    for (layout, name) in layout_names.into_iter().enumerate() {
+        let layout = layout as u32;
+        let mut keys = Vec::new();
+        for xkb_keycode in min..max.saturating_add(1) {
+            if (XKBH.xkb_keymap_num_layouts_for_key)(keymap, xkb_keycode) == 0 {
+                continue;
+            }
+            let legend_with = |mods: u32| {
+                (XKBH.xkb_state_update_mask)(state, mods, 0, 0, 0, 0, layout);
+                level_legend(state, xkb_keycode)
+            };
+            let base = legend_with(0);
+            let shift_legend = legend_with(shift);
+            let (mut altgr_legend, mut shift_altgr_legend) = (None, None);
+            if altgr != 0 {
+                let legend = legend_with(altgr);
+                if legend.keysym != base.keysym {
+                    altgr_legend = Some(legend);
+                }
+                let legend = legend_with(shift | altgr);
+                if legend.keysym != shift_legend.keysym {
+                    shift_altgr_legend = Some(legend);
+                }
+            }
+            let key_name = (XKBH.xkb_keymap_key_get_name)(keymap, xkb_keycode);
+            keys.push(KeyLegend {
+                keycode: xkb_keycode - 8,
+                name: if key_name.is_null() {
+                    None
+                } else {
+                    Some(CStr::from_ptr(key_name).to_string_lossy().into_owned())
+                },
+                base: base,
+                shift: shift_legend,
+                altgr: altgr_legend,
+                shift_altgr: shift_altgr_legend,
+            });
+        }
+        layouts.push(LayoutLegends {
+            name: name,
+            keys: keys,
+        });
+    }
+    xkb_release!(State, XKBH.xkb_state_unref, state);
+    layouts
+}
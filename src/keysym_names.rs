@@ -0,0 +1,89 @@
+// Generated by build.rs from src/ffi/keysyms.rs
+include!(concat!(env!("OUT_DIR"), "/keysym_names.rs"));
+
+/// Get the name of a keysym, like `"a"` or `"Return"`
+///
+/// This uses a table built into the crate, so it works without libxkbcommon and
+/// does not allocate. Keysyms with several names get their canonical one. Returns
+/// `None` for keysyms without a name, like most Unicode keysyms.
+pub fn keysym_name(keysym: u32) -> Option<&'static str> {
+    KEYSYMS_BY_VALUE
+        .binary_search_by_key(&keysym, |&(value, _)| value)
+        .ok()
+        .map(|idx| KEYSYMS_BY_VALUE[idx].1)
+}
+
+/// Get a keysym from its name, like `"a"` or `"Return"`
+///
+/// The name is case-sensitive. Like `keysym_name()`, this works without libxkbcommon.
+pub fn keysym_from_name(name: &str) -> Option<u32> {
+    KEYSYMS_BY_NAME
+        .binary_search_by(|&(n, _)| n.cmp(name))
+        .ok()
+        .map(|idx| KEYSYMS_BY_NAME[idx].1)
+}
+
+/// How a keysym name matched a search, from the best to the worst
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum KeysymMatchKind {
+    /// The name is the query
+    Exact,
+    /// The name starts with the query
+    Prefix,
+    /// The name contains the query
+    Substring,
+    /// The name contains the characters of the query, in order
+    Fuzzy,
+}
+
+/// A keysym found by `search_keysyms()`
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct KeysymCandidate {
+    /// The name that matched, which may be an alias of the keysym
+    pub name: &'static str,
+    /// The keysym
+    pub keysym: u32,
+    /// How the name matched
+    pub kind: KeysymMatchKind,
+}
+
+fn is_subsequence(query: &str, name: &str) -> bool {
+    let mut name = name.chars();
+    query.chars().all(|q| name.any(|c| c == q))
+}
+
+/// Search keysyms by name, ignoring case
+///
+/// This is meant for binding dialogs where the user can type the name of a key.
+/// The candidates are sorted from the best match to the worst, then from the
+/// shortest name to the longest.
+pub fn search_keysyms(query: &str) -> Vec<KeysymCandidate> {
+    let query = query.to_lowercase();
+    if query.is_empty() {
+        return Vec::new();
+    }
+    let mut candidates: Vec<KeysymCandidate> = KEYSYMS_BY_NAME
+        .iter()
+        .filter_map(|&(name, keysym)| {
+            let lower = name.to_lowercase();
+            let kind = if lower == query {
+                KeysymMatchKind::Exact
+            } else if lower.starts_with(&query[..]) {
+                KeysymMatchKind::Prefix
+            } else if lower.contains(&query[..]) {
+                KeysymMatchKind::Substring
+            } else if is_subsequence(&query, &lower) {
+                KeysymMatchKind::Fuzzy
+            } else {
+                return None;
+            };
+            Some(KeysymCandidate {
+                name: name,
+                keysym: keysym,
+                kind: kind,
+            })
+        })
+        .collect();
+    candidates.sort_by(|a, b| (a.kind, a.name.len(), a.name).cmp(&(b.kind, b.name.len(), b.name)));
+    candidates
+}
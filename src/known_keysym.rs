@@ -0,0 +1,37 @@
+// Generated by build.rs from src/ffi/keysyms.rs
+include!(concat!(env!("OUT_DIR"), "/known_keysym.rs"));
+
+impl KnownKeysym {
+    /// Get the variant of a raw keysym
+    ///
+    /// Keysyms with a variant never give `Other`, so values built with this function
+    /// can be compared and hashed.
+    pub fn from_raw(keysym: u32) -> KnownKeysym {
+        known_from_raw(keysym)
+    }
+
+    /// Get the raw keysym of this variant
+    pub fn raw(self) -> u32 {
+        known_to_raw(self)
+    }
+
+    /// Whether this keysym has a variant
+    pub fn is_known(self) -> bool {
+        match self {
+            KnownKeysym::Other(_) => false,
+            _ => true,
+        }
+    }
+}
+
+impl From<u32> for KnownKeysym {
+    fn from(keysym: u32) -> KnownKeysym {
+        KnownKeysym::from_raw(keysym)
+    }
+}
+
+impl From<KnownKeysym> for u32 {
+    fn from(keysym: KnownKeysym) -> u32 {
+        keysym.raw()
+    }
+}
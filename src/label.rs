@@ -0,0 +1,189 @@
+use ffi::keysyms;
+use keymap;
+use keysym_names::keysym_name;
+use std::env;
+
+/// How shortcut labels are written, see `Shortcut::label()`
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum LabelStyle {
+    /// English names joined by `+`, like "Ctrl+Alt+T"
+    Plain,
+    /// The symbols of the modifier keys, like "⌃⌥T"
+    Symbolic,
+    /// Localized names joined by `+`, like "Strg+Alt+T"
+    Localized(ModifierNames),
+}
+
+impl Default for LabelStyle {
+    fn default() -> LabelStyle {
+        LabelStyle::Plain
+    }
+}
+
+/// The names of the modifiers in `LabelStyle::Localized` labels
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct ModifierNames {
+    /// The name of the "control" modifier
+    pub ctrl: String,
+    /// The name of the "alt" modifier
+    pub alt: String,
+    /// The name of the "shift" modifier
+    pub shift: String,
+    /// The name of the "logo" modifier
+    pub logo: String,
+    /// The name of the AltGr key, selecting the third shift level
+    pub alt_gr: String,
+}
+
+impl Default for ModifierNames {
+    fn default() -> ModifierNames {
+        ModifierNames::from_static(&PLAIN_NAMES)
+    }
+}
+
+// ctrl, alt, shift, logo, alt_gr
+type Names = (&'static str, &'static str, &'static str, &'static str, &'static str);
+
+const PLAIN_NAMES: Names = ("Ctrl", "Alt", "Shift", "Super", "AltGr");
+const SYMBOLS: Names = ("⌃", "⌥", "⇧", "⌘", "⌥");
+
+// the names printed on the keyboards sold for these languages
+const LANGUAGE_NAMES: &'static [(&'static str, Names)] = &[
+    ("da", ("Ctrl", "Alt", "Skift", "Super", "Alt Gr")),
+    ("de", ("Strg", "Alt", "Umschalt", "Super", "Alt Gr")),
+    ("es", ("Ctrl", "Alt", "Mayús", "Super", "Alt Gr")),
+    ("fi", ("Ctrl", "Alt", "Vaihto", "Super", "Alt Gr")),
+    ("fr", ("Ctrl", "Alt", "Maj", "Super", "Alt Gr")),
+    ("it", ("Ctrl", "Alt", "Maiusc", "Super", "Alt Gr")),
+    ("nb", ("Ctrl", "Alt", "Skift", "Super", "Alt Gr")),
+    ("nl", ("Ctrl", "Alt", "Shift", "Super", "Alt Gr")),
+    ("pt", ("Ctrl", "Alt", "Shift", "Super", "Alt Gr")),
+    ("sv", ("Ctrl", "Alt", "Skift", "Super", "Alt Gr")),
+];
+
+impl ModifierNames {
+    fn from_static(names: &Names) -> ModifierNames {
+        ModifierNames {
+            ctrl: names.0.to_owned(),
+            alt: names.1.to_owned(),
+            shift: names.2.to_owned(),
+            logo: names.3.to_owned(),
+            alt_gr: names.4.to_owned(),
+        }
+    }
+
+    /// The names of the modifiers for a locale, like `de_DE.UTF-8`
+    ///
+    /// Unknown languages get the English names.
+    pub fn for_locale(locale: &str) -> ModifierNames {
+        let language = locale.split(|c| c == '_' || c == '.' || c == '@').next().unwrap_or("");
+        match LANGUAGE_NAMES.iter().find(|&&(lang, _)| lang == language) {
+            Some(&(_, ref names)) => ModifierNames::from_static(names),
+            None => ModifierNames::default(),
+        }
+    }
+
+    /// The names of the modifiers for the locale of the environment
+    ///
+    /// The locale is read from the first non-empty of `LC_ALL`, `LC_MESSAGES` and `LANG`.
+    pub fn from_env() -> ModifierNames {
+        ["LC_ALL", "LC_MESSAGES", "LANG"]
+            .iter()
+            .filter_map(|var| env::var(var).ok())
+            .find(|locale| !locale.is_empty())
+            .map_or_else(ModifierNames::default, |locale| ModifierNames::for_locale(&locale))
+    }
+}
+
+/// The modifiers of a label
+#[derive(Copy, Clone, Default)]
+pub struct LabelMods {
+    pub ctrl: bool,
+    pub alt: bool,
+    pub shift: bool,
+    pub logo: bool,
+    pub alt_gr: bool,
+}
+
+// keys better known by another name than their keysym, and their symbols
+const KEY_NAMES: &'static [(u32, &'static str, &'static str)] = &[
+    (keysyms::XKB_KEY_Return, "Enter", "↩"),
+    (keysyms::XKB_KEY_KP_Enter, "Enter", "⌤"),
+    (keysyms::XKB_KEY_Escape, "Esc", "⎋"),
+    (keysyms::XKB_KEY_space, "Space", "␣"),
+    (keysyms::XKB_KEY_BackSpace, "Backspace", "⌫"),
+    (keysyms::XKB_KEY_Delete, "Del", "⌦"),
+    (keysyms::XKB_KEY_Insert, "Ins", "Ins"),
+    (keysyms::XKB_KEY_Tab, "Tab", "⇥"),
+    (keysyms::XKB_KEY_ISO_Left_Tab, "Tab", "⇥"),
+    (keysyms::XKB_KEY_Up, "Up", "↑"),
+    (keysyms::XKB_KEY_Down, "Down", "↓"),
+    (keysyms::XKB_KEY_Left, "Left", "←"),
+    (keysyms::XKB_KEY_Right, "Right", "→"),
+    (keysyms::XKB_KEY_Home, "Home", "↖"),
+    (keysyms::XKB_KEY_End, "End", "↘"),
+    (keysyms::XKB_KEY_Prior, "PgUp", "⇞"),
+    (keysyms::XKB_KEY_Next, "PgDn", "⇟"),
+];
+
+/// The label of the key producing a keysym, like "T" or "Enter"
+pub fn key_label(keysym: u32, style: &LabelStyle) -> String {
+    if let Some(&(_, name, symbol)) = KEY_NAMES.iter().find(|&&(sym, _, _)| sym == keysym) {
+        return match *style {
+            LabelStyle::Symbolic => symbol.to_owned(),
+            _ => name.to_owned(),
+        };
+    }
+    // latin-1 keysyms are their code point, which also works without libxkbcommon
+    let c = if (0x21 <= keysym && keysym <= 0x7e) || (0xa1 <= keysym && keysym <= 0xff) {
+        ::std::char::from_u32(keysym)
+    } else {
+        keymap::keysym_char(keysym)
+    };
+    match c {
+        Some(c) if !c.is_whitespace() => c.to_uppercase().collect(),
+        _ => keysym_name(keysym).map_or_else(|| format!("{:#x}", keysym), |name| name.to_owned()),
+    }
+}
+
+/// A label made of modifiers and a key
+pub fn label(mods: LabelMods, key: &str, style: &LabelStyle) -> String {
+    let plain;
+    let names = match *style {
+        LabelStyle::Plain => {
+            plain = ModifierNames::default();
+            &plain
+        }
+        LabelStyle::Symbolic => {
+            // macOS order, without separators
+            let mut label = String::new();
+            for &(active, symbol) in &[
+                (mods.ctrl, SYMBOLS.0),
+                (mods.alt || mods.alt_gr, SYMBOLS.1),
+                (mods.shift, SYMBOLS.2),
+                (mods.logo, SYMBOLS.3),
+            ] {
+                if active {
+                    label.push_str(symbol);
+                }
+            }
+            label.push_str(key);
+            return label;
+        }
+        LabelStyle::Localized(ref names) => names,
+    };
+    let mut parts: Vec<&str> = Vec::new();
+    for &(active, name) in &[
+        (mods.ctrl, &names.ctrl),
+        (mods.alt, &names.alt),
+        (mods.shift, &names.shift),
+        (mods.logo, &names.logo),
+        (mods.alt_gr, &names.alt_gr),
+    ] {
+        if active {
+            parts.push(name);
+        }
+    }
+    parts.push(key);
+    parts.join("+")
+}
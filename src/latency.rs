@@ -0,0 +1,58 @@
+use std::os::raw::{c_int, c_long};
+use std::time::Duration;
+
+/// The latency of a key event, reported to `KeyboardOptions::latency_hook`
+///
+/// It measures the path of the event from the compositor to the end of the callbacks,
+/// to quantify input latency end to end.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct KeyLatency {
+    /// The serial of the key event
+    pub serial: u32,
+    /// The timestamp of the key event, as sent by the compositor
+    pub time: u32,
+    /// The raw evdev keycode of the key
+    pub rawkey: u32,
+    /// The delay between the timestamp of the compositor and the receipt of the event
+    ///
+    /// `None` if the timestamps of the compositor are not in the `CLOCK_MONOTONIC` time
+    /// base, as the protocol does not require it. It has a millisecond resolution.
+    pub delivery: Option<Duration>,
+    /// The time between the receipt of the event and the first callback
+    pub translation: Duration,
+    /// The time between the receipt of the event and the end of the last callback
+    pub processing: Duration,
+}
+
+// delays longer than this mean the compositor uses another clock
+const MAX_DELIVERY_MS: u32 = 60_000;
+
+#[repr(C)]
+struct Timespec {
+    tv_sec: c_long,
+    tv_nsec: c_long,
+}
+
+#[cfg(target_os = "linux")]
+const CLOCK_MONOTONIC: c_int = 1;
+#[cfg(not(target_os = "linux"))]
+const CLOCK_MONOTONIC: c_int = 4;
+
+extern "C" {
+    fn clock_gettime(clock: c_int, tp: *mut Timespec) -> c_int;
+}
+
+/// The delay since a compositor timestamp, if it is in the `CLOCK_MONOTONIC` time base
+pub fn delivery_delay(time: u32) -> Option<Duration> {
+    let mut now = Timespec { tv_sec: 0, tv_nsec: 0 };
+    if unsafe { clock_gettime(CLOCK_MONOTONIC, &mut now) } != 0 {
+        return None;
+    }
+    // the timestamps of the protocol are milliseconds, wrapping around
+    let now_ms = (now.tv_sec as u64 * 1000 + now.tv_nsec as u64 / 1_000_000) as u32;
+    let delay = now_ms.wrapping_sub(time);
+    if delay > MAX_DELIVERY_MS {
+        return None;
+    }
+    Some(Duration::from_millis(u64::from(delay)))
+}
@@ -0,0 +1,46 @@
+use mapped_keyboard::{KeyEvent, KeyboardIdentity};
+use wayland_client::EventQueueHandle;
+use wayland_client::protocol::wl_keyboard::WlKeyboard;
+
+/// Whether an event goes on to the next layer
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Propagation {
+    /// Pass the event to the next layer, and eventually to the `key` callback
+    Continue,
+    /// Consume the event
+    ///
+    /// The next layers and the `key` callback do not receive it, and the shortcuts,
+    /// chords, gestures and input mode change it triggered are not notified.
+    Stop,
+}
+
+/// A layer of key handling, stacked above the `key` callback
+///
+/// Layers make keyboard behaviors reusable: a shortcut layer can consume the keys it
+/// handles, and pass the others down to the text input of the application. They are
+/// listed in `MappedKeyboardImplementation::layers`, the first one receiving the events
+/// first.
+///
+/// Layers share the implementation data of the keyboard, so reusable layers are
+/// usually generic over it, with a trait giving access to their own state.
+pub struct KeyLayer<ID> {
+    /// The name of the layer, for logs
+    pub name: &'static str,
+    /// A key was pressed or released
+    pub key: fn(
+     evqh: &mut EventQueueHandle,
+     idata: &mut ID,
+     keyboard: &WlKeyboard,
+     identity: &KeyboardIdentity,
+     event: &KeyEvent,
+    ) -> Propagation,
+}
+
+impl<ID> Clone for KeyLayer<ID> {
+    fn clone(&self) -> KeyLayer<ID> {
+        KeyLayer {
+            name: self.name,
+            key: self.key,
+        }
+    }
+}
@@ -0,0 +1,73 @@
+//! Counting of the libxkbcommon objects for the `leak-check` feature
+
+use std::sync::atomic::{AtomicIsize, Ordering};
+
+/// A kind of libxkbcommon object, counted by the `leak-check` feature
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum XkbObject {
+    /// An `xkb_context`
+    Context,
+    /// An `xkb_keymap`
+    Keymap,
+    /// An `xkb_state`
+    State,
+    /// An `xkb_compose_table`
+    ComposeTable,
+    /// An `xkb_compose_state`
+    ComposeState,
+}
+
+const OBJECTS: [XkbObject; 5] = [
+    XkbObject::Context,
+    XkbObject::Keymap,
+    XkbObject::State,
+    XkbObject::ComposeTable,
+    XkbObject::ComposeState,
+];
+
+lazy_static!(
+    static ref REFERENCES: [AtomicIsize; 5] = [
+        AtomicIsize::new(0),
+        AtomicIsize::new(0),
+        AtomicIsize::new(0),
+        AtomicIsize::new(0),
+        AtomicIsize::new(0),
+    ];
+);
+
+fn references(object: XkbObject) -> &'static AtomicIsize {
+    &REFERENCES[object as usize]
+}
+
+pub fn acquired(object: XkbObject) {
+    references(object).fetch_add(1, Ordering::SeqCst);
+}
+
+pub fn released(object: XkbObject) {
+    references(object).fetch_sub(1, Ordering::SeqCst);
+}
+
+/// The references to libxkbcommon objects held by this crate, by kind of object
+///
+/// Every object created and every reference taken counts one, and every reference
+/// released uncounts one. The kinds without references are left out.
+pub fn live_xkb_objects() -> Vec<(XkbObject, isize)> {
+    OBJECTS
+        .iter()
+        .map(|&object| (object, references(object).load(Ordering::SeqCst)))
+        .filter(|&(_, count)| count != 0)
+        .collect()
+}
+
+/// Panic if this crate still holds references to libxkbcommon objects
+///
+/// Call it at the end of a test, once the keyboards, compose machines and other
+/// objects of this crate are dropped. The counts are global to the process, so the
+/// tests checking them must not run in parallel with other tests using this crate,
+/// for example with `cargo test -- --test-threads=1`.
+pub fn assert_no_xkb_leaks() {
+    let live = live_xkb_objects();
+    if !live.is_empty() {
+        panic!("libxkbcommon objects were not released: {:?}", live);
+    }
+}
@@ -7,7 +7,126 @@
 //! ## Usage
 //!
 //! To intialize a wl_keyboard with this crate, simply use the provided
-//! `register_kbd` function. See its documentation for details.
+//! `register_kbd` function. See its documentation for details. The
+//! `mapped_keyboard_implementation!` macro builds an implementation from the callbacks
+//! you need.
+//!
+//! Small applications can instead use a `KeyboardHandler`, which gets the keyboard from
+//! the seat, registers it and generates its key repeats, and queues simple events.
+//!
+//! ## Implementation data
+//!
+//! The implementation data given at registration must be `'static`: it is owned by
+//! the event queue of `wayland-client`, which requires this bound and keeps the data
+//! for as long as the keyboard may receive events. As a consequence, a registration
+//! scoped to a borrow of the application state cannot be made safe with this version
+//! of `wayland-client`.
+//!
+//! To access the same data from the callbacks and from the rest of the application,
+//! use `register_kbd_with_state()`, which stores it in the state of the event queue,
+//! or wrap it in `Shared` or `SyncShared`.
+//!
+//! ## Multiple keyboards
+//!
+//! Each registered `wl_keyboard` has its own keymap, modifiers, compose and repeat
+//! state: nothing is shared between registrations, so clients bound to several seats
+//! register each of their keyboards and keep the returned `KeyboardHandle`s apart.
+//! The `KeyboardIdentity` given to every callback tells which keyboard an event comes
+//! from, with `KeyboardOptions::seat_name` naming its seat. Wayland exposes a single
+//! keyboard per seat, merging the physical devices, so there is no finer identity.
+//!
+//! ## Loading libxkbcommon
+//!
+//! libxkbcommon is loaded at runtime on first use, trying `libxkbcommon.so.0` then
+//! `libxkbcommon.so`. Set the `WAYLAND_KBD_XKB_PATH` environment variable or call
+//! `set_xkbcommon_libraries()` to load another build of it. If it cannot be loaded,
+//! registration fails unless `KeyboardOptions::allow_degraded` is set, in which case
+//! the keyboards only deliver raw keycodes.
+//!
+//! ## Errors
+//!
+//! Once registered, a keyboard does not panic on bad input from the compositor: a
+//! keymap that cannot be mapped or compiled is reported through the `error` callback
+//! as a `KeyboardError`, and the previous keymap stays in use. The functions of this
+//! crate that do not need a keyboard return `None` or an error rather than panic when
+//! libxkbcommon could not be loaded.
+//!
+//! Protocol errors and disconnections are reported by `wayland-client` when dispatching
+//! the event queue, and silently kill the keyboards: use `KeyboardHandle::is_alive()`
+//! to notice it and get a new keyboard from the seat.
+//!
+//! ## Cargo features
+//!
+//! - `compose` (enabled by default): handle dead keys and the Compose key with the
+//!   compose tables of libxkbcommon, see `ComposeMachine`. Without it, the keyboards
+//!   never compose and the compose functions of libxkbcommon are not loaded, which
+//!   suits clients only interested in keysyms and modifiers, like status bars.
+//! - `tracing`: emit `tracing` spans and events around keymap compilation,
+//!   keyboard state updates and the dispatching of callbacks.
+//! - `serde`: implement `Serialize` and `Deserialize` for the keymap description
+//!   types, like `KeymapDescription`, for `StateReport`, and for `PortableKeyEvent`, a
+//!   stable form of the key events to exchange them between processes.
+//! - `registry`: load the models, layouts and options known to the system with
+//!   libxkbregistry, see `Registry`.
+//! - `terminal`: encode key events into the sequences terminals send to applications,
+//!   in the xterm or kitty keyboard protocol encodings, see `encode_key()`.
+//! - `unicode-info`: describe the characters produced by the keys with their Unicode
+//!   name and category, see `KeyEvent::char_info()`.
+//! - `alloc-assertions`: panic if updating the modifiers, or looking up the keysym
+//!   and layout of a key, allocates. Only these sections are checked: the rest of the
+//!   processing of key events allocates, for their text, compose sequences and the
+//!   callbacks. The allocations are counted by `CountingAllocator`, which must be the
+//!   global allocator of the program.
+//! - `leak-check`: count the libxkbcommon contexts, keymaps, states and compose
+//!   objects held by this crate, so that tests can check they are all released with
+//!   `assert_no_xkb_leaks()`.
+//! - `portal`: bind shortcuts globally through the GlobalShortcuts portal of
+//!   xdg-desktop-portal, with the D-Bus connection of the application, see
+//!   `GlobalShortcuts`.
+//!
+//! ## Toolkits
+//!
+//! There are no conversions into the key events of GUI toolkits like egui or iced:
+//! their current versions need Rust 1.88, and Cargo resolves optional dependencies even
+//! when their feature is disabled, which would raise the minimum Rust version of this
+//! crate for everyone. `KeyEvent` carries what such a conversion needs, the `nav`
+//! classification for named keys, `utf8` for text and `mods` for the modifiers, so it
+//! takes a few lines in the application:
+//!
+//! ```ignore
+//! fn to_egui(event: &KeyEvent) -> Option<egui::Event> {
+//!     let key = match event.nav? {
+//!         NavKey::Up => egui::Key::ArrowUp,
+//!         NavKey::Down => egui::Key::ArrowDown,
+//!         NavKey::Left => egui::Key::ArrowLeft,
+//!         NavKey::Right => egui::Key::ArrowRight,
+//!         NavKey::Home => egui::Key::Home,
+//!         NavKey::End => egui::Key::End,
+//!         NavKey::PageUp => egui::Key::PageUp,
+//!         NavKey::PageDown => egui::Key::PageDown,
+//!         NavKey::Insert => egui::Key::Insert,
+//!         NavKey::Delete => egui::Key::Delete,
+//!         NavKey::Backspace => egui::Key::Backspace,
+//!     };
+//!     let mods = event.mods.modifiers;
+//!     Some(egui::Event::Key {
+//!         key,
+//!         physical_key: None,
+//!         pressed: event.state == KeyState::Pressed,
+//!         repeat: event.origin == KeyOrigin::Repeat,
+//!         modifiers: egui::Modifiers {
+//!             alt: mods.contains(Modifiers::ALT),
+//!             ctrl: mods.contains(Modifiers::CTRL),
+//!             shift: mods.contains(Modifiers::SHIFT),
+//!             mac_cmd: false,
+//!             command: mods.contains(Modifiers::CTRL),
+//!         },
+//!     })
+//! }
+//! ```
+//!
+//! The text of `utf8` goes into an `egui::Event::Text`, and iced is fed the same way,
+//! with `iced::keyboard::key::Named` for the named keys.
 
 #[macro_use]
 extern crate bitflags;
@@ -16,11 +135,125 @@ extern crate dlib;
 #[macro_use]
 extern crate lazy_static;
 extern crate memmap;
+#[cfg(feature = "serde")]
+extern crate serde;
+#[cfg(feature = "tracing")]
+extern crate tracing;
+#[cfg(test)]
+extern crate tempfile;
 extern crate wayland_client;
 
+#[macro_use]
+mod macros;
+#[macro_use]
+mod trace;
+#[cfg(feature = "serde")]
+#[macro_use]
+mod serde_impls;
+
+mod action;
+#[cfg(feature = "alloc-assertions")]
+mod alloc_check;
+mod capabilities;
+mod chord;
+mod classify;
+mod compose;
+mod config;
+mod debug_dump;
+mod diagnostics;
 mod ffi;
+mod gesture;
+mod grapheme;
+mod handler;
+mod indicator;
+mod keymap;
+mod label;
+mod latency;
+mod layer;
+#[cfg(feature = "leak-check")]
+mod leak_check;
+mod keysym_names;
+mod known_keysym;
+mod library;
+mod locale;
 mod mapped_keyboard;
+mod media;
+mod overflow;
+#[cfg(feature = "portal")]
+mod portal;
+mod portable;
+#[cfg(feature = "registry")]
+mod registry;
+mod remap;
+mod repeat;
+mod shared;
+mod shortcut;
+mod state_report;
+mod strict;
+mod stuck;
+#[cfg(feature = "terminal")]
+mod terminal;
+mod text_buffer;
+mod text_macro;
+#[cfg(feature = "unicode-info")]
+mod unicode_info;
 
+pub use action::Action;
+#[cfg(feature = "alloc-assertions")]
+pub use alloc_check::{thread_allocations, CountingAllocator};
+pub use capabilities::{features, xkb_capabilities, Features, XkbCapabilities};
+pub use chord::Chord;
+pub use classify::{braille_dot, braille_pattern, braille_pattern_keysym, is_enter, is_keypad, normalize_enter,
+                   EnterKey, InputMethodKey, InputModeState, KeyKind, NavKey};
+#[cfg(feature = "compose")]
+pub use compose::ComposeMachine;
+pub use compose::{ComposeSequence, ComposeStatus};
+pub use config::KeyboardConfig;
+pub use debug_dump::DebugDump;
+pub use diagnostics::{Diagnostics, DiagnosticsSnapshot};
 pub use ffi::keysyms;
-pub use mapped_keyboard::{register_kbd, register_kbd_from_rmlvo, MappedKeyboardError,
-                          MappedKeyboardImplementation, ModifiersState, RMLVO};
+pub use gesture::{GestureConfig, KeyGesture};
+pub use grapheme::{graphemes, is_single_grapheme};
+pub use handler::{KeyboardHandler, KeyboardHandlerEvent};
+pub use indicator::LayoutIndicator;
+pub use keymap::{KeyCombo, KeyDescription, KeyLegend, KeyLocation, KeymapChange, KeymapDescription,
+                 LayoutLegends, LevelLegend, MAX_KEYCODE};
+pub use keysym_names::{keysym_from_name, keysym_name, search_keysyms, KeysymCandidate,
+                       KeysymMatchKind};
+pub use known_keysym::KnownKeysym;
+pub use label::{LabelStyle, ModifierNames};
+pub use latency::KeyLatency;
+#[cfg(feature = "leak-check")]
+pub use leak_check::{assert_no_xkb_leaks, live_xkb_objects, XkbObject};
+pub use layer::{KeyLayer, Propagation};
+pub use library::{set_xkbcommon_libraries, set_xkbcommon_library, xkbcommon_library,
+                  xkbcommon_tried_libraries};
+pub use locale::{suggest_rmlvo, suggest_rmlvo_for_locale};
+pub use mapped_keyboard::{register_kbd, register_kbd_from_rmlvo, register_kbd_with_options,
+                          register_kbd_with_state, raw_keys, validate_rmlvo, ComposeCancelReason,
+                          ComposeEvent, ComposePolicy, KeyEvent, KeyOrigin, KeyboardGuard, KeyboardHandle,
+                          KeyboardContext, KeyboardError, KeyboardIdentity, KeyboardOptions, KeymapInfo,
+                          MappedKeyboardError, MappedKeyboardImplementation, Modifier, ModifierStatus,
+                          Modifiers, ModifiersDiff, ModifiersState, ModifiersStatus, RawKeys, RepeatHandle,
+                          SerializedState, RMLVO};
+pub use media::{MediaCategory, MediaKey};
+pub use overflow::OverflowPolicy;
+#[cfg(feature = "portal")]
+pub use portal::{portal_trigger, shortcut_from_portal_trigger, GlobalShortcuts};
+pub use portable::PortableKeyEvent;
+#[cfg(feature = "registry")]
+pub use registry::{Registry, RegistryError, RegistryLayout, RegistryModel, RegistryOption,
+                   RegistryOptionGroup};
+pub use remap::ModifierRemap;
+pub use repeat::RepeatPolicy;
+pub use shared::{Shared, SyncShared};
+pub use shortcut::{Shortcut, ShortcutKey, ShortcutMatching};
+#[cfg(feature = "terminal")]
+pub use terminal::{encode_key, KittyFlags, TerminalModes};
+pub use state_report::{LockLeds, StateReport};
+pub use strict::ProtocolViolation;
+pub use stuck::StuckModifiers;
+pub use text_buffer::TextBuffer;
+pub use text_macro::TextMacro;
+#[cfg(feature = "unicode-info")]
+pub use unicode_info::{char_info, CharCategory, CharInfo};
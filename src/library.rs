@@ -0,0 +1,41 @@
+use ffi;
+
+/// Choose the libxkbcommon library to load
+///
+/// `path` is given to `dlopen()`, so it can be a soname like `"libxkbcommon.so.0"` or
+/// the full path of a library, as needed by Flatpak or Nix builds shipping their own.
+/// It takes precedence over the `WAYLAND_KBD_XKB_PATH` environment variable.
+///
+/// The library is loaded the first time it is needed, and cannot be changed after that:
+/// this returns `false` and has no effect if it was already loaded.
+pub fn set_xkbcommon_library<S: Into<String>>(path: S) -> bool {
+    ffi::set_xkbcommon_paths(vec![path.into()])
+}
+
+/// Choose the libxkbcommon libraries to try loading, in order
+///
+/// The first one that can be loaded is used. Like `set_xkbcommon_library()`, this
+/// returns `false` and has no effect if the loading was already done.
+///
+/// By default, `WAYLAND_KBD_XKB_PATH` is used if it is set, as a `:`-separated list,
+/// else `"libxkbcommon.so.0"` then `"libxkbcommon.so"` are tried.
+pub fn set_xkbcommon_libraries<I, S>(paths: I) -> bool
+where
+    I: IntoIterator<Item = S>,
+    S: Into<String>,
+{
+    ffi::set_xkbcommon_paths(paths.into_iter().map(Into::into).collect())
+}
+
+/// The libxkbcommon library that is loaded, or the first one that will be tried
+pub fn xkbcommon_library() -> String {
+    ffi::xkbcommon_path()
+}
+
+/// The libxkbcommon libraries that were tried, in order
+///
+/// Empty if libxkbcommon was not needed yet. If it could be loaded, the last one is
+/// the library in use.
+pub fn xkbcommon_tried_libraries() -> Vec<String> {
+    ffi::xkbcommon_tried()
+}
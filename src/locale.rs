@@ -0,0 +1,132 @@
+use mapped_keyboard::RMLVO;
+use std::env;
+
+// layouts for a language in a given territory, when they differ from the language default
+const TERRITORY_LAYOUTS: &'static [(&'static str, &'static str, &'static str, &'static str)] = &[
+    // (language, territory, layout, variant)
+    ("en", "GB", "gb", ""),
+    ("en", "IE", "ie", ""),
+    ("de", "CH", "ch", ""),
+    ("fr", "CH", "ch", "fr"),
+    ("it", "CH", "ch", ""),
+    ("de", "AT", "at", ""),
+    ("fr", "BE", "be", ""),
+    ("nl", "BE", "be", ""),
+    ("de", "BE", "be", ""),
+    ("fr", "CA", "ca", ""),
+    ("pt", "BR", "br", ""),
+    ("es", "AR", "latam", ""),
+    ("es", "BO", "latam", ""),
+    ("es", "CL", "latam", ""),
+    ("es", "CO", "latam", ""),
+    ("es", "CR", "latam", ""),
+    ("es", "EC", "latam", ""),
+    ("es", "MX", "latam", ""),
+    ("es", "PE", "latam", ""),
+    ("es", "UY", "latam", ""),
+    ("es", "VE", "latam", ""),
+];
+
+// layouts for a language, layouts of non-latin scripts come after a latin one
+const LANGUAGE_LAYOUTS: &'static [(&'static str, &'static str)] = &[
+    ("ar", "us,ara"),
+    ("be", "us,by"),
+    ("bg", "us,bg"),
+    ("ca", "es"),
+    ("cs", "cz"),
+    ("da", "dk"),
+    ("de", "de"),
+    ("el", "us,gr"),
+    ("en", "us"),
+    ("es", "es"),
+    ("et", "ee"),
+    ("eu", "es"),
+    ("fa", "us,ir"),
+    ("fi", "fi"),
+    ("fr", "fr"),
+    ("ga", "ie"),
+    ("gl", "es"),
+    ("he", "us,il"),
+    ("hi", "us,in"),
+    ("hr", "hr"),
+    ("hu", "hu"),
+    ("hy", "us,am"),
+    ("id", "us"),
+    ("is", "is"),
+    ("it", "it"),
+    ("ja", "jp"),
+    ("ka", "us,ge"),
+    ("kk", "us,kz"),
+    ("ko", "kr"),
+    ("lt", "lt"),
+    ("lv", "lv"),
+    ("mk", "us,mk"),
+    ("ms", "us"),
+    ("nb", "no"),
+    ("nl", "us"),
+    ("nn", "no"),
+    ("no", "no"),
+    ("pl", "pl"),
+    ("pt", "pt"),
+    ("ro", "ro"),
+    ("ru", "us,ru"),
+    ("sk", "sk"),
+    ("sl", "si"),
+    ("sq", "al"),
+    ("sr", "rs"),
+    ("sv", "se"),
+    ("th", "us,th"),
+    ("tr", "tr"),
+    ("uk", "us,ua"),
+    ("vi", "vn"),
+    ("zh", "us"),
+];
+
+/// Suggest a RMLVO matching the locale of the environment
+///
+/// The locale is read from the first non-empty of `LC_ALL`, `LC_CTYPE` and `LANG`.
+/// See `suggest_rmlvo_for_locale()`.
+pub fn suggest_rmlvo() -> Option<RMLVO> {
+    ["LC_ALL", "LC_CTYPE", "LANG"]
+        .iter()
+        .filter_map(|var| env::var(var).ok())
+        .find(|locale| !locale.is_empty())
+        .and_then(|locale| suggest_rmlvo_for_locale(&locale))
+}
+
+/// Suggest a RMLVO matching a locale, like `de_DE.UTF-8`
+///
+/// This is only a reasonable first guess, to be used when the compositor does not
+/// provide a keymap and the user did not choose one. For languages written with a
+/// non-latin script, a latin layout is included first.
+///
+/// Returns `None` for the `C` and `POSIX` locales, and for unknown languages.
+pub fn suggest_rmlvo_for_locale(locale: &str) -> Option<RMLVO> {
+    // language[_territory][.codeset][@modifier]
+    let name = locale.split(|c| c == '.' || c == '@').next().unwrap_or("");
+    let mut parts = name.splitn(2, '_');
+    let language = parts.next().unwrap_or("");
+    let territory = parts.next().unwrap_or("");
+
+    let (layout, variant) = match TERRITORY_LAYOUTS
+        .iter()
+        .find(|&&(lang, terr, _, _)| lang == language && terr == territory)
+    {
+        Some(&(_, _, layout, variant)) => (layout, variant),
+        None => match LANGUAGE_LAYOUTS.iter().find(|&&(lang, _)| lang == language) {
+            Some(&(_, layout)) => (layout, ""),
+            None => return None,
+        },
+    };
+    Some(RMLVO {
+        rules: None,
+        model: None,
+        layout: Some(layout.to_owned()),
+        variant: if variant.is_empty() {
+            None
+        } else {
+            Some(variant.to_owned())
+        },
+        options: None,
+    })
+}
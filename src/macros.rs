@@ -0,0 +1,27 @@
+/// Build a `MappedKeyboardImplementation` from the callbacks you need
+///
+/// The type of the implementation data comes first, followed by the callbacks as
+/// closures, named like the fields of `MappedKeyboardImplementation`. The callbacks
+/// left out do nothing:
+///
+/// ```ignore
+/// let implementation = mapped_keyboard_implementation!(MyData {
+///     key: |_, idata, _, _, event| {
+///         if let Some(text) = event.utf8 {
+///             idata.text.push_str(&text);
+///         }
+///     },
+///     leave: |_, idata, _, _, _, _| idata.text.clear(),
+/// });
+/// ```
+///
+/// As for the fields, the closures must not capture their environment.
+#[macro_export]
+macro_rules! mapped_keyboard_implementation {
+    ($idata:ty { $($callback:ident: $implementation:expr),* $(,)* }) => {
+        $crate::MappedKeyboardImplementation::<$idata> {
+            $($callback: $implementation,)*
+            ..::std::default::Default::default()
+        }
+    };
+}
@@ -1,14 +1,44 @@
-use ffi::{self, xkb_state_component};
+use action::Action;
+use capabilities::xkb_capabilities;
+use chord::Chord;
+use compose::{self, ComposeMachine, ComposeSequence, ComposeStatus};
+use classify::{self, InputModeState, KeyKind, NavKey};
+use debug_dump::DebugDump;
+use diagnostics::Diagnostics;
+use gesture::{GestureConfig, GestureState, KeyGesture};
+use grapheme;
+use indicator::{self, LayoutIndicator};
+use label::LabelStyle;
+use latency::{self, KeyLatency};
+use layer::{KeyLayer, Propagation};
+use ffi::{self, keysyms, xkb_state_component};
 use ffi::XKBCOMMON_HANDLE as XKBH;
+use keymap::{self, KeyCombo, KeyLocation, KeymapChange, KeymapDescription, LayoutLegends, ReverseIndex};
 use memmap::MmapOptions;
-use std::env;
-use std::ffi::CString;
+use overflow::OverflowPolicy;
+use std::any::Any;
+use std::ffi::{CStr, CString};
+use std::fmt;
+use std::hash::{Hash, Hasher};
 use std::fs::File;
+use std::io;
 use std::os::raw::c_char;
-use std::os::unix::ffi::OsStringExt;
 use std::os::unix::io::{FromRawFd, RawFd};
 use std::ptr;
-use wayland_client::EventQueueHandle;
+use std::slice::Chunks;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+use remap::{remap_keycode, remap_masks, ModifierRemap, RemapMods};
+use repeat::{RepeatPolicy, RepeatState, DEFAULT_REPEAT_INFO};
+use shortcut::{Shortcut, ShortcutKey, ShortcutMatching};
+use state_report::{LockLeds, StateReport};
+use strict::{ProtocolTracker, ProtocolViolation};
+use stuck::StuckModifiers;
+use text_macro::{TextMacro, TextMacroState};
+#[cfg(feature = "unicode-info")]
+use unicode_info::{self, CharInfo};
+use wayland_client::{EventQueueHandle, Proxy, StateToken};
 use wayland_client::protocol::wl_keyboard::{self, KeyState, KeymapFormat, WlKeyboard};
 use wayland_client::protocol::wl_surface::WlSurface;
 
@@ -16,182 +46,967 @@ struct KbState {
     xkb_context: *mut ffi::xkb_context,
     xkb_keymap: *mut ffi::xkb_keymap,
     xkb_state: *mut ffi::xkb_state,
-    xkb_compose_table: *mut ffi::xkb_compose_table,
-    xkb_compose_state: *mut ffi::xkb_compose_state,
+    compose: Option<ComposeMachine>,
     mods_state: ModifiersState,
     locked: bool,
+    options: KeyboardOptions,
+    compose_text: String,
+    compose_last_time: u32,
+    // the id of the active compose context, see KeyboardHandle::set_compose_context()
+    compose_context: Option<u64>,
+    // the inactive compose contexts with a pending sequence
+    compose_contexts: Vec<ComposeContext>,
+    compose_events: Vec<ComposeEvent>,
+    macro_state: TextMacroState,
+    held_keys: Vec<HeldKey>,
+    // the keys held when the focus was lost, to detect stuck modifiers
+    keys_at_leave: Vec<HeldKey>,
+    gesture_state: GestureState,
+    input_mode: InputModeState,
+    rmlvo: Option<RMLVO>,
+    // the last keymap sent by the compositor, kept while locked to follow it again later
+    compositor_keymap: Option<Vec<u8>>,
+    surface_keymaps: Vec<SurfaceKeymap>,
+    // the keymap and state of the keyboard, put aside while a surface keymap is active
+    default_keymap: Option<(*mut ffi::xkb_keymap, *mut ffi::xkb_state)>,
+    repeat_info: Option<(i32, i32)>,
+    identity: KeyboardIdentity,
+    repeat: Option<RepeatState>,
+    repeat_paused: bool,
+    raw_masks: StateMasks,
+    reverse_index: Option<ReverseIndex>,
+    // the last layout indicator notified through the `layout_indicator` callback
+    last_indicator: Option<LayoutIndicator>,
+    protocol: ProtocolTracker,
+    // the registered wl_keyboard, to tell when it dies
+    keyboard: Option<WlKeyboard>,
+}
+
+/// The components of a keyboard state, as given to `xkb_state_update_mask`
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct StateMasks {
+    pub depressed: u32,
+    pub latched: u32,
+    pub locked: u32,
+    pub depressed_layout: u32,
+    pub latched_layout: u32,
+    pub locked_layout: u32,
+}
+
+/// The compose state of an inactive compose context
+struct ComposeContext {
+    id: Option<u64>,
+    machine: ComposeMachine,
+    text: String,
+    last_time: u32,
+}
+
+/// A keymap used instead of the keyboard's while a surface has focus
+struct SurfaceKeymap {
+    surface: WlSurface,
+    xkb_keymap: *mut ffi::xkb_keymap,
+}
+
+/// A key currently held down
+#[derive(Copy, Clone, Debug)]
+pub struct HeldKey {
+    pub keycode: u32,
+    pub keysym: u32,
+    /// The time of the key press, `None` if it was already held when gaining focus
+    pub time: Option<u32>,
+}
+
+/// Convert a duration to milliseconds, for comparison with event timestamps
+pub fn duration_ms(d: Duration) -> u64 {
+    d.as_secs() * 1000 + u64::from(d.subsec_nanos() / 1_000_000)
+}
+
+/// Check if a keysym is the one of a modifier key
+pub fn is_modifier_keysym(keysym: u32) -> bool {
+    (keysym >= keysyms::XKB_KEY_Shift_L && keysym <= keysyms::XKB_KEY_Hyper_R)
+        || (keysym >= keysyms::XKB_KEY_ISO_Lock && keysym <= keysyms::XKB_KEY_ISO_Last_Group_Lock)
+        || keysym == keysyms::XKB_KEY_Mode_switch || keysym == keysyms::XKB_KEY_Num_Lock
+}
+
+/// Policy applied to a key press that does not continue a pending compose sequence
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum ComposePolicy {
+    /// The sequence is cancelled and the key produces no text
+    ///
+    /// This is the default.
+    Cancel,
+    /// The sequence is cancelled and the text of the keys that made it up is
+    /// delivered, followed by the text of the key
+    ///
+    /// Keys that have no text of their own (like dead keys) contribute nothing.
+    Buffer,
+    /// The key produces its text as if no sequence was pending, and the sequence
+    /// stays pending
+    PassThrough,
+}
+
+/// Where a key event comes from
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum KeyOrigin {
+    /// The key was pressed or released, as sent by the compositor
+    Device,
+    /// The event is a repeat of a held key, synthesized by `KeyboardHandle::poll_repeat()`
+    Repeat,
+}
+
+/// A key event, after it has been interpreted with the keymap
+#[derive(Clone, Debug)]
+pub struct KeyEvent {
+    /// The serial of the event
+    pub serial: u32,
+    /// The timestamp of the event, in milliseconds
+    pub time: u32,
+    /// The modifiers active when the key was pressed or released
+    pub mods: ModifiersState,
+    /// The raw evdev keycode of the key
+    pub rawkey: u32,
+    /// The keysym produced by the key
+    pub keysym: u32,
+    /// The index of the layout the key was translated with
+    ///
+    /// Keys not defined in the active layout are translated with another one, so this
+    /// may differ from the active layout. The names of the layouts are given by
+    /// `KeyboardHandle::keymap_description()`.
+    pub layout: u32,
+    /// Whether the key was pressed or released
+    ///
+    /// Repeats are reported as pressed, see `origin` to tell them apart.
+    pub state: KeyState,
+    /// Whether the event comes from the compositor or is a repeat
+    pub origin: KeyOrigin,
+    /// The text produced by the key press, if any
+    ///
+    /// The text of a compose sequence is delivered whole in a single event, even when
+    /// it has several code points, like a letter followed by a combining accent. Use
+    /// `grapheme()` to insert it as a single character.
+    pub utf8: Option<String>,
+    /// The kind of the key
+    pub kind: KeyKind,
+    /// Whether the key is on the numeric keypad, like keypad Enter or keypad digits
+    pub is_keypad: bool,
+    /// The navigation or editing key, like an arrow or Delete, if it is one
+    pub nav: Option<NavKey>,
+    /// The number of this repeat of a held key, see `KeyboardHandle::poll_repeat()`
+    ///
+    /// It is 0 for the events sent by the compositor, and counts from 1 for the
+    /// repeats. Repeats have the serial of the original key press, and a `time`
+    /// synthesized from the time of the press and the repeat rate and delay.
+    pub repeat_count: u32,
+    /// Whether the keyboard runs without libxkbcommon, see `KeyboardOptions::allow_degraded`
+    ///
+    /// Degraded events only carry the raw keycode: the keysym is 0 (`XKB_KEY_NoSymbol`),
+    /// there is no text and the modifiers are all inactive.
+    pub degraded: bool,
+}
+
+impl KeyEvent {
+    /// Whether this is a repeat of a held key
+    pub fn is_repeat(&self) -> bool {
+        self.origin == KeyOrigin::Repeat
+    }
+
+    /// The text produced by the key, if it is a single grapheme cluster
+    ///
+    /// A grapheme cluster is a character as perceived by the user, which text widgets
+    /// should insert, and delete, atomically: `"e\u{301}"` is a single one. Returns
+    /// `None` for the text of a compose sequence made of several characters, see
+    /// `graphemes()`.
+    pub fn grapheme(&self) -> Option<&str> {
+        match self.utf8 {
+            Some(ref text) if grapheme::is_single_grapheme(text) => Some(text),
+            _ => None,
+        }
+    }
+
+    /// The grapheme clusters of the text produced by the key
+    ///
+    /// Empty if the key produced no text. See `wayland_kbd::graphemes()`.
+    pub fn graphemes(&self) -> Vec<&str> {
+        self.utf8.as_ref().map_or(Vec::new(), |text| grapheme::graphemes(text))
+    }
+
+    /// Describe the characters of the text produced by the key
+    ///
+    /// Empty if the key produced no text, like key releases.
+    #[cfg(feature = "unicode-info")]
+    pub fn char_info(&self) -> Vec<CharInfo> {
+        self.utf8
+            .as_ref()
+            .map_or(Vec::new(), |text| text.chars().map(unicode_info::char_info).collect())
+    }
+
+    /// The semantic action of this key press, if any
+    ///
+    /// Returns `None` for key releases. See `Action::from_key()`.
+    pub fn action(&self) -> Option<Action> {
+        if self.state == KeyState::Pressed {
+            Action::from_key(self.keysym, &self.mods)
+        } else {
+            None
+        }
+    }
+}
+
+/// A notification about a compose sequence
+///
+/// Together, these allow displaying the pending sequence like an input method
+/// would, without using one.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ComposeEvent {
+    /// A compose sequence is pending, with this display text
+    ///
+    /// For example, `"´"` after a dead acute key. See `KeyboardHandle::compose_preedit()`.
+    Preedit(String),
+    /// The pending compose sequence was completed, producing this text
+    ///
+    /// The text is also delivered by the `key` callback.
+    Committed(String),
+    /// The pending compose sequence was cancelled
+    Cancelled(ComposeCancelReason),
+}
+
+/// The reason why a compose sequence was cancelled
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum ComposeCancelReason {
+    /// A key that does not continue the sequence was pressed
+    NoMatch,
+    /// No key was pressed for longer than the configured compose timeout
+    Timeout,
+    /// The keyboard focus was lost, see `KeyboardOptions::compose_cancel_on_leave`
+    FocusLost,
+}
+
+/// The modifiers and layout of a keyboard, serialized for forwarding
+///
+/// The values are in the wire format of the `wl_keyboard.modifiers` event, and are
+/// meant to be forwarded as-is to a nested compositor, a remote desktop peer or
+/// a virtual keyboard using the same keymap.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct SerializedState {
+    /// The depressed modifiers mask
+    pub mods_depressed: u32,
+    /// The latched modifiers mask
+    pub mods_latched: u32,
+    /// The locked modifiers mask
+    pub mods_locked: u32,
+    /// The effective layout index
+    pub group: u32,
+    /// The depressed layout index
+    ///
+    /// This and the next fields are not part of the wire format, but allow
+    /// restoring the state exactly with `KeyboardHandle::apply_serialized_state()`.
+    pub layout_depressed: u32,
+    /// The latched layout index
+    pub layout_latched: u32,
+    /// The locked layout index
+    pub layout_locked: u32,
+}
+
+/// Options tuning the behavior of a mapped keyboard
+///
+/// Use `KeyboardOptions::default()` to get the default behavior.
+#[derive(Clone, Debug)]
+pub struct KeyboardOptions {
+    /// Whether to process compose sequences
+    ///
+    /// If `false`, no compose table is loaded and dead keys produce no text. This is
+    /// always the case when the crate is built without the `compose` feature.
+    pub compose: bool,
+    /// How to treat key presses that do not continue a pending compose sequence
+    pub compose_policy: ComposePolicy,
+    /// Cancel a pending compose sequence if no key was pressed for this long
+    ///
+    /// The timeout is checked against the timestamps of the key events, so the
+    /// cancellation is notified when the next key is pressed.
+    pub compose_timeout: Option<Duration>,
+    /// Whether a pending compose sequence is cancelled when the keyboard focus is lost
+    ///
+    /// If `false` (the default), the sequence continues when the focus comes back.
+    /// If `true`, the cancellation is notified through the `compose` callback, before
+    /// the `leave` callback.
+    pub compose_cancel_on_leave: bool,
+    /// Compose sequences to add to the ones of the locale
+    ///
+    /// They take precedence over the sequences of the locale, and of the user's
+    /// compose file, that they conflict with.
+    pub compose_sequences: Vec<ComposeSequence>,
+    /// The locale of the compose table, like `"en_US.UTF-8"`
+    ///
+    /// If `None` (the default), it is taken from the `LC_ALL`, `LC_CTYPE` or `LANG`
+    /// environment variables, and the user's compose file, like `~/.XCompose`, is used
+    /// when there is one. If set, the environment is not read at all, which is useful for
+    /// sandboxed or server-side uses.
+    pub compose_locale: Option<String>,
+    /// Key sequences expanding to some text, see `TextMacro`
+    pub text_macros: Vec<TextMacro>,
+    /// Sets of keys notified through the `chord` callback when held together, see `Chord`
+    pub chords: Vec<Chord>,
+    /// Shortcuts notified through the `shortcut` callback when triggered, see `Shortcut`
+    pub shortcuts: Vec<Shortcut>,
+    /// Keys on which gestures are notified through the `gesture` callback, see `GestureConfig`
+    pub gestures: Vec<GestureConfig>,
+    /// A collector of statistics about the input, see `Diagnostics`
+    pub diagnostics: Option<Diagnostics>,
+    /// Modifier remappings applied on top of the keymap, see `ModifierRemap`
+    ///
+    /// They can be changed later using `KeyboardHandle::set_modifier_remaps()`.
+    pub modifier_remaps: Vec<ModifierRemap>,
+    /// Key repeat rate and delay, overriding the ones sent by the compositor
+    ///
+    /// They are reported by the `repeat_info` callback instead of the compositor's.
+    /// Whatever their source, rates above 1000 repeats per second are treated as 1000.
+    pub repeat_info: Option<(i32, i32)>,
+    /// The name of the seat of the keyboard, as sent by the `wl_seat.name` event
+    ///
+    /// As this event may come after the keyboard is registered, the name can also be
+    /// set later using `KeyboardHandle::set_seat_name()`.
+    pub seat_name: Option<String>,
+    /// How key repeat behaves, see `RepeatPolicy` and `KeyboardHandle::poll_repeat()`
+    pub repeat_policy: RepeatPolicy,
+    /// Whether `KP_Enter` and `ISO_Enter` are reported as Return
+    ///
+    /// Defaults to `false`. Only the keysym is changed, `KeyEvent::is_keypad` still
+    /// tells whether the key is on the keypad.
+    pub normalize_enter: bool,
+    /// Whether to register the keyboard even if libxkbcommon cannot be loaded
+    ///
+    /// Defaults to `false`, the registration then fails with `LibraryNotFound`. If
+    /// `true`, the keyboard runs in a degraded mode instead: no keymap is ever loaded and
+    /// the key events only carry raw keycodes, see `KeyEvent::degraded`. This lets
+    /// minimal clients get some input on systems without libxkbcommon.
+    pub allow_degraded: bool,
+    /// A function called with the latency of each key event, see `KeyLatency`
+    ///
+    /// It is called after the callbacks of the event. Defaults to `None`.
+    pub latency_hook: Option<fn(&KeyLatency)>,
+    /// Whether to check that the compositor follows the `wl_keyboard` protocol
+    ///
+    /// Defaults to `false`. If `true`, violations like a key event without focus or
+    /// decreasing serials are reported through the `error` callback, see
+    /// `ProtocolViolation`.
+    pub strict: bool,
+    /// Data given to the callbacks through `KeyboardIdentity::context`
+    ///
+    /// Defaults to `None`, see `KeyboardContext`.
+    pub context: Option<KeyboardContext>,
+    /// The maximum number of synthesized events queued at once
+    ///
+    /// This bounds the repeats returned by a call to `KeyboardHandle::poll_repeat()`,
+    /// and the events queued by a `KeyboardHandler` between two polls, so that memory
+    /// cannot grow without bound when the application stalls. The repeats over the bound
+    /// are never generated. Defaults to 256, `None` disables the bound.
+    ///
+    /// Text macros need no bound: they produce a single text per key event, and the
+    /// text they hold back is at most the one of their longest sequence.
+    pub max_queued_events: Option<usize>,
+    /// What to do with the events exceeding `max_queued_events`
+    ///
+    /// Defaults to `OverflowPolicy::DropOldest`.
+    pub overflow_policy: OverflowPolicy,
+}
+
+impl Default for KeyboardOptions {
+    fn default() -> KeyboardOptions {
+        KeyboardOptions {
+            compose: true,
+            compose_policy: ComposePolicy::Cancel,
+            compose_timeout: None,
+            compose_cancel_on_leave: false,
+            compose_sequences: Vec::new(),
+            compose_locale: None,
+            text_macros: Vec::new(),
+            chords: Vec::new(),
+            shortcuts: Vec::new(),
+            gestures: Vec::new(),
+            diagnostics: None,
+            modifier_remaps: Vec::new(),
+            repeat_info: None,
+            seat_name: None,
+            repeat_policy: RepeatPolicy::default(),
+            normalize_enter: false,
+            allow_degraded: false,
+            latency_hook: None,
+            strict: false,
+            context: None,
+            max_queued_events: Some(256),
+            overflow_policy: OverflowPolicy::default(),
+        }
+    }
+}
+
+bitflags!(
+    /// A set of keyboard modifiers
+    ///
+    /// The sets can be combined with the usual operators, like `Modifiers::CTRL |
+    /// Modifiers::SHIFT`.
+    #[derive(Default)]
+    pub struct Modifiers: u8 {
+        /// The "control" key
+        const CTRL = 1;
+        /// The "alt" key
+        const ALT = 2;
+        /// The "shift" key
+        const SHIFT = 4;
+        /// The "Caps lock" key
+        const CAPS_LOCK = 8;
+        /// The "logo" key
+        ///
+        /// Also known as the "windows" key on most keyboards
+        const LOGO = 16;
+        /// The "Num lock" key
+        const NUM_LOCK = 32;
+    }
+);
+
+// the names of the modifiers in the keymaps
+const MODIFIER_NAMES: [(Modifiers, &'static [u8]); 6] = [
+    (Modifiers::CTRL, ffi::XKB_MOD_NAME_CTRL),
+    (Modifiers::ALT, ffi::XKB_MOD_NAME_ALT),
+    (Modifiers::SHIFT, ffi::XKB_MOD_NAME_SHIFT),
+    (Modifiers::CAPS_LOCK, ffi::XKB_MOD_NAME_CAPS),
+    (Modifiers::LOGO, ffi::XKB_MOD_NAME_LOGO),
+    (Modifiers::NUM_LOCK, ffi::XKB_MOD_NAME_NUM),
+];
+
+impl Modifiers {
+    /// The modifiers whose name in the keymap matches `is_set`
+    fn from_names<F: Fn(&[u8]) -> bool>(is_set: F) -> Modifiers {
+        MODIFIER_NAMES
+            .iter()
+            .filter(|&&(_, name)| is_set(name))
+            .fold(Modifiers::empty(), |mods, &(flag, _)| mods | flag)
+    }
 }
 
 /// Represents the current state of the keyboard modifiers
 ///
-/// Each field of this struct represents a modifier and is `true` if this modifier is active.
+/// `modifiers` holds the active modifiers, which are also given by accessors like
+/// `ctrl()`.
 ///
 /// For some modifiers, this means that the key is currently pressed, others are toggled
-/// (like caps lock).
-#[derive(Copy, Clone, Debug)]
+/// (like caps lock). See `status` for how each modifier came to be active.
+///
+/// Comparisons and hashing take `status` into account: a latched and a depressed
+/// shift are not equal. Use `ModifiersState::from_modifiers()` when building keys of
+/// dispatch tables.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
 pub struct ModifiersState {
+    /// The active modifiers
+    pub modifiers: Modifiers,
+    /// Whether each modifier is held, latched or locked
+    pub status: ModifiersStatus,
+}
+
+#[cfg(feature = "serde")]
+serde_struct!(ModifiersState { modifiers, status });
+
+/// How a modifier is active
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum ModifierStatus {
+    /// The modifier is not active
+    Inactive,
+    /// The modifier is active because a key is held down
+    Depressed,
+    /// The modifier is latched, and will be released after the next key press
+    Latched,
+    /// The modifier is locked
+    Locked,
+}
+
+#[cfg(feature = "serde")]
+serde_unit_enum!(ModifierStatus { Inactive, Depressed, Latched, Locked });
+
+impl Default for ModifierStatus {
+    fn default() -> ModifierStatus {
+        ModifierStatus::Inactive
+    }
+}
+
+/// The status of each keyboard modifier
+///
+/// A modifier can be both held and locked, for example while Caps Lock is pressed
+/// to unlock it. Locked takes precedence over latched, which takes precedence over
+/// depressed.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct ModifiersStatus {
     /// The "control" key
-    pub ctrl: bool,
+    pub ctrl: ModifierStatus,
     /// The "alt" key
-    pub alt: bool,
+    pub alt: ModifierStatus,
     /// The "shift" key
-    pub shift: bool,
+    pub shift: ModifierStatus,
     /// The "Caps lock" key
-    pub caps_lock: bool,
+    pub caps_lock: ModifierStatus,
     /// The "logo" key
-    ///
-    /// Also known as the "windows" key on most keyboards
-    pub logo: bool,
+    pub logo: ModifierStatus,
+    /// The "Num lock" key
+    pub num_lock: ModifierStatus,
+}
+
+#[cfg(feature = "serde")]
+serde_struct!(ModifiersStatus { ctrl, alt, shift, caps_lock, logo, num_lock });
+
+/// One of the keyboard modifiers of `ModifiersState`
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Modifier {
+    /// The "control" key
+    Ctrl,
+    /// The "alt" key
+    Alt,
+    /// The "shift" key
+    Shift,
+    /// The "Caps lock" key
+    CapsLock,
+    /// The "logo" key
+    Logo,
     /// The "Num lock" key
-    pub num_lock: bool,
+    NumLock,
+}
+
+const ALL_MODIFIERS: [Modifier; 6] = [
+    Modifier::Ctrl,
+    Modifier::Alt,
+    Modifier::Shift,
+    Modifier::CapsLock,
+    Modifier::Logo,
+    Modifier::NumLock,
+];
+
+impl Modifier {
+    /// The set holding only this modifier
+    pub fn flag(self) -> Modifiers {
+        match self {
+            Modifier::Ctrl => Modifiers::CTRL,
+            Modifier::Alt => Modifiers::ALT,
+            Modifier::Shift => Modifiers::SHIFT,
+            Modifier::CapsLock => Modifiers::CAPS_LOCK,
+            Modifier::Logo => Modifiers::LOGO,
+            Modifier::NumLock => Modifiers::NUM_LOCK,
+        }
+    }
+}
+
+/// The changes between two modifier states, see `ModifiersState::diff()`
+///
+/// The modifiers are listed in the order of the fields of `ModifiersState`.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct ModifiersDiff {
+    /// The modifiers that became active
+    pub activated: Vec<Modifier>,
+    /// The modifiers that became inactive
+    pub deactivated: Vec<Modifier>,
+    /// The modifiers whose status changed, with their previous and new status
+    ///
+    /// This includes the activated and deactivated modifiers, and for example a
+    /// modifier going from latched to locked.
+    pub status_changes: Vec<(Modifier, ModifierStatus, ModifierStatus)>,
+}
+
+impl ModifiersDiff {
+    /// Whether no modifier changed
+    pub fn is_empty(&self) -> bool {
+        self.activated.is_empty() && self.deactivated.is_empty() && self.status_changes.is_empty()
+    }
+}
+
+impl ModifiersStatus {
+    /// The status of a modifier
+    pub fn get(&self, modifier: Modifier) -> ModifierStatus {
+        match modifier {
+            Modifier::Ctrl => self.ctrl,
+            Modifier::Alt => self.alt,
+            Modifier::Shift => self.shift,
+            Modifier::CapsLock => self.caps_lock,
+            Modifier::Logo => self.logo,
+            Modifier::NumLock => self.num_lock,
+        }
+    }
+}
+
+impl Default for ModifiersState {
+    fn default() -> ModifiersState {
+        ModifiersState::new()
+    }
 }
 
 impl ModifiersState {
-    fn new() -> ModifiersState {
+    /// A state with the given modifiers active, without status
+    pub fn from_modifiers(modifiers: Modifiers) -> ModifiersState {
         ModifiersState {
-            ctrl: false,
-            alt: false,
-            shift: false,
-            caps_lock: false,
-            logo: false,
-            num_lock: false,
+            modifiers: modifiers,
+            status: ModifiersStatus::default(),
+        }
+    }
+
+    /// Whether a modifier is active
+    pub fn is_active(&self, modifier: Modifier) -> bool {
+        self.modifiers.contains(modifier.flag())
+    }
+
+    /// Whether the "control" key is active
+    pub fn ctrl(&self) -> bool {
+        self.modifiers.contains(Modifiers::CTRL)
+    }
+
+    /// Whether the "alt" key is active
+    pub fn alt(&self) -> bool {
+        self.modifiers.contains(Modifiers::ALT)
+    }
+
+    /// Whether the "shift" key is active
+    pub fn shift(&self) -> bool {
+        self.modifiers.contains(Modifiers::SHIFT)
+    }
+
+    /// Whether the "Caps lock" key is active
+    pub fn caps_lock(&self) -> bool {
+        self.modifiers.contains(Modifiers::CAPS_LOCK)
+    }
+
+    /// Whether the "logo" key is active
+    pub fn logo(&self) -> bool {
+        self.modifiers.contains(Modifiers::LOGO)
+    }
+
+    /// Whether the "Num lock" key is active
+    pub fn num_lock(&self) -> bool {
+        self.modifiers.contains(Modifiers::NUM_LOCK)
+    }
+
+    /// Compute which modifiers changed from `old` to `new`
+    pub fn diff(old: &ModifiersState, new: &ModifiersState) -> ModifiersDiff {
+        let mut diff = ModifiersDiff::default();
+        for &modifier in &ALL_MODIFIERS {
+            match (old.is_active(modifier), new.is_active(modifier)) {
+                (false, true) => diff.activated.push(modifier),
+                (true, false) => diff.deactivated.push(modifier),
+                _ => {}
+            }
+            let (old_status, new_status) = (old.status.get(modifier), new.status.get(modifier));
+            if old_status != new_status {
+                diff.status_changes.push((modifier, old_status, new_status));
+            }
         }
+        diff
+    }
+
+    fn new() -> ModifiersState {
+        ModifiersState::from_modifiers(Modifiers::empty())
     }
 
     fn update_with(&mut self, state: *mut ffi::xkb_state) {
-        self.ctrl = unsafe {
-            (XKBH.xkb_state_mod_name_is_active)(
-                state,
-                ffi::XKB_MOD_NAME_CTRL.as_ptr() as *const c_char,
-                xkb_state_component::XKB_STATE_MODS_EFFECTIVE,
-            ) > 0
-        };
-        self.alt = unsafe {
-            (XKBH.xkb_state_mod_name_is_active)(
-                state,
-                ffi::XKB_MOD_NAME_ALT.as_ptr() as *const c_char,
-                xkb_state_component::XKB_STATE_MODS_EFFECTIVE,
-            ) > 0
+        let is_active = |name: &[u8], component: xkb_state_component| unsafe {
+            (XKBH.xkb_state_mod_name_is_active)(state, name.as_ptr() as *const c_char, component) > 0
         };
-        self.shift = unsafe {
-            (XKBH.xkb_state_mod_name_is_active)(
-                state,
-                ffi::XKB_MOD_NAME_SHIFT.as_ptr() as *const c_char,
-                xkb_state_component::XKB_STATE_MODS_EFFECTIVE,
-            ) > 0
-        };
-        self.caps_lock = unsafe {
-            (XKBH.xkb_state_mod_name_is_active)(
-                state,
-                ffi::XKB_MOD_NAME_CAPS.as_ptr() as *const c_char,
-                xkb_state_component::XKB_STATE_MODS_EFFECTIVE,
-            ) > 0
-        };
-        self.logo = unsafe {
-            (XKBH.xkb_state_mod_name_is_active)(
-                state,
-                ffi::XKB_MOD_NAME_LOGO.as_ptr() as *const c_char,
-                xkb_state_component::XKB_STATE_MODS_EFFECTIVE,
-            ) > 0
+        let status = |name: &[u8]| {
+            if is_active(name, xkb_state_component::XKB_STATE_MODS_LOCKED) {
+                ModifierStatus::Locked
+            } else if is_active(name, xkb_state_component::XKB_STATE_MODS_LATCHED) {
+                ModifierStatus::Latched
+            } else if is_active(name, xkb_state_component::XKB_STATE_MODS_DEPRESSED) {
+                ModifierStatus::Depressed
+            } else {
+                ModifierStatus::Inactive
+            }
         };
-        self.num_lock = unsafe {
-            (XKBH.xkb_state_mod_name_is_active)(
-                state,
-                ffi::XKB_MOD_NAME_NUM.as_ptr() as *const c_char,
-                xkb_state_component::XKB_STATE_MODS_EFFECTIVE,
-            ) > 0
+        let effective = xkb_state_component::XKB_STATE_MODS_EFFECTIVE;
+        self.modifiers = Modifiers::from_names(|name| is_active(name, effective));
+        self.status = ModifiersStatus {
+            ctrl: status(ffi::XKB_MOD_NAME_CTRL),
+            alt: status(ffi::XKB_MOD_NAME_ALT),
+            shift: status(ffi::XKB_MOD_NAME_SHIFT),
+            caps_lock: status(ffi::XKB_MOD_NAME_CAPS),
+            logo: status(ffi::XKB_MOD_NAME_LOGO),
+            num_lock: status(ffi::XKB_MOD_NAME_NUM),
         };
     }
 }
 
+lazy_static!(
+    static ref NEXT_KEYBOARD_ID: AtomicUsize = AtomicUsize::new(0);
+);
+
+/// The identity of a keyboard registered with this crate
+///
+/// It is given to every callback, so that logs and per-device settings can tell
+/// the keyboards apart.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct KeyboardIdentity {
+    /// An id unique among the keyboards registered in this process
+    pub id: usize,
+    /// The name of the seat of the keyboard, like `"seat0"`, if known
+    ///
+    /// It is set from `KeyboardOptions::seat_name` or
+    /// `KeyboardHandle::set_seat_name()`.
+    pub seat_name: Option<String>,
+    /// The data attached to the keyboard at registration, see `KeyboardOptions::context`
+    pub context: Option<KeyboardContext>,
+}
+
+/// Data attached to a keyboard at registration, and given to its callbacks
+///
+/// It decouples the configuration chosen at registration from the mutable data of
+/// the application: put it in `KeyboardOptions::context` and get it back in the
+/// callbacks with `identity.context.as_ref().and_then(|c| c.get::<T>())`.
+///
+/// Clones share the same data. Comparisons and hashing use the identity of the data,
+/// not its value.
+#[derive(Clone)]
+pub struct KeyboardContext {
+    data: Arc<dyn Any + Send>,
+}
+
+impl KeyboardContext {
+    /// Wrap some data
+    pub fn new<T: Any + Send>(data: T) -> KeyboardContext {
+        KeyboardContext { data: Arc::new(data) }
+    }
+
+    /// The data, if it has type `T`
+    pub fn get<T: Any>(&self) -> Option<&T> {
+        self.data.downcast_ref()
+    }
+
+    fn address(&self) -> usize {
+        &*self.data as *const _ as *const u8 as usize
+    }
+}
+
+impl fmt::Debug for KeyboardContext {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "KeyboardContext({:#x})", self.address())
+    }
+}
+
+impl PartialEq for KeyboardContext {
+    fn eq(&self, other: &KeyboardContext) -> bool {
+        self.address() == other.address()
+    }
+}
+
+impl Eq for KeyboardContext {}
+
+impl Hash for KeyboardContext {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.address().hash(state)
+    }
+}
+
 unsafe impl Send for KbState {}
 
 impl KbState {
-    fn update_modifiers(&mut self, mods_depressed: u32, mods_latched: u32, mods_locked: u32, group: u32) {
+    fn update_modifiers(&mut self, masks: StateMasks) {
+        let _no_alloc = kbd_no_alloc!("modifiers update");
+        self.raw_masks = masks;
+        self.apply_modifiers();
+    }
+
+    /// Apply the last modifiers received, adjusted to the remappings
+    fn apply_modifiers(&mut self) {
         if !self.ready() {
             return;
         }
+        let masks = if self.options.modifier_remaps.is_empty() {
+            self.raw_masks
+        } else {
+            let mods = unsafe {
+                let index = |name: &[u8]| {
+                    (XKBH.xkb_keymap_mod_get_index)(self.xkb_keymap, name.as_ptr() as *const c_char)
+                };
+                RemapMods {
+                    ctrl: index(ffi::XKB_MOD_NAME_CTRL),
+                    caps: index(ffi::XKB_MOD_NAME_CAPS),
+                    alt: index(ffi::XKB_MOD_NAME_ALT),
+                    logo: index(ffi::XKB_MOD_NAME_LOGO),
+                }
+            };
+            remap_masks(
+                &self.options.modifier_remaps,
+                mods,
+                &self.held_keys,
+                self.raw_masks,
+            )
+        };
+        let _span = kbd_span!(
+            "update_modifiers",
+            masks.depressed,
+            masks.latched,
+            masks.locked,
+            masks.depressed_layout,
+            masks.latched_layout,
+            masks.locked_layout
+        );
         let mask = unsafe {
             (XKBH.xkb_state_update_mask)(
                 self.xkb_state,
-                mods_depressed,
-                mods_latched,
-                mods_locked,
-                0,
-                0,
-                group,
+                masks.depressed,
+                masks.latched,
+                masks.locked,
+                masks.depressed_layout,
+                masks.latched_layout,
+                masks.locked_layout,
             )
         };
-        if mask.contains(xkb_state_component::XKB_STATE_MODS_EFFECTIVE) {
-            // effective value of mods have changed, we need to update our state
+        let mods_components = xkb_state_component::XKB_STATE_MODS_DEPRESSED
+            | xkb_state_component::XKB_STATE_MODS_LATCHED
+            | xkb_state_component::XKB_STATE_MODS_LOCKED
+            | xkb_state_component::XKB_STATE_MODS_EFFECTIVE;
+        if mask.intersects(mods_components) {
+            if self.options.repeat_policy.stop_on_modifiers {
+                self.repeat = None;
+            }
+            // value of mods have changed, we need to update our state
             self.mods_state.update_with(self.xkb_state);
+            kbd_event!(mods = ?self.mods_state, "modifiers changed");
         }
     }
 
-    fn get_one_sym_raw(&mut self, keycode: u32) -> u32 {
+    /// Convert an evdev keycode to the xkb keycode it is translated as
+    fn xkb_keycode(&self, keycode: u32) -> u32 {
+        keymap::to_xkb_keycode(remap_keycode(&self.options.modifier_remaps, keycode))
+    }
+
+    /// The XKB name of a physical key
+    fn key_name(&self, keycode: u32) -> Option<String> {
         if !self.ready() {
-            return 0;
+            return None;
+        }
+        let xkb_keycode = keymap::to_xkb_keycode(keycode);
+        let name = unsafe { (XKBH.xkb_keymap_key_get_name)(self.xkb_keymap, xkb_keycode) };
+        if name.is_null() {
+            None
+        } else {
+            Some(unsafe { CStr::from_ptr(name) }.to_string_lossy().into_owned())
         }
-        unsafe { (XKBH.xkb_state_key_get_one_sym)(self.xkb_state, keycode + 8) }
     }
 
-    fn get_utf8_raw(&mut self, keycode: u32) -> Option<String> {
+    /// Whether a key repeats when held, according to the keymap
+    fn key_repeats(&self, keycode: u32) -> bool {
+        if !self.ready() {
+            return false;
+        }
+        unsafe { (XKBH.xkb_keymap_key_repeats)(self.xkb_keymap, self.xkb_keycode(keycode)) > 0 }
+    }
+
+    /// The repeat rate and delay in use
+    fn effective_repeat_info(&self) -> (i32, i32) {
+        self.options
+            .repeat_info
+            .or(self.repeat_info)
+            .unwrap_or(DEFAULT_REPEAT_INFO)
+    }
+
+    /// The modifiers consumed to translate a key, in the current state
+    fn consumed_mods(&self, keycode: u32) -> ModifiersState {
+        let mut consumed = ModifiersState::new();
+        if !self.ready() {
+            return consumed;
+        }
+        let xkb_keycode = self.xkb_keycode(keycode);
+        let is_consumed = |name: &[u8]| unsafe {
+            let index = (XKBH.xkb_keymap_mod_get_index)(self.xkb_keymap, name.as_ptr() as *const c_char);
+            (XKBH.xkb_state_mod_index_is_consumed)(self.xkb_state, xkb_keycode, index) > 0
+        };
+        consumed.modifiers = Modifiers::from_names(is_consumed);
+        consumed
+    }
+
+    /// The location of the first level of a key, in the layout it is translated with
+    fn first_level(&self, keycode: u32) -> Option<KeyLocation> {
         if !self.ready() {
             return None;
         }
-        let size =
-            unsafe { (XKBH.xkb_state_key_get_utf8)(self.xkb_state, keycode + 8, ptr::null_mut(), 0) } + 1;
-        if size <= 1 {
+        let xkb_keycode = self.xkb_keycode(keycode);
+        if xkb_keycode == ffi::XKB_KEYCODE_INVALID {
             return None;
+        }
+        let layout = unsafe { (XKBH.xkb_state_key_get_layout)(self.xkb_state, xkb_keycode) };
+        Some(KeyLocation {
+            keycode: xkb_keycode - 8,
+            layout: layout,
+            level: 0,
+        })
+    }
+
+    /// The keysyms of a key in the layouts other than the one it is translated with
+    ///
+    /// For each layout, the keysym at the shift level of the current modifiers and
+    /// the keysym of the first level, or 0 for levels without exactly one keysym.
+    fn other_layouts_syms(&self, keycode: u32) -> Vec<(u32, u32)> {
+        if !self.ready() {
+            return Vec::new();
+        }
+        let xkb_keycode = self.xkb_keycode(keycode);
+        let keymap = self.xkb_keymap;
+        let level_sym = |layout, level| unsafe {
+            let mut syms: *const ffi::xkb_keysym_t = ptr::null();
+            let count =
+                (XKBH.xkb_keymap_key_get_syms_by_level)(keymap, xkb_keycode, layout, level, &mut syms);
+            if count == 1 && !syms.is_null() {
+                *syms
+            } else {
+                0
+            }
         };
-        let mut buffer = Vec::with_capacity(size as usize);
         unsafe {
-            buffer.set_len(size as usize);
-            (XKBH.xkb_state_key_get_utf8)(
-                self.xkb_state,
-                keycode + 8,
-                buffer.as_mut_ptr() as *mut _,
-                size as usize,
-            );
-        };
-        // remove the final `\0`
-        buffer.pop();
-        // libxkbcommon will always provide valid UTF8
-        Some(unsafe { String::from_utf8_unchecked(buffer) })
+            let current = (XKBH.xkb_state_key_get_layout)(self.xkb_state, xkb_keycode);
+            let count = (XKBH.xkb_keymap_num_layouts_for_key)(keymap, xkb_keycode);
+            (0..count)
+                .filter(|&layout| layout != current)
+                .map(|layout| {
+                    let level = (XKBH.xkb_state_key_get_level)(self.xkb_state, xkb_keycode, layout);
+                    (level_sym(layout, level), level_sym(layout, 0))
+                })
+                .collect()
+        }
     }
 
-    fn compose_feed(&mut self, keysym: u32) -> Option<ffi::xkb_compose_feed_result> {
-        if !self.ready() || self.xkb_compose_state.is_null() {
-            return None;
+    /// The index of the layout a key is translated with, 0 if no keymap is loaded
+    fn key_layout(&self, keycode: u32) -> u32 {
+        if !self.ready() {
+            return 0;
+        }
+        let xkb_keycode = self.xkb_keycode(keycode);
+        if xkb_keycode == ffi::XKB_KEYCODE_INVALID {
+            return 0;
+        }
+        match unsafe { (XKBH.xkb_state_key_get_layout)(self.xkb_state, xkb_keycode) } {
+            ffi::XKB_LAYOUT_INVALID => 0,
+            layout => layout,
         }
-        Some(unsafe {
-            (XKBH.xkb_compose_state_feed)(self.xkb_compose_state, keysym)
-        })
     }
 
-    fn compose_status(&mut self) -> Option<ffi::xkb_compose_status> {
-        if !self.ready() || self.xkb_compose_state.is_null() {
-            return None;
+    fn get_one_sym_raw(&mut self, keycode: u32) -> u32 {
+        if !self.ready() {
+            return 0;
+        }
+        let sym = unsafe { (XKBH.xkb_state_key_get_one_sym)(self.xkb_state, self.xkb_keycode(keycode)) };
+        if self.options.normalize_enter {
+            classify::normalize_enter(sym)
+        } else {
+            sym
         }
-        Some(unsafe {
-            (XKBH.xkb_compose_state_get_status)(self.xkb_compose_state)
-        })
     }
 
-    fn compose_get_utf8(&mut self) -> Option<String> {
-        if !self.ready() || self.xkb_compose_state.is_null() {
+    fn get_utf8_raw(&mut self, keycode: u32) -> Option<String> {
+        if !self.ready() {
             return None;
         }
-        let size =
-            unsafe { (XKBH.xkb_compose_state_get_utf8)(self.xkb_compose_state, ptr::null_mut(), 0) } + 1;
+        let keycode = self.xkb_keycode(keycode);
+        let size = unsafe { (XKBH.xkb_state_key_get_utf8)(self.xkb_state, keycode, ptr::null_mut(), 0) } + 1;
         if size <= 1 {
             return None;
         };
-        let mut buffer = Vec::with_capacity(size as usize);
+        let mut buffer = vec![0u8; size as usize];
         unsafe {
-            buffer.set_len(size as usize);
-            (XKBH.xkb_compose_state_get_utf8)(
-                self.xkb_compose_state,
+            (XKBH.xkb_state_key_get_utf8)(
+                self.xkb_state,
+                keycode,
                 buffer.as_mut_ptr() as *mut _,
                 size as usize,
             );
@@ -202,24 +1017,220 @@ impl KbState {
         Some(unsafe { String::from_utf8_unchecked(buffer) })
     }
 
-    fn new() -> Result<KbState, MappedKeyboardError> {
-        let xkbh = match ffi::XKBCOMMON_OPTION.as_ref() {
-            Some(h) => h,
-            None => return Err(MappedKeyboardError::XKBNotFound),
-        };
-        let xkb_context = unsafe { (xkbh.xkb_context_new)(ffi::xkb_context_flags::XKB_CONTEXT_NO_FLAGS) };
-        if xkb_context.is_null() {
-            return Err(MappedKeyboardError::XKBNotFound);
+    fn compose_reset(&mut self) {
+        if let Some(ref mut compose) = self.compose {
+            compose.reset();
         }
+        self.compose_text.clear();
+    }
 
-        let mut me = KbState {
-            xkb_context: xkb_context,
+    /// Make the compose state of another context active, putting the current one aside
+    fn switch_compose_context(&mut self, id: Option<u64>) {
+        if self.compose_context == id {
+            return;
+        }
+        let previous = ::std::mem::replace(&mut self.compose_context, id);
+        let machine = match self.compose.take() {
+            Some(machine) => machine,
+            // compose is not available
+            None => return,
+        };
+        let text = ::std::mem::replace(&mut self.compose_text, String::new());
+        let index = self.compose_contexts.iter().position(|c| c.id == id);
+        let restored = index.map(|index| self.compose_contexts.remove(index));
+        // contexts without a pending sequence are not kept, a fresh state replaces them
+        let next = if machine.pending().is_empty() {
+            match restored {
+                Some(_) => None,
+                None => Some(machine),
+            }
+        } else {
+            let fresh = match restored {
+                Some(_) => None,
+                None => machine.fresh(),
+            };
+            self.compose_contexts.push(ComposeContext {
+                id: previous,
+                machine: machine,
+                text: text,
+                last_time: self.compose_last_time,
+            });
+            fresh
+        };
+        match restored {
+            Some(context) => {
+                self.compose = Some(context.machine);
+                self.compose_text = context.text;
+                self.compose_last_time = context.last_time;
+            }
+            None => {
+                self.compose = next;
+                self.compose_last_time = 0;
+            }
+        }
+    }
+
+    /// The keysyms of the pending compose sequence
+    fn compose_pending(&self) -> &[u32] {
+        match self.compose {
+            Some(ref compose) => compose.pending(),
+            None => &[],
+        }
+    }
+
+    /// Cancel the pending compose sequence if it timed out at `time`
+    fn check_compose_timeout(&mut self, time: u32) {
+        let timeout = match self.options.compose_timeout {
+            Some(timeout) => timeout,
+            None => return,
+        };
+        if self.compose_pending().is_empty() {
+            return;
+        }
+        if u64::from(time.wrapping_sub(self.compose_last_time)) >= duration_ms(timeout) {
+            self.compose_reset();
+            self.compose_cancelled_event(ComposeCancelReason::Timeout);
+        }
+    }
+
+    /// Notify that the pending compose sequence was cancelled
+    fn compose_cancelled_event(&mut self, reason: ComposeCancelReason) {
+        if let Some(ref diagnostics) = self.options.diagnostics {
+            diagnostics.record_cancelled();
+        }
+        self.compose_events.push(ComposeEvent::Cancelled(reason));
+    }
+
+    /// Compute the text produced by a key press, feeding it to the compose state
+    fn press_utf8(&mut self, keycode: u32, keysym: u32, time: u32) -> Option<String> {
+        if !self.ready() {
+            return None;
+        }
+        let passthrough = self.options.compose_policy == ComposePolicy::PassThrough;
+        let (status, pending) = match self.compose {
+            // compose is not available
+            None => return self.get_utf8_raw(keycode),
+            Some(ref mut compose) => {
+                // kept to restore the sequence if the key does not continue it
+                let pending = if passthrough { compose.pending().to_vec() } else { Vec::new() };
+                // modifier keysyms are not part of sequences and produce no text
+                if !compose.feed(keysym) {
+                    return None;
+                }
+                (compose.status(), pending)
+            }
+        };
+        match status {
+            ComposeStatus::Composing => {
+                self.compose_last_time = time;
+                if let Some(txt) = self.get_utf8_raw(keycode) {
+                    self.compose_text.push_str(&txt);
+                }
+                let preedit = compose::preedit(self.compose_pending());
+                self.compose_events.push(ComposeEvent::Preedit(preedit));
+                None
+            }
+            ComposeStatus::Composed => {
+                if let Some(ref diagnostics) = self.options.diagnostics {
+                    diagnostics.record_composed();
+                }
+                self.compose_text.clear();
+                let utf8 = self.compose.as_ref().and_then(|compose| compose.utf8());
+                if let Some(ref txt) = utf8 {
+                    self.compose_events.push(ComposeEvent::Committed(txt.clone()));
+                }
+                utf8
+            }
+            ComposeStatus::Cancelled => self.compose_cancelled(keycode, &pending),
+            ComposeStatus::Nothing => self.get_utf8_raw(keycode),
+        }
+    }
+
+    /// The pending sequence was cancelled by `keycode`, apply the compose policy
+    fn compose_cancelled(&mut self, keycode: u32, pending: &[u32]) -> Option<String> {
+        match self.options.compose_policy {
+            ComposePolicy::Cancel => {
+                self.compose_text.clear();
+                self.compose_cancelled_event(ComposeCancelReason::NoMatch);
+                None
+            }
+            ComposePolicy::Buffer => {
+                self.compose_cancelled_event(ComposeCancelReason::NoMatch);
+                let mut txt = ::std::mem::replace(&mut self.compose_text, String::new());
+                if let Some(key_txt) = self.get_utf8_raw(keycode) {
+                    txt.push_str(&key_txt);
+                }
+                if txt.is_empty() {
+                    None
+                } else {
+                    Some(txt)
+                }
+            }
+            ComposePolicy::PassThrough => {
+                // restore the pending sequence by feeding it again
+                if let Some(ref mut compose) = self.compose {
+                    compose.reset();
+                    for &sym in pending {
+                        compose.feed(sym);
+                    }
+                }
+                self.get_utf8_raw(keycode)
+            }
+        }
+    }
+
+    fn new(options: KeyboardOptions) -> Result<KbState, MappedKeyboardError> {
+        let seat_name = options.seat_name.clone();
+        let context = options.context.clone();
+        let xkb_context = match ffi::XKBCOMMON_OPTION.as_ref() {
+            Some(xkbh) => unsafe {
+                xkb_acquire!(Context, (xkbh.xkb_context_new)(ffi::xkb_context_flags::XKB_CONTEXT_NO_FLAGS))
+            },
+            None if options.allow_degraded => ptr::null_mut(),
+            None => return Err(MappedKeyboardError::library_not_found()),
+        };
+        if xkb_context.is_null() && !options.allow_degraded {
+            return Err(MappedKeyboardError::XKBNotFound);
+        }
+        if xkb_context.is_null() {
+            kbd_event!("libxkbcommon is not available, running degraded");
+        }
+
+        let mut me = KbState {
+            xkb_context: xkb_context,
             xkb_keymap: ptr::null_mut(),
             xkb_state: ptr::null_mut(),
-            xkb_compose_table: ptr::null_mut(),
-            xkb_compose_state: ptr::null_mut(),
+            compose: None,
             mods_state: ModifiersState::new(),
             locked: false,
+            options: options,
+            compose_text: String::new(),
+            compose_last_time: 0,
+            compose_context: None,
+            compose_contexts: Vec::new(),
+            compose_events: Vec::new(),
+            macro_state: TextMacroState::new(),
+            held_keys: Vec::new(),
+            keys_at_leave: Vec::new(),
+            gesture_state: GestureState::new(),
+            input_mode: InputModeState::default(),
+            rmlvo: None,
+            compositor_keymap: None,
+            surface_keymaps: Vec::new(),
+            default_keymap: None,
+            repeat_info: None,
+            identity: KeyboardIdentity {
+                id: NEXT_KEYBOARD_ID.fetch_add(1, Ordering::Relaxed),
+                seat_name: seat_name,
+                context: context,
+            },
+            repeat: None,
+            repeat_paused: false,
+            raw_masks: StateMasks::default(),
+            reverse_index: None,
+            last_indicator: None,
+            protocol: ProtocolTracker::default(),
+            keyboard: None,
         };
 
         unsafe {
@@ -230,99 +1241,588 @@ impl KbState {
     }
 
     unsafe fn init_compose(&mut self) {
-        let locale = env::var_os("LC_ALL")
-            .or_else(|| env::var_os("LC_CTYPE"))
-            .or_else(|| env::var_os("LANG"))
-            .unwrap_or_else(|| "C".into());
-        let locale = CString::new(locale.into_vec()).unwrap();
-
-        let compose_table = (XKBH.xkb_compose_table_new_from_locale)(
-            self.xkb_context,
-            locale.as_ptr(),
-            ffi::xkb_compose_compile_flags::XKB_COMPOSE_COMPILE_NO_FLAGS,
-        );
-
-        if compose_table.is_null() {
-            // init of compose table failed, continue without compose
-            return;
-        }
-
-        let compose_state = (XKBH.xkb_compose_state_new)(
-            compose_table,
-            ffi::xkb_compose_state_flags::XKB_COMPOSE_STATE_NO_FLAGS,
-        );
-
-        if compose_state.is_null() {
-            // init of compose state failed, continue without compose
-            (XKBH.xkb_compose_table_unref)(compose_table);
+        if !self.options.compose || self.degraded() {
             return;
         }
-
-        self.xkb_compose_table = compose_table;
-        self.xkb_compose_state = compose_state;
+        // continue without compose if the table cannot be loaded
+        let (locale, user_file) = match self.options.compose_locale {
+            Some(ref locale) => match CString::new(locale.clone()) {
+                Ok(locale) => (locale, false),
+                // not a valid locale
+                Err(_) => return,
+            },
+            None => (compose::locale(), true),
+        };
+        let extra = &self.options.compose_sequences;
+        self.compose = ComposeMachine::with_context(self.xkb_context, &locale, extra, user_file);
     }
 
     unsafe fn post_init(&mut self, xkb_keymap: *mut ffi::xkb_keymap) {
-        let xkb_state = (XKBH.xkb_state_new)(xkb_keymap);
+        let xkb_state = xkb_acquire!(State, (XKBH.xkb_state_new)(xkb_keymap));
         self.xkb_keymap = xkb_keymap;
         self.xkb_state = xkb_state;
         self.mods_state.update_with(xkb_state);
+        // the modifiers may have been received before the keymap
+        self.apply_modifiers();
     }
 
     unsafe fn de_init(&mut self) {
-        (XKBH.xkb_state_unref)(self.xkb_state);
+        if self.degraded() {
+            return;
+        }
+        xkb_release!(State, XKBH.xkb_state_unref, self.xkb_state);
         self.xkb_state = ptr::null_mut();
-        (XKBH.xkb_keymap_unref)(self.xkb_keymap);
+        xkb_release!(Keymap, XKBH.xkb_keymap_unref, self.xkb_keymap);
+        self.xkb_keymap = ptr::null_mut();
+        self.reverse_index = None;
+        self.repeat = None;
+    }
+
+    /// Use a surface keymap instead of the keyboard's, until `leave_surface_keymap()`
+    unsafe fn enter_surface_keymap(&mut self, xkb_keymap: *mut ffi::xkb_keymap) {
+        self.leave_surface_keymap();
+        self.default_keymap = Some((self.xkb_keymap, self.xkb_state));
         self.xkb_keymap = ptr::null_mut();
+        self.xkb_state = ptr::null_mut();
+        self.reverse_index = None;
+        self.repeat = None;
+        self.post_init(xkb_acquire!(Keymap, (XKBH.xkb_keymap_ref)(xkb_keymap)));
+    }
+
+    /// Go back to the keyboard's keymap if a surface keymap is active
+    ///
+    /// Returns the surface keymap that was active, or null.
+    unsafe fn leave_surface_keymap(&mut self) -> *mut ffi::xkb_keymap {
+        let (xkb_keymap, xkb_state) = match self.default_keymap.take() {
+            Some(default) => default,
+            None => return ptr::null_mut(),
+        };
+        // still referenced by `surface_keymaps`
+        let surface_keymap = self.xkb_keymap;
+        self.de_init();
+        self.xkb_keymap = xkb_keymap;
+        self.xkb_state = xkb_state;
+        if self.ready() {
+            self.mods_state.update_with(xkb_state);
+        }
+        self.apply_modifiers();
+        surface_keymap
     }
 
-    unsafe fn init_with_fd(&mut self, fd: RawFd, size: usize) {
-        let map = MmapOptions::new().len(size).map(&File::from_raw_fd(fd)).unwrap();
+    /// Keep a keymap sent by the compositor, closing its fd
+    ///
+    /// The previous keymap is kept if it cannot be mapped.
+    unsafe fn store_compositor_keymap(&mut self, fd: RawFd, size: usize) -> Result<(), KeyboardError> {
+        // the file owns the fd, so that it is closed on every path
+        let file = File::from_raw_fd(fd);
+        let map = match MmapOptions::new().len(size).map(&file) {
+            Ok(map) => map,
+            Err(error) => {
+                kbd_event!(size, "could not map the compositor keymap");
+                return Err(KeyboardError::KeymapMapFailed { error: error });
+            }
+        };
+        let mut keymap = map[..].to_vec();
+        // xkb_keymap_new_from_string() needs a NUL-terminated string
+        if keymap.last() != Some(&0) {
+            keymap.push(0);
+        }
+        self.compositor_keymap = Some(keymap);
+        Ok(())
+    }
+
+    /// Handle a keymap sent by the compositor, closing its fd
+    ///
+    /// Returns the change from the previous keymap, if it was replaced.
+    unsafe fn receive_keymap(&mut self, format: KeymapFormat, fd: RawFd, size: usize)
+                             -> Result<Option<KeymapChange>, KeyboardError> {
+        match format {
+            KeymapFormat::XkbV1 => self.store_compositor_keymap(fd, size)?,
+            KeymapFormat::NoKeymap => {
+                // TODO: how to handle this (hopefully never occuring) case?
+                // the fd is sent all the same, and must be closed
+                drop(File::from_raw_fd(fd));
+                self.compositor_keymap = None;
+            }
+        }
+        if self.locked {
+            // state is locked, ignore keymap updates
+            kbd_event!("keymap is locked, ignoring compositor keymap");
+            return Ok(None);
+        }
+        self.load_compositor_keymap()
+    }
 
-        let xkb_keymap = (XKBH.xkb_keymap_new_from_string)(
+    /// Compile the last keymap sent by the compositor, or null if there is none
+    unsafe fn compile_compositor_keymap(&self) -> Result<*mut ffi::xkb_keymap, KeyboardError> {
+        let keymap = match self.compositor_keymap {
+            Some(ref keymap) => keymap,
+            None => return Ok(ptr::null_mut()),
+        };
+        if self.degraded() {
+            // the keymap cannot be compiled, keep delivering raw keycodes
+            return Ok(ptr::null_mut());
+        }
+        let _span = kbd_span!("keymap_compile", source = "compositor", size = keymap.len());
+        let xkb_keymap = xkb_acquire!(Keymap, (XKBH.xkb_keymap_new_from_string)(
             self.xkb_context,
-            map.as_ptr() as *const _,
+            keymap.as_ptr() as *const _,
             ffi::xkb_keymap_format::XKB_KEYMAP_FORMAT_TEXT_V1,
             ffi::xkb_keymap_compile_flags::XKB_KEYMAP_COMPILE_NO_FLAGS,
-        );
-
+        ));
         if xkb_keymap.is_null() {
-            panic!("Received invalid keymap from compositor.");
+            kbd_event!("invalid keymap from the compositor");
+            return Err(KeyboardError::InvalidKeymap);
         }
+        Ok(xkb_keymap)
+    }
 
-        self.post_init(xkb_keymap);
+    /// Replace the keymap with the last one sent by the compositor
+    ///
+    /// The current keymap is kept if it does not compile. Returns the change from
+    /// the previous keymap, if there was one.
+    unsafe fn load_compositor_keymap(&mut self) -> Result<Option<KeymapChange>, KeyboardError> {
+        let xkb_keymap = self.compile_compositor_keymap()?;
+        let surface_keymap = self.leave_surface_keymap();
+        let previous = if self.ready() {
+            let previous = keymap::describe(self.xkb_keymap, self.layout_names());
+            // new keymap, we first deinit to free resources
+            self.de_init();
+            Some(previous)
+        } else {
+            None
+        };
+        if !xkb_keymap.is_null() {
+            self.post_init(xkb_keymap);
+        }
+        let change = match previous {
+            Some(ref previous) if self.ready() => {
+                let new = keymap::describe(self.xkb_keymap, self.layout_names());
+                Some(KeymapChange::between(previous, &new))
+            }
+            _ => None,
+        };
+        if !surface_keymap.is_null() {
+            self.enter_surface_keymap(surface_keymap);
+        }
+        self.apply_modifiers();
+        Ok(change)
     }
 
     unsafe fn init_with_rmlvo(&mut self, names: ffi::xkb_rule_names) -> Result<(), MappedKeyboardError> {
-        let xkb_keymap = (XKBH.xkb_keymap_new_from_names)(
+        let xkb_keymap = self.compile_rmlvo(names)?;
+        self.post_init(xkb_keymap);
+        Ok(())
+    }
+
+    unsafe fn compile_rmlvo(&self, names: ffi::xkb_rule_names)
+                            -> Result<*mut ffi::xkb_keymap, MappedKeyboardError> {
+        if self.degraded() {
+            return Err(MappedKeyboardError::library_not_found());
+        }
+        let _span = kbd_span!("keymap_compile", source = "rmlvo");
+        let xkb_keymap = xkb_acquire!(Keymap, (XKBH.xkb_keymap_new_from_names)(
             self.xkb_context,
             &names,
             ffi::xkb_keymap_compile_flags::XKB_KEYMAP_COMPILE_NO_FLAGS,
-        );
+        ));
 
         if xkb_keymap.is_null() {
+            kbd_event!("RMLVO keymap compilation failed");
             return Err(MappedKeyboardError::BadNames);
         }
 
-        self.post_init(xkb_keymap);
+        Ok(xkb_keymap)
+    }
 
-        Ok(())
+    fn layout_names(&self) -> Vec<String> {
+        if !self.ready() {
+            return Vec::new();
+        }
+        unsafe { keymap_layout_names(self.xkb_keymap) }
+    }
+
+    fn active_layout(&self) -> Option<u32> {
+        if !self.ready() {
+            return None;
+        }
+        let count = unsafe { (XKBH.xkb_keymap_num_layouts)(self.xkb_keymap) };
+        (0..count).find(|&idx| unsafe {
+            (XKBH.xkb_state_layout_index_is_active)(
+                self.xkb_state,
+                idx,
+                xkb_state_component::XKB_STATE_LAYOUT_EFFECTIVE,
+            ) > 0
+        })
+    }
+
+    fn layout_indicator(&self) -> Option<LayoutIndicator> {
+        let index = match self.active_layout() {
+            Some(index) => index,
+            None => return None,
+        };
+        let name = self.layout_names().into_iter().nth(index as usize).unwrap_or_default();
+        // the layouts of a RMLVO are already short names
+        let short_name = self
+            .rmlvo
+            .as_ref()
+            .and_then(|rmlvo| rmlvo.layout.as_ref())
+            .and_then(|layouts| indicator::rmlvo_short_name(layouts, index))
+            .unwrap_or_else(|| indicator::short_name(&name));
+        Some(LayoutIndicator {
+            index: index,
+            short_name: short_name,
+            name: name,
+        })
+    }
+
+    /// The layout indicator, if it changed since it was last notified
+    fn indicator_change(&mut self) -> Option<LayoutIndicator> {
+        let indicator = self.layout_indicator();
+        if indicator.is_none() || indicator == self.last_indicator {
+            return None;
+        }
+        self.last_indicator = indicator.clone();
+        indicator
+    }
+
+    /// Track an event of the compositor, returning the violations to report
+    fn check_protocol<F>(&mut self, check: F) -> Vec<ProtocolViolation>
+    where
+        F: FnOnce(&mut ProtocolTracker) -> Vec<ProtocolViolation>,
+    {
+        let violations = check(&mut self.protocol);
+        if !self.options.strict {
+            return Vec::new();
+        }
+        if !violations.is_empty() {
+            kbd_event!(?violations, "protocol violations");
+        }
+        violations
+    }
+
+    /// The reverse index of the keymap, built on first use
+    fn reverse_index(&mut self) -> Option<&ReverseIndex> {
+        if !self.ready() {
+            return None;
+        }
+        if self.reverse_index.is_none() {
+            self.reverse_index = Some(unsafe { ReverseIndex::build(self.xkb_keymap) });
+        }
+        self.reverse_index.as_ref()
+    }
+
+    /// The modifiers of a mask of the modifiers of the keymap
+    fn mods_from_mask(&self, mask: u32) -> (ModifiersState, bool) {
+        let is_set = |name: &[u8]| {
+            let name = name.as_ptr() as *const c_char;
+            let index = unsafe { (XKBH.xkb_keymap_mod_get_index)(self.xkb_keymap, name) };
+            index < 32 && mask & (1 << index) != 0
+        };
+        let held = |set: bool| if set { ModifierStatus::Depressed } else { ModifierStatus::Inactive };
+        let mut mods = ModifiersState::from_modifiers(Modifiers::from_names(&is_set));
+        mods.status = ModifiersStatus {
+            ctrl: held(mods.ctrl()),
+            alt: held(mods.alt()),
+            shift: held(mods.shift()),
+            caps_lock: held(mods.caps_lock()),
+            logo: held(mods.logo()),
+            num_lock: held(mods.num_lock()),
+        };
+        (mods, is_set(ffi::XKB_MOD_NAME_MOD5))
+    }
+
+    /// The simplest way to type a character with the current keymap
+    fn can_type(&mut self, c: char) -> Option<KeyCombo> {
+        self.easiest_combo(keymap::char_keysyms(c))
+    }
+
+    // the easiest key and modifiers to type one of these keysyms
+    fn easiest_combo(&mut self, keysyms: Vec<u32>) -> Option<KeyCombo> {
+        let candidates: Vec<(u32, KeyLocation)> = match self.reverse_index() {
+            Some(index) => keysyms
+                .into_iter()
+                .flat_map(|keysym| index.lookup(keysym).iter().map(move |&location| (keysym, location)))
+                .collect(),
+            None => return None,
+        };
+        let lock = unsafe {
+            (XKBH.xkb_keymap_mod_get_index)(self.xkb_keymap, ffi::XKB_MOD_NAME_CAPS.as_ptr() as *const c_char)
+        };
+        let lock_mask = if lock < 32 { 1 << lock } else { 0 };
+        // prefer the layout of the key, then not toggling Caps Lock, then fewer modifiers
+        let mut best: Option<((bool, bool, u32), u32, KeyLocation, u32)> = None;
+        for (keysym, location) in candidates {
+            let xkb_keycode = location.keycode + 8;
+            let other_layout = self.key_layout(location.keycode) != location.layout;
+            let masks =
+                unsafe { keymap::level_mods(self.xkb_keymap, xkb_keycode, location.layout, location.level) };
+            for mask in masks.unwrap_or_else(Vec::new) {
+                let rank = (other_layout, mask & lock_mask != 0, mask.count_ones());
+                if best.as_ref().map_or(true, |best| rank < best.0) {
+                    best = Some((rank, keysym, location, mask));
+                }
+            }
+        }
+        best.map(|((other_layout, _, _), keysym, location, mask)| {
+            let (mods, alt_gr) = self.mods_from_mask(mask);
+            let base_keysym = unsafe {
+                keymap::level_keysym(self.xkb_keymap, location.keycode + 8, location.layout, 0)
+            };
+            KeyCombo {
+                keysym: keysym,
+                location: location,
+                mods: mods,
+                alt_gr: alt_gr,
+                mods_mask: mask,
+                other_layout: other_layout,
+                base_keysym: base_keysym.unwrap_or(0),
+            }
+        })
+    }
+
+    fn apply_serialized(&mut self, serialized: SerializedState) {
+        self.update_modifiers(StateMasks {
+            depressed: serialized.mods_depressed,
+            latched: serialized.mods_latched,
+            locked: serialized.mods_locked,
+            depressed_layout: serialized.layout_depressed,
+            latched_layout: serialized.layout_latched,
+            locked_layout: serialized.layout_locked,
+        })
+    }
+
+    fn serialize(&self) -> Option<SerializedState> {
+        if !self.ready() {
+            return None;
+        }
+        unsafe {
+            Some(SerializedState {
+                mods_depressed: (XKBH.xkb_state_serialize_mods)(
+                    self.xkb_state,
+                    xkb_state_component::XKB_STATE_MODS_DEPRESSED,
+                ),
+                mods_latched: (XKBH.xkb_state_serialize_mods)(
+                    self.xkb_state,
+                    xkb_state_component::XKB_STATE_MODS_LATCHED,
+                ),
+                mods_locked: (XKBH.xkb_state_serialize_mods)(
+                    self.xkb_state,
+                    xkb_state_component::XKB_STATE_MODS_LOCKED,
+                ),
+                group: (XKBH.xkb_state_serialize_layout)(
+                    self.xkb_state,
+                    xkb_state_component::XKB_STATE_LAYOUT_EFFECTIVE,
+                ),
+                layout_depressed: (XKBH.xkb_state_serialize_layout)(
+                    self.xkb_state,
+                    xkb_state_component::XKB_STATE_LAYOUT_DEPRESSED,
+                ),
+                layout_latched: (XKBH.xkb_state_serialize_layout)(
+                    self.xkb_state,
+                    xkb_state_component::XKB_STATE_LAYOUT_LATCHED,
+                ),
+                layout_locked: (XKBH.xkb_state_serialize_layout)(
+                    self.xkb_state,
+                    xkb_state_component::XKB_STATE_LAYOUT_LOCKED,
+                ),
+            })
+        }
+    }
+
+    fn debug_dump(&self) -> DebugDump {
+        DebugDump {
+            xkbcommon: ffi::xkbcommon_path(),
+            capabilities: xkb_capabilities(),
+            keymap_loaded: self.ready(),
+            keymap_locked: self.locked,
+            rmlvo: self.rmlvo.clone(),
+            layouts: self.layout_names(),
+            active_layout: self.active_layout(),
+            modifiers: self.mods_state,
+            compose_available: self.compose.is_some(),
+            compose_pending: self.compose_pending().to_vec(),
+            repeat_info: self.repeat_info,
+        }
+    }
+
+    fn state_report(&self) -> StateReport {
+        let led = |name: &[u8]| {
+            self.ready() && unsafe {
+                (XKBH.xkb_state_led_name_is_active)(self.xkb_state, name.as_ptr() as *const c_char) > 0
+            }
+        };
+        StateReport {
+            layouts: self.layout_names(),
+            active_layout: self.active_layout(),
+            modifiers: self.mods_state,
+            leds: LockLeds {
+                caps_lock: led(ffi::XKB_LED_NAME_CAPS),
+                num_lock: led(ffi::XKB_LED_NAME_NUM),
+                scroll_lock: led(ffi::XKB_LED_NAME_SCROLL),
+            },
+            compose: self.compose.as_ref().map(|compose| compose.status()),
+            compose_pending: self.compose_pending().to_vec(),
+            repeat_info: self.effective_repeat_info(),
+            repeat_paused: self.repeat_paused,
+        }
+    }
+
+    /// Generate the repeats of the held key that are due at `now`
+    fn repeat_events(&mut self, now: Instant) -> Vec<KeyEvent> {
+        if self.repeat_paused {
+            return Vec::new();
+        }
+        let mut repeat = match self.repeat.take() {
+            Some(repeat) => repeat,
+            None => return Vec::new(),
+        };
+        let mut events = Vec::new();
+        if repeat.next <= now {
+            let (keysym, layout, utf8, mods) = if self.options.repeat_policy.retranslate_on_modifiers {
+                let keysym = self.get_one_sym_raw(repeat.keycode);
+                let layout = self.key_layout(repeat.keycode);
+                (keysym, layout, self.get_utf8_raw(repeat.keycode), self.mods_state)
+            } else {
+                (repeat.keysym, repeat.layout, repeat.utf8.clone(), repeat.mods)
+            };
+            let kind = KeyKind::classify(keysym, utf8.as_ref().map(|s| &s[..]));
+            if self.options.repeat_policy.coalesce {
+                let skipped = repeat.skip_stale(now);
+                if skipped > 0 {
+                    kbd_event!(skipped, "dropping stale repeats");
+                    if let Some(ref diagnostics) = self.options.diagnostics {
+                        diagnostics.record_stale_repeats(skipped);
+                    }
+                }
+            }
+            // the repeats over the bound are skipped rather than generated and dropped
+            let due = repeat.due(now) as usize;
+            let policy = self.options.overflow_policy;
+            let (oldest, newest) = policy.bound(due, self.options.max_queued_events);
+            if oldest + newest > 0 {
+                kbd_event!(dropped = oldest + newest, "dropping repeats over the queue bound");
+                if let Some(ref diagnostics) = self.options.diagnostics {
+                    diagnostics.record_overflowed_repeats(oldest + newest);
+                }
+            }
+            repeat.skip(oldest as u32);
+            events.reserve(due - oldest - newest);
+            for _ in 0..due - oldest - newest {
+                let time = repeat.advance();
+                events.push(KeyEvent {
+                    serial: repeat.serial,
+                    time: time,
+                    mods: mods,
+                    rawkey: repeat.keycode,
+                    keysym: keysym,
+                    layout: layout,
+                    state: KeyState::Pressed,
+                    origin: KeyOrigin::Repeat,
+                    utf8: utf8.clone(),
+                    kind: kind,
+                    is_keypad: classify::is_keypad(repeat.keycode, keysym),
+                    nav: NavKey::from_keysym(keysym),
+                    repeat_count: repeat.count,
+                    // keys never repeat without a keymap
+                    degraded: false,
+                });
+            }
+            repeat.skip(newest as u32);
+        }
+        self.repeat = Some(repeat);
+        events
+    }
+
+    /// Start or stop the repeat of a key, following the repeat policy
+    fn track_repeat(&mut self, keycode: u32, serial: u32, time: u32, keysym: u32, layout: u32,
+                    key_state: KeyState, now: Instant) {
+        if key_state == KeyState::Pressed {
+            let policy = self.options.repeat_policy;
+            if self.key_repeats(keycode) && (self.repeat.is_none() || policy.new_press_cancels) {
+                self.repeat = RepeatState::start(
+                    keycode,
+                    serial,
+                    time,
+                    keysym,
+                    layout,
+                    // the text of the key alone, not the end of a compose
+                    // sequence or a text macro
+                    self.get_utf8_raw(keycode),
+                    self.mods_state,
+                    self.effective_repeat_info(),
+                    now,
+                );
+            }
+        } else if self.repeat.as_ref().map(|r| r.keycode) == Some(keycode) {
+            self.repeat = None;
+        }
+    }
+
+    /// Whether the registered `wl_keyboard` can still receive events
+    fn keyboard_alive(&self) -> bool {
+        self.keyboard.as_ref().map_or(false, |keyboard| keyboard.is_alive())
     }
 
     #[inline]
     fn ready(&self) -> bool {
         !self.xkb_state.is_null()
     }
+
+    /// Whether the keyboard runs without libxkbcommon
+    #[inline]
+    fn degraded(&self) -> bool {
+        self.xkb_context.is_null()
+    }
+}
+
+unsafe fn keymap_layout_names(keymap: *mut ffi::xkb_keymap) -> Vec<String> {
+    let count = (XKBH.xkb_keymap_num_layouts)(keymap);
+    (0..count)
+        .map(|idx| {
+            let name = (XKBH.xkb_keymap_layout_get_name)(keymap, idx);
+            if name.is_null() {
+                String::new()
+            } else {
+                CStr::from_ptr(name).to_string_lossy().into_owned()
+            }
+        })
+        .collect()
+}
+
+impl KbState {
+    /// Free the keymaps and compose tables of a keyboard that will not be used anymore
+    fn release_resources(&mut self) {
+        unsafe {
+            self.leave_surface_keymap();
+            for surface_keymap in self.surface_keymaps.drain(..) {
+                xkb_release!(Keymap, XKBH.xkb_keymap_unref, surface_keymap.xkb_keymap);
+            }
+            self.de_init();
+        }
+        self.compose = None;
+        self.compose_contexts.clear();
+        self.compose_text.clear();
+        self.compositor_keymap = None;
+        self.held_keys.clear();
+        self.repeat = None;
+        self.keyboard = None;
+    }
 }
 
 impl Drop for KbState {
     fn drop(&mut self) {
+        if self.degraded() {
+            return;
+        }
         unsafe {
-            (XKBH.xkb_compose_state_unref)(self.xkb_compose_state);
-            (XKBH.xkb_compose_table_unref)(self.xkb_compose_table);
-            (XKBH.xkb_state_unref)(self.xkb_state);
-            (XKBH.xkb_keymap_unref)(self.xkb_keymap);
-            (XKBH.xkb_context_unref)(self.xkb_context);
+            self.leave_surface_keymap();
+            for surface_keymap in self.surface_keymaps.drain(..) {
+                xkb_release!(Keymap, XKBH.xkb_keymap_unref, surface_keymap.xkb_keymap);
+            }
+            xkb_release!(State, XKBH.xkb_state_unref, self.xkb_state);
+            xkb_release!(Keymap, XKBH.xkb_keymap_unref, self.xkb_keymap);
+            xkb_release!(Context, XKBH.xkb_context_unref, self.xkb_context);
         }
     }
 }
@@ -330,10 +1830,89 @@ impl Drop for KbState {
 #[derive(Debug)]
 /// An error that occured while trying to initialize a mapped keyboard
 pub enum MappedKeyboardError {
-    /// libxkbcommon is not available
+    /// libxkbcommon failed to initialize
     XKBNotFound,
+    /// No libxkbcommon library could be loaded
+    ///
+    /// See `set_xkbcommon_libraries()` to try other ones.
+    LibraryNotFound {
+        /// The libraries that were tried, in order
+        tried: Vec<String>,
+    },
     /// Provided RMLVO sepcified a keymap that would not be loaded
     BadNames,
+    /// A field of the provided RMLVO contains a NUL byte
+    InteriorNul {
+        /// The name of the field, like `"layout"`
+        field: &'static str,
+        /// The value of the field
+        value: String,
+    },
+}
+
+#[derive(Debug)]
+/// An error that occured while handling the events of a keyboard
+///
+/// It is reported by the `error` callback, and the keyboard keeps working with its
+/// current keymap.
+pub enum KeyboardError {
+    /// The keymap sent by the compositor could not be mapped in memory
+    KeymapMapFailed {
+        /// The error of the mapping
+        error: io::Error,
+    },
+    /// The keymap sent by the compositor could not be compiled
+    InvalidKeymap,
+    /// The compositor violated the protocol, see `KeyboardOptions::strict`
+    Protocol {
+        /// The violation
+        violation: ProtocolViolation,
+    },
+}
+
+impl MappedKeyboardError {
+    fn library_not_found() -> MappedKeyboardError {
+        MappedKeyboardError::LibraryNotFound {
+            tried: ffi::xkbcommon_tried(),
+        }
+    }
+}
+
+/// An iterator over the keycodes of a `wl_keyboard` key array
+///
+/// See `raw_keys()`.
+#[derive(Clone, Debug)]
+pub struct RawKeys<'a> {
+    chunks: Chunks<'a, u8>,
+}
+
+impl<'a> Iterator for RawKeys<'a> {
+    type Item = u32;
+
+    fn next(&mut self) -> Option<u32> {
+        // a trailing incomplete keycode is ignored
+        let bytes = match self.chunks.next() {
+            Some(bytes) if bytes.len() == 4 => bytes,
+            _ => return None,
+        };
+        let (b0, b1, b2, b3) = (bytes[0] as u32, bytes[1] as u32, bytes[2] as u32, bytes[3] as u32);
+        // the array is in the byte order of the host
+        if cfg!(target_endian = "little") {
+            Some(b0 | b1 << 8 | b2 << 16 | b3 << 24)
+        } else {
+            Some(b0 << 24 | b1 << 16 | b2 << 8 | b3)
+        }
+    }
+}
+
+/// Parse the array of keycodes of a `wl_keyboard.enter` event
+///
+/// The raw bytes of the array carry no alignment guarantee, so they are read
+/// without being reinterpreted in place.
+pub fn raw_keys<'a>(array: &'a [u8]) -> RawKeys<'a> {
+    RawKeys {
+        chunks: array.chunks(4),
+    }
 }
 
 /// Register a keyboard with the implementation provided by this crate
@@ -345,23 +1924,18 @@ pub enum MappedKeyboardError {
 /// as such you need to call this method as soon as you have created the keyboard
 /// to make sure this event does not get lost.
 ///
-/// Returns an error if xkbcommon could not be initialized.
+/// Returns a handle to the keyboard, or an error if xkbcommon could not be initialized.
 pub fn register_kbd<ID: 'static>(evqh: &mut EventQueueHandle, kbd: &WlKeyboard,
                                  implem: MappedKeyboardImplementation<ID>, idata: ID)
-                                 -> Result<(), MappedKeyboardError> {
-    let mapped_kbd = KbState::new()?;
-    evqh.register(
-        kbd,
-        wl_keyboard_implementation(),
-        (mapped_kbd, implem, idata),
-    );
-    Ok(())
+                                 -> Result<KeyboardHandle, MappedKeyboardError> {
+    register_kbd_with_options(evqh, kbd, implem, idata, None, KeyboardOptions::default())
 }
 
 /// The RMLVO description of a keymap
 ///
 /// All fiels are optional, and the system default
 /// will be used if set to `None`.
+#[derive(Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
 pub struct RMLVO {
     /// The rules file to use
     pub rules: Option<String>,
@@ -378,59 +1952,768 @@ pub struct RMLVO {
     pub options: Option<String>,
 }
 
-/// Register a keyboard with the implementation provided by this crate
-///
-/// This requires you to provide an implementation and its implementation data
-/// to receive the events after they have been interpreted with the keymap.
-///
-/// The keymap will be loaded from the provided RMLVO rules. Any keymap provided
-/// by the compositor will be ignored.
-///
-/// Returns an error if xkbcommon could not be initialized.
-pub fn register_kbd_from_rmlvo<ID: 'static>(evqh: &mut EventQueueHandle, kbd: &WlKeyboard,
-                                            implem: MappedKeyboardImplementation<ID>, idata: ID,
-                                            rmlvo: RMLVO)
-                                            -> Result<(), MappedKeyboardError> {
-    let mut mapped_kbd = KbState::new()?;
-
-    fn to_cstring(s: Option<String>) -> Result<Option<CString>, MappedKeyboardError> {
-        s.map_or(Ok(None), |s| CString::new(s).map(Option::Some))
-            .map_err(|_| MappedKeyboardError::BadNames)
-    }
-
-    let rules = to_cstring(rmlvo.rules)?;
-    let model = to_cstring(rmlvo.model)?;
-    let layout = to_cstring(rmlvo.layout)?;
-    let variant = to_cstring(rmlvo.variant)?;
-    let options = to_cstring(rmlvo.options)?;
-
-    let xkb_names = ffi::xkb_rule_names {
-        rules: rules.map_or(ptr::null(), |s| s.as_ptr()),
-        model: model.map_or(ptr::null(), |s| s.as_ptr()),
-        layout: layout.map_or(ptr::null(), |s| s.as_ptr()),
-        variant: variant.map_or(ptr::null(), |s| s.as_ptr()),
-        options: options.map_or(ptr::null(), |s| s.as_ptr()),
-    };
+/// The fields of a `RMLVO`, as C strings
+struct RmlvoNames {
+    rules: Option<CString>,
+    model: Option<CString>,
+    layout: Option<CString>,
+    variant: Option<CString>,
+    options: Option<CString>,
+}
 
-    unsafe {
-        mapped_kbd.init_with_rmlvo(xkb_names)?;
-    }
+impl RmlvoNames {
+    fn new(rmlvo: &RMLVO) -> Result<RmlvoNames, MappedKeyboardError> {
+        fn to_cstring(field: &'static str, s: &Option<String>)
+                      -> Result<Option<CString>, MappedKeyboardError> {
+            match *s {
+                Some(ref s) => CString::new(s.clone()).map(Some).map_err(|_| {
+                    MappedKeyboardError::InteriorNul {
+                        field: field,
+                        value: s.clone(),
+                    }
+                }),
+                None => Ok(None),
+            }
+        }
 
-    mapped_kbd.locked = true;
+        Ok(RmlvoNames {
+            rules: to_cstring("rules", &rmlvo.rules)?,
+            model: to_cstring("model", &rmlvo.model)?,
+            layout: to_cstring("layout", &rmlvo.layout)?,
+            variant: to_cstring("variant", &rmlvo.variant)?,
+            options: to_cstring("options", &rmlvo.options)?,
+        })
+    }
 
+    /// The rule names, borrowing the strings of `self`
+    fn as_rule_names(&self) -> ffi::xkb_rule_names {
+        ffi::xkb_rule_names {
+            rules: self.rules.as_ref().map_or(ptr::null(), |s| s.as_ptr()),
+            model: self.model.as_ref().map_or(ptr::null(), |s| s.as_ptr()),
+            layout: self.layout.as_ref().map_or(ptr::null(), |s| s.as_ptr()),
+            variant: self.variant.as_ref().map_or(ptr::null(), |s| s.as_ptr()),
+            options: self.options.as_ref().map_or(ptr::null(), |s| s.as_ptr()),
+        }
+    }
+}
+
+/// Information about a keymap
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct KeymapInfo {
+    /// The names of the layouts of the keymap
+    pub layouts: Vec<String>,
+}
+
+/// Check that a RMLVO describes a valid keymap
+///
+/// This compiles the keymap without using it, and returns information about it.
+/// It is useful to verify user input before applying it.
+///
+/// Returns an error if xkbcommon could not be initialized or the keymap could
+/// not be compiled.
+pub fn validate_rmlvo(rmlvo: &RMLVO) -> Result<KeymapInfo, MappedKeyboardError> {
+    let xkbh = match ffi::XKBCOMMON_OPTION.as_ref() {
+        Some(h) => h,
+        None => return Err(MappedKeyboardError::library_not_found()),
+    };
+    let names = RmlvoNames::new(rmlvo)?;
+    unsafe {
+        let xkb_context =
+            xkb_acquire!(Context, (xkbh.xkb_context_new)(ffi::xkb_context_flags::XKB_CONTEXT_NO_FLAGS));
+        if xkb_context.is_null() {
+            return Err(MappedKeyboardError::XKBNotFound);
+        }
+        let xkb_keymap = xkb_acquire!(Keymap, (xkbh.xkb_keymap_new_from_names)(
+            xkb_context,
+            &names.as_rule_names(),
+            ffi::xkb_keymap_compile_flags::XKB_KEYMAP_COMPILE_NO_FLAGS,
+        ));
+        let result = if xkb_keymap.is_null() {
+            Err(MappedKeyboardError::BadNames)
+        } else {
+            let info = KeymapInfo {
+                layouts: keymap_layout_names(xkb_keymap),
+            };
+            xkb_release!(Keymap, xkbh.xkb_keymap_unref, xkb_keymap);
+            Ok(info)
+        };
+        xkb_release!(Context, xkbh.xkb_context_unref, xkb_context);
+        result
+    }
+}
+
+/// Register a keyboard with the implementation provided by this crate
+///
+/// This requires you to provide an implementation and its implementation data
+/// to receive the events after they have been interpreted with the keymap.
+///
+/// The keymap will be loaded from the provided RMLVO rules. Any keymap provided
+/// by the compositor will be ignored.
+///
+/// Returns a handle to the keyboard, or an error if xkbcommon could not be initialized.
+pub fn register_kbd_from_rmlvo<ID: 'static>(evqh: &mut EventQueueHandle, kbd: &WlKeyboard,
+                                            implem: MappedKeyboardImplementation<ID>, idata: ID,
+                                            rmlvo: RMLVO)
+                                            -> Result<KeyboardHandle, MappedKeyboardError> {
+    register_kbd_with_options(
+        evqh,
+        kbd,
+        implem,
+        idata,
+        Some(rmlvo),
+        KeyboardOptions::default(),
+    )
+}
+
+/// Register a keyboard with the implementation provided by this crate
+///
+/// This requires you to provide an implementation and its implementation data
+/// to receive the events after they have been interpreted with the keymap.
+///
+/// If `rmlvo` is provided, the keymap will be loaded from it and any keymap provided
+/// by the compositor will be ignored, like `register_kbd_from_rmlvo`. Otherwise it
+/// will be loaded from the events sent by the compositor, like `register_kbd`.
+///
+/// The `options` allow tuning the behavior of the keyboard, see `KeyboardOptions`.
+///
+/// Returns a handle to the keyboard, or an error if xkbcommon could not be initialized.
+pub fn register_kbd_with_options<ID: 'static>(evqh: &mut EventQueueHandle, kbd: &WlKeyboard,
+                                              implem: MappedKeyboardImplementation<ID>, idata: ID,
+                                              rmlvo: Option<RMLVO>, options: KeyboardOptions)
+                                              -> Result<KeyboardHandle, MappedKeyboardError> {
+    let mut mapped_kbd = KbState::new(options)?;
+
+    if let Some(rmlvo) = rmlvo {
+        let names = RmlvoNames::new(&rmlvo)?;
+
+        unsafe {
+            mapped_kbd.init_with_rmlvo(names.as_rule_names())?;
+        }
+
+        mapped_kbd.locked = true;
+        mapped_kbd.rmlvo = Some(rmlvo);
+    }
+
+    mapped_kbd.keyboard = kbd.clone();
+    let handle = KeyboardHandle {
+        state: Arc::new(Mutex::new(mapped_kbd)),
+    };
     evqh.register(
         kbd,
         wl_keyboard_implementation(),
-        (mapped_kbd, implem, idata),
+        (handle.clone(), implem, idata),
     );
-    Ok(())
+    Ok(handle)
+}
+
+/// Register a keyboard, storing its implementation data in the state of the event queue
+///
+/// This is like `register_kbd_with_options()`, but `idata` is inserted in the state of
+/// the event queue, and the callbacks receive the `StateToken` to access it:
+///
+/// ```ignore
+/// key: |evqh, token, _, _, event| {
+///     let data = evqh.state().get_mut(token);
+///     // ...
+/// }
+/// ```
+///
+/// The returned token can be used to access the data between dispatches too, using
+/// `EventQueue::state()`, without having to share it through a `Rc<RefCell<_>>`.
+///
+/// Returns an error if xkbcommon could not be initialized or the keymap could not be
+/// compiled, in which case `idata` is dropped.
+pub fn register_kbd_with_state<ID: 'static>(evqh: &mut EventQueueHandle, kbd: &WlKeyboard,
+                                            implem: MappedKeyboardImplementation<StateToken<ID>>, idata: ID,
+                                            rmlvo: Option<RMLVO>, options: KeyboardOptions)
+                                            -> Result<(KeyboardHandle, StateToken<ID>), MappedKeyboardError> {
+    let token = evqh.state().insert(idata);
+    match register_kbd_with_options(evqh, kbd, implem, token.clone(), rmlvo, options) {
+        Ok(handle) => Ok((handle, token)),
+        Err(err) => {
+            evqh.state().remove(token);
+            Err(err)
+        }
+    }
+}
+
+/// A handle to a keyboard registered with this crate
+///
+/// It is returned by the registration functions and gives access to the state of
+/// the keyboard outside of the callbacks. It can be cloned, and all clones refer
+/// to the same keyboard.
+#[derive(Clone)]
+pub struct KeyboardHandle {
+    state: Arc<Mutex<KbState>>,
+}
+
+impl KeyboardHandle {
+    fn lock<'a>(&'a self) -> MutexGuard<'a, KbState> {
+        // the state is kept consistent between events, even if a callback panicked
+        match self.state.lock() {
+            Ok(guard) => guard,
+            Err(poisoned) => poisoned.into_inner(),
+        }
+    }
+
+    /// Load a keymap from RMLVO, and ignore the keymaps sent by the compositor
+    ///
+    /// This is what `register_kbd_from_rmlvo` does at registration. On error, the
+    /// current keymap is kept. The `keymap_changed` callback is not called.
+    pub fn lock_keymap(&self, rmlvo: RMLVO) -> Result<(), MappedKeyboardError> {
+        let names = RmlvoNames::new(&rmlvo)?;
+        let mut state = self.lock();
+        unsafe {
+            let xkb_keymap = state.compile_rmlvo(names.as_rule_names())?;
+            let surface_keymap = state.leave_surface_keymap();
+            if state.ready() {
+                state.de_init();
+            }
+            state.post_init(xkb_keymap);
+            if !surface_keymap.is_null() {
+                state.enter_surface_keymap(surface_keymap);
+            }
+        }
+        state.apply_modifiers();
+        state.locked = true;
+        state.rmlvo = Some(rmlvo);
+        Ok(())
+    }
+
+    /// Use the keymaps sent by the compositor again, after `lock_keymap()`
+    ///
+    /// The last keymap sent by the compositor is loaded right away. If it did not
+    /// send any, no keymap is loaded until it does. The `keymap_changed` callback is
+    /// not called.
+    ///
+    /// If the last keymap sent by the compositor does not compile, the current keymap
+    /// is kept until the compositor sends another one.
+    pub fn follow_compositor(&self) -> Result<(), KeyboardError> {
+        let mut state = self.lock();
+        if !state.locked {
+            return Ok(());
+        }
+        state.locked = false;
+        state.rmlvo = None;
+        unsafe { state.load_compositor_keymap() }.map(|_| ())
+    }
+
+    /// Use a keymap compiled from RMLVO while a surface has keyboard focus
+    ///
+    /// The keymap is swapped in when the surface gains focus and the keyboard's is
+    /// restored when it loses it, which is useful for surfaces embedding terminals or
+    /// virtual machines. It replaces any previous keymap of the surface. Surfaces
+    /// already destroyed are ignored, and the keymaps of destroyed surfaces are freed
+    /// on the next focus change.
+    pub fn set_surface_keymap(&self, surface: &WlSurface, rmlvo: RMLVO) -> Result<(), MappedKeyboardError> {
+        let names = RmlvoNames::new(&rmlvo)?;
+        let surface = match surface.clone() {
+            Some(surface) => surface,
+            None => return Ok(()),
+        };
+        let mut state = self.lock();
+        let xkb_keymap = unsafe { state.compile_rmlvo(names.as_rule_names())? };
+        let surface_keymap = SurfaceKeymap {
+            surface: surface,
+            xkb_keymap: xkb_keymap,
+        };
+        match state
+            .surface_keymaps
+            .iter()
+            .position(|s| s.surface.equals(&surface_keymap.surface))
+        {
+            Some(index) => {
+                let previous = state.surface_keymaps[index].xkb_keymap;
+                unsafe {
+                    let active = state.leave_surface_keymap();
+                    if active == previous {
+                        state.enter_surface_keymap(xkb_keymap);
+                    } else if !active.is_null() {
+                        state.enter_surface_keymap(active);
+                    }
+                    xkb_release!(Keymap, XKBH.xkb_keymap_unref, previous);
+                }
+                state.surface_keymaps[index] = surface_keymap;
+            }
+            None => state.surface_keymaps.push(surface_keymap),
+        }
+        Ok(())
+    }
+
+    /// Stop using a specific keymap for a surface, see `set_surface_keymap()`
+    pub fn clear_surface_keymap(&self, surface: &WlSurface) {
+        let mut state = self.lock();
+        let index = match state.surface_keymaps.iter().position(|s| s.surface.equals(surface)) {
+            Some(index) => index,
+            None => return,
+        };
+        let surface_keymap = state.surface_keymaps.remove(index);
+        unsafe {
+            let active = state.leave_surface_keymap();
+            if !active.is_null() && active != surface_keymap.xkb_keymap {
+                state.enter_surface_keymap(active);
+            }
+            xkb_release!(Keymap, XKBH.xkb_keymap_unref, surface_keymap.xkb_keymap);
+        }
+    }
+
+    /// Whether the keymaps sent by the compositor are ignored, see `lock_keymap()`
+    pub fn is_keymap_locked(&self) -> bool {
+        self.lock().locked
+    }
+
+    /// Reset the keyboard state, as if no key had been pressed
+    ///
+    /// The held keys are forgotten, the key repeat stops, the pending compose
+    /// sequence and text macro are dropped, and the depressed and latched modifiers
+    /// and layouts are cleared. Locked modifiers and layouts, like Caps Lock, are kept.
+    ///
+    /// This is useful after a VT switch, at the end of a grab, or when events were
+    /// missed. No callback is called, and the next modifiers event of the compositor
+    /// overwrites the modifiers.
+    pub fn reset(&self) {
+        let mut state = self.lock();
+        state.held_keys.clear();
+        state.keys_at_leave.clear();
+        state.gesture_state.release_all();
+        state.repeat = None;
+        state.compose_reset();
+        state.compose_events.clear();
+        state.macro_state = TextMacroState::new();
+        state.raw_masks.depressed = 0;
+        state.raw_masks.latched = 0;
+        state.raw_masks.depressed_layout = 0;
+        state.raw_masks.latched_layout = 0;
+        state.apply_modifiers();
+    }
+
+    /// Change the modifier remappings applied on top of the keymap
+    ///
+    /// This takes effect immediately, including for the modifiers currently active.
+    pub fn set_modifier_remaps(&self, remaps: Vec<ModifierRemap>) {
+        let mut state = self.lock();
+        state.options.modifier_remaps = remaps;
+        state.apply_modifiers();
+    }
+
+    /// Serialize the current modifiers and layout, for forwarding
+    ///
+    /// Modifier remappings are taken into account. Returns `None` if no keymap is loaded.
+    pub fn serialize_state(&self) -> Option<SerializedState> {
+        self.lock().serialize()
+    }
+
+    /// Apply a serialized state to the keyboard
+    ///
+    /// All the modifiers and layout components are applied, which allows latched
+    /// and locked layouts to behave correctly. The state is overwritten by the next
+    /// modifiers sent by the compositor.
+    ///
+    /// This is a side entry point, for states obtained with `serialize_state()`, possibly
+    /// from another keyboard. The `modifiers` events of the compositor are handled as
+    /// before: they only carry the effective layout, which is applied as the locked one.
+    pub fn apply_serialized_state(&self, serialized: SerializedState) {
+        self.lock().apply_serialized(serialized)
+    }
+
+    /// The range of raw keycodes of the current keymap, `None` if no keymap is loaded
+    ///
+    /// Raw keycodes outside of this range, up to `MAX_KEYCODE`, are still valid but
+    /// produce no keysym with this keymap.
+    pub fn keycode_range(&self) -> Option<(u32, u32)> {
+        let state = self.lock();
+        if !state.ready() {
+            return None;
+        }
+        unsafe { keymap::keycode_range(state.xkb_keymap) }
+    }
+
+    /// Find the keys producing a keysym in the current keymap
+    ///
+    /// All layouts and shift levels are considered. The result is sorted by keycode,
+    /// layout and level, and is empty if no keymap is loaded.
+    ///
+    /// The index this relies on is built on first use and kept until the keymap changes.
+    pub fn keys_for_keysym(&self, keysym: u32) -> Vec<KeyLocation> {
+        let mut state = self.lock();
+        state
+            .reverse_index()
+            .map_or(Vec::new(), |index| index.lookup(keysym).to_vec())
+    }
+
+    /// Whether a keysym can be produced by some key of the current keymap
+    ///
+    /// All layouts and shift levels are considered, so this tells whether a shortcut
+    /// can be typed at all, not whether it can be typed in the active layout. Returns
+    /// `false` if no keymap is loaded. See `keys_for_keysym()` for the keys producing it.
+    pub fn keymap_contains(&self, keysym: u32) -> bool {
+        let mut state = self.lock();
+        state.reverse_index().map_or(false, |index| index.contains(keysym))
+    }
+
+    /// List the keysyms that can be typed in the active layout
+    ///
+    /// Every shift level of every key is considered. The keysyms are sorted and
+    /// deduplicated, so that user-entered bindings can be checked with `binary_search()`.
+    /// The list is empty if no keymap is loaded.
+    pub fn layout_keysyms(&self) -> Vec<u32> {
+        let state = self.lock();
+        if !state.ready() {
+            return Vec::new();
+        }
+        unsafe { keymap::layout_keysyms(state.xkb_keymap, state.xkb_state) }
+    }
+
+    /// Find how a character can be typed with the current keymap
+    ///
+    /// Returns the key and the modifiers producing the character, preferring keys
+    /// that do not require switching the layout, then the fewest modifiers. Returns
+    /// `None` if no key produces it, or if no keymap is loaded.
+    ///
+    /// Before libxkbcommon 1.0, only the characters without modifier or with Shift
+    /// are found, and characters only known by their legacy keysym, like Cyrillic
+    /// letters, are not found. See `xkb_capabilities()`.
+    pub fn can_type(&self, c: char) -> Option<KeyCombo> {
+        self.lock().can_type(c)
+    }
+
+    /// Find the key and modifiers producing a keysym with the current keymap
+    ///
+    /// This is how shortcuts should be labeled: on a German layout, `Ctrl+/` is typed
+    /// with Ctrl+Shift+7, given by the `mods` and `base_keysym` of the result. Keys of
+    /// the active layout are preferred, then the fewest modifiers. Returns `None` if no
+    /// key produces the keysym, or if no keymap is loaded.
+    ///
+    /// Before libxkbcommon 1.0, only the keysyms without modifier or with Shift are
+    /// found, see `xkb_capabilities()`.
+    pub fn mods_required_for(&self, keysym: u32) -> Option<KeyCombo> {
+        self.lock().easiest_combo(vec![keysym])
+    }
+
+    /// A label for a shortcut, naming the keys typing it with the current keymap
+    ///
+    /// For example Control + `slash` is "Ctrl+Shift+7" on a German layout. Shortcuts on
+    /// physical keys, or whose keysym no key produces, are labeled with
+    /// `Shortcut::label()`.
+    pub fn shortcut_label(&self, shortcut: &Shortcut, style: &LabelStyle) -> String {
+        let combo = match shortcut.key {
+            ShortcutKey::Keysym(keysym) => self.mods_required_for(keysym),
+            ShortcutKey::Name(_) => None,
+        };
+        match combo {
+            Some(ref combo) if combo.base_keysym != 0 => shortcut.combo_label(combo, style),
+            _ => shortcut.label(style),
+        }
+    }
+
+    /// Extract the description of the whole current keymap
+    ///
+    /// This materializes the keysyms of every key, for every layout and shift level,
+    /// so that the keymap can be used without libxkbcommon. Returns `None` if no keymap
+    /// is loaded.
+    pub fn keymap_description(&self) -> Option<KeymapDescription> {
+        let state = self.lock();
+        if !state.ready() {
+            return None;
+        }
+        Some(unsafe { keymap::describe(state.xkb_keymap, state.layout_names()) })
+    }
+
+    /// The time at which the held key should repeat next, if any
+    ///
+    /// This crate has no timer: applications wanting key repeat should wake up at this
+    /// time and call `poll_repeat()`. The deadline changes as keys are pressed and
+    /// released, so it should be checked again after dispatching events.
+    pub fn repeat_deadline(&self) -> Option<Instant> {
+        let mut state = self.lock();
+        if !state.keyboard_alive() {
+            // the release of the held key will never come
+            state.repeat = None;
+        }
+        if state.repeat_paused {
+            return None;
+        }
+        state.repeat.as_ref().map(|r| r.next)
+    }
+
+    /// Generate the repeats of the held key that are due at `now`
+    ///
+    /// The repeats follow the repeat rate and delay sent by the compositor, or
+    /// `KeyboardOptions::repeat_info`, and the `KeyboardOptions::repeat_policy`. When
+    /// the compositor changes them while a key is held, the key follows the new ones. They
+    /// do not go through compose sequences, text macros or shortcuts. No repeat is
+    /// generated while paused with `RepeatHandle::pause()`, and the repeats missed by
+    /// polling late are dropped, see `RepeatPolicy::coalesce`.
+    pub fn poll_repeat(&self, now: Instant) -> Vec<KeyEvent> {
+        let mut state = self.lock();
+        if !state.keyboard_alive() {
+            state.repeat = None;
+        }
+        state.repeat_events(now)
+    }
+
+    /// The time at which a key held for gestures becomes a `KeyGesture::Hold`, if any
+    ///
+    /// Like `repeat_deadline()`, applications wanting holds reported as soon as they
+    /// happen should wake up at this time and call `poll_gestures()`. Otherwise they are
+    /// reported by the `gesture` callback on the next key event.
+    pub fn gesture_deadline(&self) -> Option<Instant> {
+        let mut guard = self.lock();
+        let state = &mut *guard;
+        if !state.keyboard_alive() {
+            state.gesture_state.release_all();
+        }
+        state.gesture_state.hold_deadline(&state.options.gestures)
+    }
+
+    /// The holds detected at `now`, as pairs of the keysym of the key and `KeyGesture::Hold`
+    ///
+    /// The holds returned here are not notified again through the `gesture` callback.
+    pub fn poll_gestures(&self, now: Instant) -> Vec<(u32, KeyGesture)> {
+        let mut guard = self.lock();
+        let state = &mut *guard;
+        if !state.keyboard_alive() {
+            state.gesture_state.release_all();
+        }
+        state.gesture_state.poll_holds(&state.options.gestures, now)
+    }
+
+    /// A handle to pause, resume or cancel the key repeat of this keyboard
+    pub fn repeat_handle(&self) -> RepeatHandle {
+        RepeatHandle { keyboard: self.clone() }
+    }
+
+    /// The identity of the keyboard, as given to the callbacks
+    pub fn identity(&self) -> KeyboardIdentity {
+        self.lock().identity.clone()
+    }
+
+    /// Whether the keyboard runs without libxkbcommon, see `KeyboardOptions::allow_degraded`
+    pub fn is_degraded(&self) -> bool {
+        self.lock().degraded()
+    }
+
+    /// Whether the `wl_keyboard` of this handle can still receive events
+    ///
+    /// The keyboard dies when it is released, when its seat is removed, or when the
+    /// connection to the compositor is lost, which `EventQueue::dispatch()` reports with
+    /// an error. No event is received anymore and key repeat stops: check this after
+    /// dispatching, and get a new keyboard from the seat when it has the keyboard
+    /// capability again.
+    pub fn is_alive(&self) -> bool {
+        self.lock().keyboard_alive()
+    }
+
+    /// The active layout, with a short name for panels and status bars
+    ///
+    /// `None` if no keymap is loaded. Changes are notified through the
+    /// `layout_indicator` callback.
+    pub fn layout_indicator(&self) -> Option<LayoutIndicator> {
+        self.lock().layout_indicator()
+    }
+
+    /// Set the name of the seat of the keyboard, as sent by the `wl_seat.name` event
+    pub fn set_seat_name(&self, name: Option<String>) {
+        self.lock().identity.seat_name = name;
+    }
+
+    /// The display text of the pending compose sequence, if any
+    ///
+    /// For example, `"´"` after a dead acute key. Changes are also notified through
+    /// the `compose` callback.
+    pub fn compose_preedit(&self) -> Option<String> {
+        let state = self.lock();
+        if state.compose_pending().is_empty() {
+            None
+        } else {
+            Some(compose::preedit(state.compose_pending()))
+        }
+    }
+
+    /// Switch to the compose state of a text field
+    ///
+    /// Each id has its own pending compose sequence, so that a dead key typed in a text
+    /// field does not apply to the next key typed in another one. Call this when the
+    /// focus moves between the text fields of a surface, with ids chosen by the
+    /// application. `None` is the state used by default.
+    ///
+    /// The pending sequence of the previous text field is kept until it becomes active
+    /// again, or until `remove_compose_context()`. No `compose` callback is called, use
+    /// `compose_preedit()` to update the display.
+    pub fn set_compose_context(&self, id: Option<u64>) {
+        self.lock().switch_compose_context(id);
+    }
+
+    /// Drop the compose state of a text field, for example when it is destroyed
+    ///
+    /// If it is the active one, its pending sequence is dropped and the default state,
+    /// of id `None`, becomes active.
+    pub fn remove_compose_context(&self, id: u64) {
+        let mut state = self.lock();
+        if state.compose_context == Some(id) {
+            state.compose_reset();
+            // without a pending sequence, the state of the removed id is not kept
+            state.switch_compose_context(None);
+        } else {
+            state.compose_contexts.retain(|c| c.id != Some(id));
+        }
+    }
+
+    /// The input modes of Korean and Japanese keyboards, see `InputModeState`
+    pub fn input_mode(&self) -> InputModeState {
+        self.lock().input_mode
+    }
+
+    /// Change the input modes, to follow an input method changing them on its own
+    ///
+    /// The `input_mode` callback is not called.
+    pub fn set_input_mode(&self, mode: InputModeState) {
+        self.lock().input_mode = mode;
+    }
+
+    /// List the sequences of the loaded compose table
+    ///
+    /// This can be used to show the available sequences to the user. Returns `None` if
+    /// no compose table is loaded, or if the libxkbcommon in use is older than 1.6.
+    pub fn compose_sequences(&self) -> Option<Vec<ComposeSequence>> {
+        let state = self.lock();
+        state.compose.as_ref().and_then(|compose| compose.sequences())
+    }
+
+    /// Extract the legends of every key, for every layout of the current keymap
+    ///
+    /// This is meant for on-screen keyboards and key overlays. Returns `None` if no
+    /// keymap is loaded.
+    pub fn layout_legends(&self) -> Option<Vec<LayoutLegends>> {
+        let state = self.lock();
+        if !state.ready() {
+            return None;
+        }
+        Some(unsafe { keymap::legends(state.xkb_keymap, state.layout_names()) })
+    }
+
+    /// Produce a report of the current state of the keyboard
+    ///
+    /// Its `Display` implementation gives a text suitable for pasting in bug reports.
+    pub fn debug_dump(&self) -> DebugDump {
+        self.lock().debug_dump()
+    }
+
+    /// Capture the current state of the keyboard, for logging or assertions in tests
+    pub fn state_report(&self) -> StateReport {
+        self.lock().state_report()
+    }
+}
+
+/// A handle controlling the key repeat of a keyboard
+///
+/// Obtained from `KeyboardHandle::repeat_handle()`, for example to stop repeats while
+/// a modal dialog animates. It can be cloned, and all clones control the same keyboard.
+#[derive(Clone)]
+pub struct RepeatHandle {
+    keyboard: KeyboardHandle,
+}
+
+impl RepeatHandle {
+    /// Stop generating repeats until `resume()` is called
+    ///
+    /// Keys pressed while paused still start repeating once resumed, if they are held.
+    pub fn pause(&self) {
+        self.keyboard.lock().repeat_paused = true;
+    }
+
+    /// Generate repeats again after `pause()`
+    ///
+    /// The repeats missed while paused are skipped: the held key repeats at the next
+    /// `poll_repeat()`, then at the usual rate.
+    pub fn resume(&self) {
+        let mut state = self.keyboard.lock();
+        state.repeat_paused = false;
+        if let Some(ref mut repeat) = state.repeat {
+            let now = Instant::now();
+            if repeat.next < now {
+                repeat.next = now;
+            }
+        }
+    }
+
+    /// Whether the repeats are paused
+    pub fn is_paused(&self) -> bool {
+        self.keyboard.lock().repeat_paused
+    }
+
+    /// Stop the repeat of the held key
+    ///
+    /// The key does not repeat again until it is released and pressed again. This does
+    /// not pause the repeats of the keys pressed afterwards.
+    pub fn cancel(&self) {
+        self.keyboard.lock().repeat = None;
+    }
+}
+
+/// A registered keyboard, released when dropped
+///
+/// The keyboards registered with this crate live as long as their `wl_keyboard`. This
+/// guard owns the `wl_keyboard` of a registration: when dropped, it sends the
+/// `wl_keyboard.release` request and frees the keymaps and compose tables, so that
+/// applications creating and destroying keyboards dynamically do not leak them.
+///
+/// The `release` request exists since version 3 of `wl_seat`. With older versions, the
+/// keyboard cannot be destroyed and only the libxkbcommon resources are freed.
+pub struct KeyboardGuard {
+    keyboard: Option<WlKeyboard>,
+    handle: KeyboardHandle,
+}
+
+impl KeyboardGuard {
+    /// Take ownership of a keyboard and of the handle returned by its registration
+    pub fn new(keyboard: WlKeyboard, handle: KeyboardHandle) -> KeyboardGuard {
+        KeyboardGuard {
+            keyboard: Some(keyboard),
+            handle: handle,
+        }
+    }
+
+    /// The handle of the keyboard
+    pub fn handle(&self) -> &KeyboardHandle {
+        &self.handle
+    }
+
+    /// The `wl_keyboard` of the keyboard
+    pub fn keyboard(&self) -> &WlKeyboard {
+        // only taken when dropped
+        self.keyboard.as_ref().unwrap()
+    }
+}
+
+impl Drop for KeyboardGuard {
+    fn drop(&mut self) {
+        if let Some(keyboard) = self.keyboard.take() {
+            if keyboard.is_alive() && keyboard.version() >= 3 {
+                let _ = keyboard.release();
+            }
+        }
+        self.handle.lock().release_resources();
+    }
 }
 
+/// The callbacks receiving the events of a keyboard
+///
+/// `MappedKeyboardImplementation::default()` ignores every event, so that only the
+/// callbacks of interest need to be given:
+///
+/// ```ignore
+/// MappedKeyboardImplementation {
+///     key: |_, _, _, _, event| println!("{:?}", event.utf8),
+///     ..Default::default()
+/// }
+/// ```
 pub struct MappedKeyboardImplementation<ID> {
     pub enter: fn(
      evqh: &mut EventQueueHandle,
      idata: &mut ID,
      keyboard: &WlKeyboard,
+     identity: &KeyboardIdentity,
      serial: u32,
      surface: &WlSurface,
      mods: ModifiersState,
@@ -441,119 +2724,1067 @@ pub struct MappedKeyboardImplementation<ID> {
      evqh: &mut EventQueueHandle,
      idata: &mut ID,
      keyboard: &WlKeyboard,
+     identity: &KeyboardIdentity,
      serial: u32,
      surface: &WlSurface,
     ),
+    /// A key was pressed or released
+    ///
+    /// Modifiers events are applied as soon as they are received, so a key is translated
+    /// with the modifiers of every modifiers event sent before it, even when the
+    /// compositor sends them in the same batch. Modifiers received before the keymap
+    /// are applied once it is loaded.
     pub key: fn(
      evqh: &mut EventQueueHandle,
      idata: &mut ID,
      keyboard: &WlKeyboard,
+     identity: &KeyboardIdentity,
+     event: KeyEvent,
+    ),
+    pub repeat_info: fn(
+     evqh: &mut EventQueueHandle,
+     idata: &mut ID,
+     keyboard: &WlKeyboard,
+     identity: &KeyboardIdentity,
+     rate: i32,
+     delay: i32,
+    ),
+    pub compose: fn(
+     evqh: &mut EventQueueHandle,
+     idata: &mut ID,
+     keyboard: &WlKeyboard,
+     identity: &KeyboardIdentity,
+     event: ComposeEvent,
+    ),
+    pub chord: fn(
+     evqh: &mut EventQueueHandle,
+     idata: &mut ID,
+     keyboard: &WlKeyboard,
+     identity: &KeyboardIdentity,
      serial: u32,
      time: u32,
-     mods: ModifiersState,
-     rawkey: u32,
+     chord: &Chord,
+    ),
+    pub shortcut: fn(
+     evqh: &mut EventQueueHandle,
+     idata: &mut ID,
+     keyboard: &WlKeyboard,
+     identity: &KeyboardIdentity,
+     serial: u32,
+     time: u32,
+     shortcut: &Shortcut,
+    ),
+    pub gesture: fn(
+     evqh: &mut EventQueueHandle,
+     idata: &mut ID,
+     keyboard: &WlKeyboard,
+     identity: &KeyboardIdentity,
+     time: u32,
      keysym: u32,
-     state: KeyState,
-     utf8: Option<String>,
+     gesture: KeyGesture,
+    ),
+    /// The compositor replaced the keymap, for example after the user changed layouts
+    ///
+    /// It is not called for the first keymap, nor when the new keymap is identical.
+    pub keymap_changed: fn(
+     evqh: &mut EventQueueHandle,
+     idata: &mut ID,
+     keyboard: &WlKeyboard,
+     identity: &KeyboardIdentity,
+     change: &KeymapChange,
+    ),
+    /// An input mode of Korean or Japanese keyboards was toggled
+    ///
+    /// It is called after the `key` callback of the key toggling it, see `InputModeState`.
+    pub input_mode: fn(
+     evqh: &mut EventQueueHandle,
+     idata: &mut ID,
+     keyboard: &WlKeyboard,
+     identity: &KeyboardIdentity,
+     mode: InputModeState,
+    ),
+    /// Modifier keys held when the focus was lost were released in the meantime
+    ///
+    /// It is called after the `enter` callback, see `StuckModifiers`.
+    pub stuck_modifiers: fn(
+     evqh: &mut EventQueueHandle,
+     idata: &mut ID,
+     keyboard: &WlKeyboard,
+     identity: &KeyboardIdentity,
+     serial: u32,
+     stuck: &StuckModifiers,
+    ),
+    /// The active layout changed, or its keymap was replaced
+    ///
+    /// It is also called once the first keymap is loaded, see `LayoutIndicator`.
+    pub layout_indicator: fn(
+     evqh: &mut EventQueueHandle,
+     idata: &mut ID,
+     keyboard: &WlKeyboard,
+     identity: &KeyboardIdentity,
+     indicator: &LayoutIndicator,
+    ),
+    /// Layers receiving the key events before the `key` callback, see `KeyLayer`
+    ///
+    /// They do not see the repeats returned by `KeyboardHandle::poll_repeat()`.
+    pub layers: Vec<KeyLayer<ID>>,
+    /// The keyboard could not handle an event of the compositor, or it violated the protocol
+    ///
+    /// The keyboard keeps working with its current keymap, see `KeyboardError`.
+    pub error: fn(
+     evqh: &mut EventQueueHandle,
+     idata: &mut ID,
+     keyboard: &WlKeyboard,
+     identity: &KeyboardIdentity,
+     error: &KeyboardError,
     ),
-    pub repeat_info:
-        fn(evqh: &mut EventQueueHandle, idata: &mut ID, keyboard: &WlKeyboard, rate: i32, delay: i32),
+}
+
+impl<ID> Default for MappedKeyboardImplementation<ID> {
+    fn default() -> MappedKeyboardImplementation<ID> {
+        MappedKeyboardImplementation {
+            enter: |_, _, _, _, _, _, _, _, _| {},
+            leave: |_, _, _, _, _, _| {},
+            key: |_, _, _, _, _| {},
+            repeat_info: |_, _, _, _, _, _| {},
+            compose: |_, _, _, _, _| {},
+            chord: |_, _, _, _, _, _, _| {},
+            shortcut: |_, _, _, _, _, _, _| {},
+            gesture: |_, _, _, _, _, _, _| {},
+            keymap_changed: |_, _, _, _, _| {},
+            input_mode: |_, _, _, _, _| {},
+            stuck_modifiers: |_, _, _, _, _, _| {},
+            layout_indicator: |_, _, _, _, _| {},
+            layers: Vec::new(),
+            error: |_, _, _, _, _| {},
+        }
+    }
+}
+
+fn report_violations<ID>(implem: &MappedKeyboardImplementation<ID>, evqh: &mut EventQueueHandle,
+                         idata: &mut ID, keyboard: &WlKeyboard, identity: &KeyboardIdentity,
+                         violations: Vec<ProtocolViolation>) {
+    for violation in violations {
+        (implem.error)(evqh, idata, keyboard, identity, &KeyboardError::Protocol { violation: violation });
+    }
 }
 
 fn wl_keyboard_implementation<ID: 'static>(
     )
-    -> wl_keyboard::Implementation<(KbState, MappedKeyboardImplementation<ID>, ID)>
+    -> wl_keyboard::Implementation<(KeyboardHandle, MappedKeyboardImplementation<ID>, ID)>
 {
     wl_keyboard::Implementation {
-        keymap: |_, &mut (ref mut state, _, _), _keyboard, format, fd, size| {
-            if state.locked {
-                // state is locked, ignore keymap updates
-                return;
-            }
-            if state.ready() {
-                // new keymap, we first deinit to free resources
-                unsafe {
-                    state.de_init();
+        keymap: |evqh, &mut (ref handle, ref implem, ref mut idata), keyboard, format, fd, size| {
+            let _span = kbd_span!("keymap", ?format, size);
+            let (change, indicator, identity) = {
+                let mut state = handle.lock();
+                state.protocol.keymap();
+                let change = unsafe { state.receive_keymap(format, fd, size as usize) };
+                (change, state.indicator_change(), state.identity.clone())
+            };
+            match change {
+                Ok(Some(ref change)) if !change.is_empty() => {
+                    (implem.keymap_changed)(evqh, idata, keyboard, &identity, change);
                 }
+                Ok(_) => {}
+                Err(error) => (implem.error)(evqh, idata, keyboard, &identity, &error),
             }
-            match format {
-                KeymapFormat::XkbV1 => unsafe {
-                    state.init_with_fd(fd, size as usize);
-                },
-                KeymapFormat::NoKeymap => {
-                    // TODO: how to handle this (hopefully never occuring) case?
-                }
+            if let Some(indicator) = indicator {
+                (implem.layout_indicator)(evqh, idata, keyboard, &identity, &indicator);
             }
         },
-        enter: |evqh, &mut (ref mut state, ref implem, ref mut idata), keyboard, serial, surface, keys| {
-            let rawkeys: &[u32] =
-                unsafe { ::std::slice::from_raw_parts(keys.as_ptr() as *const u32, keys.len() / 4) };
-            let (keys, mods_state) = {
+        enter: |evqh, &mut (ref handle, ref implem, ref mut idata), keyboard, serial, surface, keys| {
+            let _span = kbd_span!("enter", serial);
+            let rawkeys: Vec<u32> = raw_keys(&keys).collect();
+            let (keys, mods_state, stuck, violations, identity) = {
+                let mut state = handle.lock();
+                let violations = state.check_protocol(|protocol| protocol.enter(serial));
+                unsafe {
+                    state.leave_surface_keymap();
+                }
+                state.surface_keymaps.retain(|s| {
+                    if !s.surface.is_alive() {
+                        unsafe { xkb_release!(Keymap, XKBH.xkb_keymap_unref, s.xkb_keymap) };
+                    }
+                    s.surface.is_alive()
+                });
+                let surface_keymap = state
+                    .surface_keymaps
+                    .iter()
+                    .find(|s| s.surface.equals(surface))
+                    .map(|s| s.xkb_keymap);
+                if let Some(xkb_keymap) = surface_keymap {
+                    unsafe {
+                        state.enter_surface_keymap(xkb_keymap);
+                    }
+                }
                 let keys: Vec<u32> = rawkeys.iter().map(|k| state.get_one_sym_raw(*k)).collect();
-                (keys, state.mods_state.clone())
+                state.held_keys = rawkeys
+                    .iter()
+                    .zip(&keys)
+                    .map(|(&keycode, &keysym)| HeldKey {
+                        keycode: keycode,
+                        keysym: keysym,
+                        time: None,
+                    })
+                    .collect();
+                let stuck = StuckModifiers::detect(&state.keys_at_leave, &state.held_keys);
+                state.keys_at_leave.clear();
+                (keys, state.mods_state.clone(), stuck, violations, state.identity.clone())
             };
+            report_violations(implem, evqh, idata, keyboard, &identity, violations);
             (implem.enter)(
                 evqh,
                 idata,
                 keyboard,
+                &identity,
                 serial,
                 surface,
                 mods_state,
-                rawkeys,
+                &rawkeys,
                 &keys,
-            )
+            );
+            if let Some(stuck) = stuck {
+                kbd_event!(?stuck, "modifiers released while unfocused");
+                (implem.stuck_modifiers)(evqh, idata, keyboard, &identity, serial, &stuck);
+            }
         },
-        leave: |evqh, &mut (_, ref implem, ref mut idata), keyboard, serial, surface| {
-            (implem.leave)(evqh, idata, keyboard, serial, surface)
+        leave: |evqh, &mut (ref handle, ref implem, ref mut idata), keyboard, serial, surface| {
+            let _span = kbd_span!("leave", serial);
+            let (cancelled, violations, identity) = {
+                let mut state = handle.lock();
+                let violations = state.check_protocol(|protocol| protocol.leave(serial));
+                state.keys_at_leave = ::std::mem::replace(&mut state.held_keys, Vec::new());
+                state.gesture_state.release_all();
+                state.repeat = None;
+                // the keys of a sequence must all be typed in the same surface
+                state.macro_state = TextMacroState::new();
+                unsafe {
+                    state.leave_surface_keymap();
+                }
+                let cancelled = state.options.compose_cancel_on_leave && !state.compose_pending().is_empty();
+                if cancelled {
+                    state.compose_reset();
+                    if let Some(ref diagnostics) = state.options.diagnostics {
+                        diagnostics.record_cancelled();
+                    }
+                }
+                (cancelled, violations, state.identity.clone())
+            };
+            report_violations(implem, evqh, idata, keyboard, &identity, violations);
+            if cancelled {
+                let event = ComposeEvent::Cancelled(ComposeCancelReason::FocusLost);
+                (implem.compose)(evqh, idata, keyboard, &identity, event);
+            }
+            (implem.leave)(evqh, idata, keyboard, &identity, serial, surface)
         },
         key: |evqh,
-              &mut (ref mut state, ref implem, ref mut idata),
+              &mut (ref handle, ref implem, ref mut idata),
               keyboard,
               serial,
               time,
               key,
               key_state| {
-            let sym = state.get_one_sym_raw(key);
-            let ignore_text = if key_state == KeyState::Pressed {
-                state.compose_feed(sym) != Some(ffi::xkb_compose_feed_result::XKB_COMPOSE_FEED_ACCEPTED)
-            } else {
-                true
-            };
-            let utf8 = if ignore_text {
-                None
-            } else if let Some(status) = state.compose_status() {
-                match status {
-                    ffi::xkb_compose_status::XKB_COMPOSE_COMPOSED => state.compose_get_utf8(),
-                    ffi::xkb_compose_status::XKB_COMPOSE_NOTHING => state.get_utf8_raw(key),
-                    _ => None,
-                }
-            } else {
-                state.get_utf8_raw(key)
-            };
-            let mods_state = state.mods_state.clone();
-            (implem.key)(
-                evqh,
-                idata,
-                keyboard,
-                serial,
-                time,
-                mods_state,
-                key,
+            let _span = kbd_span!("key", serial, time, key, state = ?key_state);
+            let received = Instant::now();
+            // the state must not be locked while the callbacks run, so that
+            // they can use the keyboard handle
+            let (
                 sym,
-                key_state,
+                layout,
                 utf8,
-            )
+                mods_state,
+                compose_events,
+                chord,
+                gestures,
+                shortcuts,
+                input_mode,
+                identity,
+                degraded,
+                latency_hook,
+                violations,
+            ) = {
+                let mut guard = handle.lock();
+                let state = &mut *guard;
+                let violations = state.check_protocol(|protocol| protocol.key(serial));
+                let start = Instant::now();
+                let (sym, layout) = {
+                    let _no_alloc = kbd_no_alloc!("keysym lookup");
+                    (state.get_one_sym_raw(key), state.key_layout(key))
+                };
+                state.held_keys.retain(|k| k.keycode != key);
+                if key_state == KeyState::Pressed {
+                    state.held_keys.push(HeldKey {
+                        keycode: key,
+                        keysym: sym,
+                        time: Some(time),
+                    });
+                }
+                if !state.options.modifier_remaps.is_empty() {
+                    // remapped modifiers depend on the keys held
+                    state.apply_modifiers();
+                }
+                let utf8 = if key_state == KeyState::Pressed {
+                    state.check_compose_timeout(time);
+                    let utf8 = state.press_utf8(key, sym, time);
+                    state.macro_state.feed(&state.options.text_macros, sym, utf8)
+                } else {
+                    None
+                };
+                let input_mode = if key_state == KeyState::Pressed && state.input_mode.feed(sym) {
+                    Some(state.input_mode)
+                } else {
+                    None
+                };
+                state.track_repeat(key, serial, time, sym, layout, key_state, Instant::now());
+                if let Some(ref diagnostics) = state.options.diagnostics {
+                    diagnostics.record_key(start.elapsed());
+                }
+                kbd_event!(keysym = sym, utf8 = ?utf8, "key translated");
+                let chord = if key_state == KeyState::Pressed {
+                    state
+                        .options
+                        .chords
+                        .iter()
+                        .find(|c| c.matches(&state.held_keys, time))
+                        .cloned()
+                } else {
+                    None
+                };
+                let gestures = state.gesture_state.feed(
+                    &state.options.gestures,
+                    key,
+                    sym,
+                    key_state == KeyState::Pressed,
+                    time,
+                    received,
+                );
+                let shortcuts: Vec<Shortcut> = if key_state == KeyState::Pressed
+                    && !state.options.shortcuts.is_empty()
+                {
+                    let key_name = state.key_name(key);
+                    let key_name = key_name.as_ref().map(|n| &n[..]);
+                    let consumed = state.consumed_mods(key);
+                    let first_level = state.first_level(key);
+                    let uses_levels = state
+                        .options
+                        .shortcuts
+                        .iter()
+                        .any(|s| s.matching != ShortcutMatching::Keysym);
+                    let other_layouts = if state
+                        .options
+                        .shortcuts
+                        .iter()
+                        .any(|s| s.matching == ShortcutMatching::AnyLayout)
+                    {
+                        state.other_layouts_syms(key)
+                    } else {
+                        Vec::new()
+                    };
+                    if uses_levels {
+                        state.reverse_index();
+                    }
+                    let state = &*state;
+                    let on_first_level = |keysym: u32| match (state.reverse_index.as_ref(), first_level) {
+                        (Some(index), Some(location)) => index.lookup(keysym).contains(&location),
+                        _ => false,
+                    };
+                    state
+                        .options
+                        .shortcuts
+                        .iter()
+                        .filter(|s| match s.matching {
+                            ShortcutMatching::Keysym => s.matches(&state.mods_state, sym, key_name),
+                            ShortcutMatching::Level => {
+                                s.matches_level(&state.mods_state, &consumed, sym, key_name, &on_first_level)
+                            }
+                            ShortcutMatching::AnyLayout => {
+                                s.matches_level(&state.mods_state, &consumed, sym, key_name, &on_first_level)
+                                    || s.matches_other_layouts(&state.mods_state, &other_layouts)
+                            }
+                        })
+                        .cloned()
+                        .collect()
+                } else {
+                    Vec::new()
+                };
+                let compose_events: Vec<ComposeEvent> = state.compose_events.drain(..).collect();
+                (
+                    sym,
+                    layout,
+                    utf8,
+                    state.mods_state.clone(),
+                    compose_events,
+                    chord,
+                    gestures,
+                    shortcuts,
+                    input_mode,
+                    state.identity.clone(),
+                    state.degraded(),
+                    state.options.latency_hook,
+                    violations,
+                )
+            };
+            report_violations(implem, evqh, idata, keyboard, &identity, violations);
+            let translation = received.elapsed();
+            // measured now, but the translation is not part of the delivery
+            let delivery = latency_hook
+                .and_then(|_| latency::delivery_delay(time))
+                .map(|delay| delay.checked_sub(translation).unwrap_or(Duration::from_secs(0)));
+            for event in compose_events {
+                (implem.compose)(evqh, idata, keyboard, &identity, event);
+            }
+            let kind = KeyKind::classify(sym, utf8.as_ref().map(|s| &s[..]));
+            let event = KeyEvent {
+                serial: serial,
+                time: time,
+                mods: mods_state,
+                rawkey: key,
+                keysym: sym,
+                layout: layout,
+                state: key_state,
+                origin: KeyOrigin::Device,
+                utf8: utf8,
+                kind: kind,
+                is_keypad: classify::is_keypad(key, sym),
+                nav: NavKey::from_keysym(sym),
+                repeat_count: 0,
+                degraded: degraded,
+            };
+            let triggered = Triggered {
+                shortcuts: shortcuts,
+                chord: chord,
+                gestures: gestures,
+                input_mode: input_mode,
+            };
+            let passed = run_layers(
+                &implem.layers,
+                |layer, event| (layer.key)(evqh, idata, keyboard, &identity, event),
+                event,
+                triggered,
+            );
+            if let Some((event, triggered)) = passed {
+                (implem.key)(evqh, idata, keyboard, &identity, event);
+                for shortcut in &triggered.shortcuts {
+                    (implem.shortcut)(evqh, idata, keyboard, &identity, serial, time, shortcut);
+                }
+                if let Some(chord) = triggered.chord {
+                    (implem.chord)(evqh, idata, keyboard, &identity, serial, time, &chord);
+                }
+                for (keysym, gesture) in triggered.gestures {
+                    (implem.gesture)(evqh, idata, keyboard, &identity, time, keysym, gesture);
+                }
+                if let Some(mode) = triggered.input_mode {
+                    (implem.input_mode)(evqh, idata, keyboard, &identity, mode);
+                }
+            }
+            if let Some(hook) = latency_hook {
+                hook(&KeyLatency {
+                    serial: serial,
+                    time: time,
+                    rawkey: key,
+                    delivery: delivery,
+                    translation: translation,
+                    processing: received.elapsed(),
+                });
+            }
         },
-        modifiers: |_,
-                    &mut (ref mut state, _, _),
-                    _keyboard,
-                    _,
+        modifiers: |evqh,
+                    &mut (ref handle, ref implem, ref mut idata),
+                    keyboard,
+                    serial,
                     mods_depressed,
                     mods_latched,
                     mods_locked,
-                    group| { state.update_modifiers(mods_depressed, mods_latched, mods_locked, group) },
-        repeat_info: |evqh, &mut (_, ref implem, ref mut idata), keyboard, rate, delay| {
-            (implem.repeat_info)(evqh, idata, keyboard, rate, delay)
+                    group| {
+            let (indicator, violations, identity) = {
+                let mut state = handle.lock();
+                let violations = state.check_protocol(|protocol| protocol.modifiers(serial));
+                // The protocol only carries the effective layout: it is set as the locked
+                // layout, so that it stays effective until the compositor changes it.
+                state.update_modifiers(StateMasks {
+                    depressed: mods_depressed,
+                    latched: mods_latched,
+                    locked: mods_locked,
+                    depressed_layout: 0,
+                    latched_layout: 0,
+                    locked_layout: group,
+                });
+                (state.indicator_change(), violations, state.identity.clone())
+            };
+            report_violations(implem, evqh, idata, keyboard, &identity, violations);
+            if let Some(indicator) = indicator {
+                (implem.layout_indicator)(evqh, idata, keyboard, &identity, &indicator);
+            }
         },
+        repeat_info: |evqh, &mut (ref handle, ref implem, ref mut idata), keyboard, rate, delay| {
+            let _span = kbd_span!("repeat_info", rate, delay);
+            let (rate, delay, identity) = {
+                let mut state = handle.lock();
+                state.repeat_info = Some((rate, delay));
+                // the held key follows the new rate and delay right away
+                let info = state.effective_repeat_info();
+                if !state.repeat.as_mut().map_or(true, |repeat| repeat.reconfigure(info)) {
+                    state.repeat = None;
+                }
+                let (rate, delay) = state.options.repeat_info.unwrap_or((rate, delay));
+                (rate, delay, state.identity.clone())
+            };
+            (implem.repeat_info)(evqh, idata, keyboard, &identity, rate, delay)
+        },
+    }
+}
+
+
+// the callbacks a key press triggers besides the `key` callback
+struct Triggered {
+    shortcuts: Vec<Shortcut>,
+    chord: Option<Chord>,
+    gestures: Vec<(u32, KeyGesture)>,
+    input_mode: Option<InputModeState>,
+}
+
+// Pass a key event through the layers, in order. Returns the event and what it
+// triggered if they all let it through, or nothing if one consumed it: the later
+// layers and every other callback of the key are then skipped.
+fn run_layers<ID, F>(layers: &[KeyLayer<ID>], mut layer_key: F, event: KeyEvent, triggered: Triggered)
+                     -> Option<(KeyEvent, Triggered)>
+where
+    F: FnMut(&KeyLayer<ID>, &KeyEvent) -> Propagation,
+{
+    let consumed_by = layers.iter().find(|layer| layer_key(layer, &event) == Propagation::Stop);
+    match consumed_by {
+        None => Some((event, triggered)),
+        Some(_) => {
+            kbd_event!(layer = ?consumed_by.map(|l| l.name), "key consumed by a layer");
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::io::Write;
+    use std::os::unix::io::IntoRawFd;
+    use tempfile;
+
+    // evdev keycode of the A key
+    const KEY_A: u32 = 30;
+    // Shift is the first real modifier in every keymap
+    const SHIFT: StateMasks = StateMasks {
+        depressed: 1,
+        latched: 0,
+        locked: 0,
+        depressed_layout: 0,
+        latched_layout: 0,
+        locked_layout: 0,
+    };
+
+    // a keymap as sent by compositors, relying on the xkb data of the system
+    const KEYMAP: &'static str = "xkb_keymap {
+        xkb_keycodes { include \"evdev\" };
+        xkb_types { include \"complete\" };
+        xkb_compat { include \"complete\" };
+        xkb_symbols { include \"pc+us\" };
+    };";
+
+    lazy_static! {
+        // the tests counting the open fds must not run while others open files
+        static ref SERIAL: Mutex<()> = Mutex::new(());
+    }
+
+    fn serial() -> MutexGuard<'static, ()> {
+        SERIAL.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    fn open_fds() -> usize {
+        fs::read_dir("/proc/self/fd").unwrap().count()
+    }
+
+    // a fd containing `contents`, owned by the caller
+    fn keymap_fd(contents: &str) -> RawFd {
+        let mut file = tempfile::tempfile().unwrap();
+        file.write_all(contents.as_bytes()).unwrap();
+        file.into_raw_fd()
+    }
+
+    fn keyboard() -> KbState {
+        let options = KeyboardOptions {
+            compose: false,
+            ..Default::default()
+        };
+        KbState::new(options).expect("libxkbcommon is needed to run the tests")
+    }
+
+    fn load_us(state: &mut KbState) {
+        load_layout(state, "us", None);
+    }
+
+    fn load_layout(state: &mut KbState, layout: &str, variant: Option<&str>) {
+        let rmlvo = RMLVO {
+            rules: None,
+            model: None,
+            layout: Some(layout.to_owned()),
+            variant: variant.map(|v| v.to_owned()),
+            options: None,
+        };
+        let names = RmlvoNames::new(&rmlvo).unwrap();
+        unsafe {
+            state.init_with_rmlvo(names.as_rule_names()).unwrap();
+        }
+    }
+
+    #[test]
+    fn key_after_modifiers() {
+        let _serial = serial();
+        let mut state = keyboard();
+        load_us(&mut state);
+        assert_eq!(state.get_one_sym_raw(KEY_A), keysyms::XKB_KEY_a);
+        state.update_modifiers(SHIFT);
+        assert!(state.mods_state.shift());
+        assert_eq!(state.get_one_sym_raw(KEY_A), keysyms::XKB_KEY_A);
+        assert_eq!(state.get_utf8_raw(KEY_A), Some("A".to_owned()));
+        state.update_modifiers(StateMasks::default());
+        assert_eq!(state.get_one_sym_raw(KEY_A), keysyms::XKB_KEY_a);
+    }
+
+    #[test]
+    fn keymap_after_modifiers() {
+        let _serial = serial();
+        let mut state = keyboard();
+        // received before the keymap, kept until it is loaded
+        state.update_modifiers(SHIFT);
+        load_us(&mut state);
+        assert!(state.mods_state.shift());
+        assert_eq!(state.get_one_sym_raw(KEY_A), keysyms::XKB_KEY_A);
+    }
+
+    #[test]
+    fn keymap_replaced_after_modifiers() {
+        let _serial = serial();
+        let mut state = keyboard();
+        load_us(&mut state);
+        state.update_modifiers(SHIFT);
+        state.update_modifiers(StateMasks::default());
+        state.update_modifiers(SHIFT);
+        unsafe {
+            state.de_init();
+        }
+        load_us(&mut state);
+        // the last masks, not the first ones nor an empty state
+        assert_eq!(state.get_one_sym_raw(KEY_A), keysyms::XKB_KEY_A);
+    }
+
+    #[test]
+    fn keymap_fds_closed() {
+        let _serial = serial();
+        let mut state = keyboard();
+        let before = open_fds();
+        for _ in 0..50 {
+            let fd = keymap_fd(KEYMAP);
+            let change = unsafe { state.receive_keymap(KeymapFormat::XkbV1, fd, KEYMAP.len()) };
+            assert!(change.is_ok());
+        }
+        assert!(state.ready());
+        assert_eq!(open_fds(), before);
+    }
+
+    #[test]
+    fn locked_keymap_fds_closed() {
+        let _serial = serial();
+        let mut state = keyboard();
+        load_us(&mut state);
+        state.locked = true;
+        let before = open_fds();
+        for _ in 0..50 {
+            let fd = keymap_fd(KEYMAP);
+            let change = unsafe { state.receive_keymap(KeymapFormat::XkbV1, fd, KEYMAP.len()) };
+            assert_eq!(change.unwrap(), None);
+        }
+        assert_eq!(open_fds(), before);
+    }
+
+    #[test]
+    fn no_keymap_fds_closed() {
+        let _serial = serial();
+        let mut state = keyboard();
+        let before = open_fds();
+        for _ in 0..50 {
+            let fd = keymap_fd("");
+            let change = unsafe { state.receive_keymap(KeymapFormat::NoKeymap, fd, 0) };
+            assert!(change.is_ok());
+        }
+        assert_eq!(open_fds(), before);
+    }
+
+    #[test]
+    fn unmappable_keymap_fds_closed() {
+        let _serial = serial();
+        let mut state = keyboard();
+        load_us(&mut state);
+        let before = open_fds();
+        for _ in 0..50 {
+            // an empty mapping is refused
+            let fd = keymap_fd(KEYMAP);
+            match unsafe { state.receive_keymap(KeymapFormat::XkbV1, fd, 0) } {
+                Err(KeyboardError::KeymapMapFailed { .. }) => {}
+                _ => panic!("the keymap should not be mapped"),
+            }
+        }
+        // the previous keymap stays in use
+        assert_eq!(state.get_one_sym_raw(KEY_A), keysyms::XKB_KEY_a);
+        assert_eq!(open_fds(), before);
+    }
+
+    fn key_event(utf8: &str) -> KeyEvent {
+        KeyEvent {
+            serial: 0,
+            time: 0,
+            mods: ModifiersState::default(),
+            rawkey: KEY_A,
+            keysym: keysyms::XKB_KEY_a,
+            layout: 0,
+            state: KeyState::Pressed,
+            origin: KeyOrigin::Device,
+            utf8: Some(utf8.to_owned()),
+            kind: KeyKind::classify(keysyms::XKB_KEY_a, Some(utf8)),
+            is_keypad: false,
+            nav: None,
+            repeat_count: 0,
+            degraded: false,
+        }
+    }
+
+    #[test]
+    fn key_event_graphemes() {
+        assert_eq!(key_event("e\u{301}").grapheme(), Some("e\u{301}"));
+        // a compose sequence or a text macro can produce several clusters
+        assert_eq!(key_event("e\u{301}a").grapheme(), None);
+        assert_eq!(key_event("e\u{301}a").graphemes(), vec!["e\u{301}", "a"]);
+    }
+
+    // the layers are driven by the tests, this is never called
+    fn no_layer_key(_: &mut EventQueueHandle, _: &mut (), _: &WlKeyboard, _: &KeyboardIdentity, _: &KeyEvent)
+                    -> Propagation {
+        Propagation::Continue
+    }
+
+    fn layers(names: &[&'static str]) -> Vec<KeyLayer<()>> {
+        names
+            .iter()
+            .map(|&name| KeyLayer {
+                name: name,
+                key: no_layer_key,
+            })
+            .collect()
+    }
+
+    fn triggered() -> Triggered {
+        Triggered {
+            shortcuts: vec![Shortcut::new(ShortcutKey::Keysym(keysyms::XKB_KEY_a))],
+            chord: None,
+            gestures: vec![(keysyms::XKB_KEY_a, KeyGesture::Tap)],
+            input_mode: Some(InputModeState::default()),
+        }
+    }
+
+    #[test]
+    fn layers_let_key_through() {
+        let mut called = Vec::new();
+        let delivery = run_layers(
+            &layers(&["first", "second"]),
+            |layer, _| {
+                called.push(layer.name);
+                Propagation::Continue
+            },
+            key_event("a"),
+            triggered(),
+        );
+        assert_eq!(called, vec!["first", "second"]);
+        let (event, triggered) = delivery.unwrap();
+        assert_eq!(event.utf8, Some("a".to_owned()));
+        assert_eq!(triggered.shortcuts.len(), 1);
+        assert_eq!(triggered.gestures.len(), 1);
+        assert!(triggered.input_mode.is_some());
+    }
+
+    #[test]
+    fn consuming_layer_stops_delivery() {
+        let mut called = Vec::new();
+        let delivery = run_layers(
+            &layers(&["first", "consuming", "last"]),
+            |layer, _| {
+                called.push(layer.name);
+                if layer.name == "consuming" {
+                    Propagation::Stop
+                } else {
+                    Propagation::Continue
+                }
+            },
+            key_event("a"),
+            triggered(),
+        );
+        assert_eq!(called, vec!["first", "consuming"]);
+        // neither the key nor its shortcuts, gestures and input mode change are delivered
+        assert!(delivery.is_none());
+    }
+
+    // evdev keycodes of a dead acute and of letters in the us(intl) layout
+    #[cfg(feature = "compose")]
+    const KEY_APOSTROPHE: u32 = 40;
+    #[cfg(feature = "compose")]
+    const KEY_E: u32 = 18;
+    #[cfg(feature = "compose")]
+    const KEY_X: u32 = 45;
+
+    #[cfg(feature = "compose")]
+    fn compose_keyboard(policy: ComposePolicy) -> KbState {
+        let options = KeyboardOptions {
+            compose_policy: policy,
+            compose_locale: Some("en_US.UTF-8".to_owned()),
+            ..Default::default()
+        };
+        let mut state = KbState::new(options).expect("libxkbcommon is needed to run the tests");
+        assert!(state.compose.is_some(), "the en_US.UTF-8 compose table is needed to run the tests");
+        load_layout(&mut state, "us", Some("intl"));
+        state
+    }
+
+    // press a key like the `key` callback does, returning its text
+    #[cfg(feature = "compose")]
+    fn press(state: &mut KbState, keycode: u32, time: u32) -> Option<String> {
+        let keysym = state.get_one_sym_raw(keycode);
+        state.check_compose_timeout(time);
+        state.press_utf8(keycode, keysym, time)
+    }
+
+    #[cfg(feature = "compose")]
+    fn take_compose_events(state: &mut KbState) -> Vec<ComposeEvent> {
+        state.compose_events.drain(..).collect()
+    }
+
+    #[cfg(feature = "compose")]
+    #[test]
+    fn compose_dead_key() {
+        let _serial = serial();
+        let mut state = compose_keyboard(ComposePolicy::Cancel);
+        assert_eq!(press(&mut state, KEY_APOSTROPHE, 0), None);
+        assert_eq!(take_compose_events(&mut state), vec![ComposeEvent::Preedit("\u{b4}".to_owned())]);
+        assert_eq!(press(&mut state, KEY_E, 10), Some("\u{e9}".to_owned()));
+        assert_eq!(take_compose_events(&mut state), vec![ComposeEvent::Committed("\u{e9}".to_owned())]);
+    }
+
+    #[cfg(feature = "compose")]
+    #[test]
+    fn compose_policy_cancel() {
+        let _serial = serial();
+        let mut state = compose_keyboard(ComposePolicy::Cancel);
+        press(&mut state, KEY_APOSTROPHE, 0);
+        take_compose_events(&mut state);
+        assert_eq!(press(&mut state, KEY_X, 10), None);
+        assert_eq!(
+            take_compose_events(&mut state),
+            vec![ComposeEvent::Cancelled(ComposeCancelReason::NoMatch)]
+        );
+        assert!(state.compose_pending().is_empty());
+        assert_eq!(press(&mut state, KEY_E, 20), Some("e".to_owned()));
+    }
+
+    #[cfg(feature = "compose")]
+    #[test]
+    fn compose_policy_buffer() {
+        let _serial = serial();
+        let mut state = compose_keyboard(ComposePolicy::Buffer);
+        press(&mut state, KEY_APOSTROPHE, 0);
+        take_compose_events(&mut state);
+        // the dead key has no text of its own
+        assert_eq!(press(&mut state, KEY_X, 10), Some("x".to_owned()));
+        assert_eq!(
+            take_compose_events(&mut state),
+            vec![ComposeEvent::Cancelled(ComposeCancelReason::NoMatch)]
+        );
+        assert!(state.compose_pending().is_empty());
+        assert_eq!(press(&mut state, KEY_E, 20), Some("e".to_owned()));
+    }
+
+    #[cfg(feature = "compose")]
+    #[test]
+    fn compose_policy_pass_through() {
+        let _serial = serial();
+        let mut state = compose_keyboard(ComposePolicy::PassThrough);
+        press(&mut state, KEY_APOSTROPHE, 0);
+        take_compose_events(&mut state);
+        assert_eq!(press(&mut state, KEY_X, 10), Some("x".to_owned()));
+        assert_eq!(take_compose_events(&mut state), vec![]);
+        assert_eq!(state.compose_pending(), &[keysyms::XKB_KEY_dead_acute]);
+        assert_eq!(press(&mut state, KEY_E, 20), Some("\u{e9}".to_owned()));
+        assert_eq!(take_compose_events(&mut state), vec![ComposeEvent::Committed("\u{e9}".to_owned())]);
+    }
+
+    #[cfg(feature = "compose")]
+    #[test]
+    fn compose_timeout() {
+        let _serial = serial();
+        let diagnostics = Diagnostics::new();
+        let mut state = compose_keyboard(ComposePolicy::Cancel);
+        state.options.compose_timeout = Some(Duration::from_millis(500));
+        state.options.diagnostics = Some(diagnostics.clone());
+        press(&mut state, KEY_APOSTROPHE, 1000);
+        take_compose_events(&mut state);
+        // within the timeout, the sequence goes on
+        assert_eq!(press(&mut state, KEY_E, 1499), Some("\u{e9}".to_owned()));
+        take_compose_events(&mut state);
+        assert_eq!(diagnostics.snapshot().compose_sequences_cancelled, 0);
+
+        press(&mut state, KEY_APOSTROPHE, 2000);
+        take_compose_events(&mut state);
+        assert_eq!(press(&mut state, KEY_E, 2500), Some("e".to_owned()));
+        assert_eq!(
+            take_compose_events(&mut state),
+            vec![ComposeEvent::Cancelled(ComposeCancelReason::Timeout)]
+        );
+        assert_eq!(diagnostics.snapshot().compose_sequences_cancelled, 1);
+        assert_eq!(diagnostics.snapshot().compose_sequences_composed, 1);
+    }
+
+    // evdev keycode of the S key
+    const KEY_S: u32 = 31;
+
+    // a us keyboard repeating every 40ms after 600ms
+    fn repeat_keyboard(policy: RepeatPolicy) -> KbState {
+        let options = KeyboardOptions {
+            compose: false,
+            repeat_info: Some((25, 600)),
+            repeat_policy: policy,
+            ..Default::default()
+        };
+        let mut state = KbState::new(options).expect("libxkbcommon is needed to run the tests");
+        load_us(&mut state);
+        state
+    }
+
+    fn press_repeating(state: &mut KbState, keycode: u32, time: u32, now: Instant) {
+        let keysym = state.get_one_sym_raw(keycode);
+        let layout = state.key_layout(keycode);
+        state.track_repeat(keycode, 0, time, keysym, layout, KeyState::Pressed, now);
+    }
+
+    fn after_ms(now: Instant, ms: u64) -> Instant {
+        now + Duration::from_millis(ms)
+    }
+
+    #[test]
+    fn repeat_new_press_cancels() {
+        let _serial = serial();
+        let now = Instant::now();
+        for &cancels in &[true, false] {
+            let mut state = repeat_keyboard(RepeatPolicy {
+                new_press_cancels: cancels,
+                ..Default::default()
+            });
+            press_repeating(&mut state, KEY_A, 0, now);
+            press_repeating(&mut state, KEY_S, 100, after_ms(now, 100));
+            let events = state.repeat_events(after_ms(now, 700));
+            let expected = if cancels { KEY_S } else { KEY_A };
+            assert_eq!(events.iter().map(|e| e.rawkey).collect::<Vec<_>>(), vec![expected]);
+        }
+    }
+
+    #[test]
+    fn repeat_retranslate_on_modifiers() {
+        let _serial = serial();
+        let now = Instant::now();
+        for &retranslate in &[true, false] {
+            let mut state = repeat_keyboard(RepeatPolicy {
+                retranslate_on_modifiers: retranslate,
+                ..Default::default()
+            });
+            press_repeating(&mut state, KEY_A, 0, now);
+            state.update_modifiers(SHIFT);
+            let events = state.repeat_events(after_ms(now, 600));
+            assert_eq!(events.len(), 1);
+            if retranslate {
+                assert_eq!(events[0].keysym, keysyms::XKB_KEY_A);
+                assert_eq!(events[0].utf8, Some("A".to_owned()));
+                assert!(events[0].mods.shift());
+            } else {
+                assert_eq!(events[0].keysym, keysyms::XKB_KEY_a);
+                assert_eq!(events[0].utf8, Some("a".to_owned()));
+                assert!(!events[0].mods.shift());
+            }
+        }
+    }
+
+    #[test]
+    fn repeat_stop_on_modifiers() {
+        let _serial = serial();
+        let now = Instant::now();
+        for &stop in &[true, false] {
+            let mut state = repeat_keyboard(RepeatPolicy {
+                stop_on_modifiers: stop,
+                ..Default::default()
+            });
+            press_repeating(&mut state, KEY_A, 0, now);
+            state.update_modifiers(SHIFT);
+            assert_eq!(state.repeat.is_none(), stop);
+            let events = state.repeat_events(after_ms(now, 600));
+            assert_eq!(events.len(), if stop { 0 } else { 1 });
+        }
+    }
+
+    #[test]
+    fn repeat_coalesce() {
+        let _serial = serial();
+        let now = Instant::now();
+        for &coalesce in &[true, false] {
+            let mut state = repeat_keyboard(RepeatPolicy {
+                coalesce: coalesce,
+                ..Default::default()
+            });
+            press_repeating(&mut state, KEY_A, 0, now);
+            // polled late, the first repeat and the nine following ones are due
+            let events = state.repeat_events(after_ms(now, 960));
+            let counts: Vec<u32> = events.iter().map(|e| e.repeat_count).collect();
+            if coalesce {
+                assert_eq!(counts, vec![10]);
+            } else {
+                assert_eq!(counts, (1..11).collect::<Vec<_>>());
+            }
+            assert_eq!(events.last().map(|e| e.time), Some(960));
+        }
+    }
+
+    #[test]
+    fn serialized_state_round_trip() {
+        let _serial = serial();
+        let mut state = keyboard();
+        load_layout(&mut state, "us,de", None);
+        // Shift depressed and Caps Lock locked, the second layout latched over the first
+        let serialized = SerializedState {
+            mods_depressed: 1,
+            mods_latched: 0,
+            mods_locked: 2,
+            group: 1,
+            layout_depressed: 0,
+            layout_latched: 1,
+            layout_locked: 0,
+        };
+        state.apply_serialized(serialized);
+        assert_eq!(
+            state.raw_masks,
+            StateMasks {
+                depressed: 1,
+                latched: 0,
+                locked: 2,
+                depressed_layout: 0,
+                latched_layout: 1,
+                locked_layout: 0,
+            }
+        );
+        assert!(state.mods_state.shift());
+        assert!(state.mods_state.caps_lock());
+        assert_eq!(state.serialize(), Some(serialized));
     }
 }
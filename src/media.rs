@@ -0,0 +1,390 @@
+use ffi::keysyms;
+
+/// A multimedia, browser or launcher key, from the XF86 keysyms
+///
+/// Common keys have their own variant, the other XF86 keysyms are kept as
+/// `MediaKey::Other`. See `MediaKey::from_keysym()`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum MediaKey {
+    /// Raise the volume
+    VolumeUp,
+    /// Lower the volume
+    VolumeDown,
+    /// Mute the sound
+    Mute,
+    /// Mute the microphone
+    MicMute,
+    /// Select an equalizer preset
+    AudioPreset,
+    /// Start playback
+    ///
+    /// Most keyboards send this for a combined play/pause key.
+    Play,
+    /// Pause playback
+    Pause,
+    /// Stop playback
+    Stop,
+    /// Go to the previous track
+    Previous,
+    /// Go to the next track
+    Next,
+    /// Start recording
+    Record,
+    /// Rewind
+    Rewind,
+    /// Fast forward
+    FastForward,
+    /// Toggle repeat mode
+    Repeat,
+    /// Toggle shuffle mode
+    Shuffle,
+    /// Cycle through the audio tracks
+    CycleTrack,
+    /// Toggle subtitles
+    Subtitle,
+    /// Eject the media
+    Eject,
+    /// Go back, like in a browser
+    Back,
+    /// Go forward, like in a browser
+    Forward,
+    /// Stop loading the page
+    BrowserStop,
+    /// Reload the page
+    Refresh,
+    /// Go to the home page
+    HomePage,
+    /// Search
+    Search,
+    /// Show the favorites
+    Favorites,
+    /// Add the page to the favorites
+    AddFavorite,
+    /// Show the history
+    History,
+    /// Open the URL of the selection
+    OpenUrl,
+    /// Launch the web browser
+    WebBrowser,
+    /// Launch the mail client
+    Mail,
+    /// Launch the calculator
+    Calculator,
+    /// Launch the calendar
+    Calendar,
+    /// Open the file manager on the computer
+    MyComputer,
+    /// Open the documents
+    Documents,
+    /// Launch the file manager
+    Explorer,
+    /// Launch a terminal
+    Terminal,
+    /// Launch the music player
+    Music,
+    /// Launch the media player
+    MediaPlayer,
+    /// Open the pictures
+    Pictures,
+    /// Launch the video player
+    Video,
+    /// Launch the instant messenger
+    Messenger,
+    /// Launch the webcam application
+    WebCam,
+    /// Launch the phone application
+    Phone,
+    /// Launch a game
+    Game,
+    /// Open the control panel
+    ControlPanel,
+    /// Launch the voice assistant
+    Assistant,
+    /// One of the 16 programmable launch keys, `XF86Launch0` to `XF86LaunchF`
+    Launch(u8),
+    /// Power off
+    PowerOff,
+    /// Suspend to RAM
+    Suspend,
+    /// Suspend to disk
+    Hibernate,
+    /// Put the system to sleep
+    Sleep,
+    /// Wake up the system
+    WakeUp,
+    /// Log off
+    LogOff,
+    /// Start the screen saver or lock the screen
+    ScreenSaver,
+    /// Show the battery status
+    Battery,
+    /// Raise the brightness of the screen
+    BrightnessUp,
+    /// Lower the brightness of the screen
+    BrightnessDown,
+    /// Cycle the brightness of the screen
+    BrightnessCycle,
+    /// Toggle automatic brightness of the screen
+    BrightnessAuto,
+    /// Toggle the keyboard backlight
+    KbdLightOnOff,
+    /// Raise the brightness of the keyboard backlight
+    KbdBrightnessUp,
+    /// Lower the brightness of the keyboard backlight
+    KbdBrightnessDown,
+    /// Switch the video output
+    Display,
+    /// Turn the screen off
+    DisplayOff,
+    /// Toggle the screen rotation lock
+    RotationLockToggle,
+    /// Toggle fullscreen
+    FullScreen,
+    /// Zoom in
+    ZoomIn,
+    /// Zoom out
+    ZoomOut,
+    /// Reset the zoom
+    ZoomReset,
+    /// Toggle the wireless network
+    Wlan,
+    /// Toggle bluetooth
+    Bluetooth,
+    /// Toggle ultra wide band
+    Uwb,
+    /// Toggle the mobile broadband (LTE, UMTS, etc.)
+    Wwan,
+    /// Toggle all radios
+    RfKill,
+    /// Toggle the touchpad
+    TouchpadToggle,
+    /// The touchpad was switched on
+    TouchpadOn,
+    /// The touchpad was switched off
+    TouchpadOff,
+    /// Toggle the on-screen keyboard
+    OnScreenKeyboard,
+    /// Copy the selection
+    Copy,
+    /// Cut the selection
+    Cut,
+    /// Paste the clipboard
+    Paste,
+    /// Create a new document
+    New,
+    /// Open a document
+    Open,
+    /// Close the document or window
+    Close,
+    /// Save the document
+    Save,
+    /// Send the mail
+    Send,
+    /// Reply to the mail
+    Reply,
+    /// Forward the mail
+    MailForward,
+    /// Check the spelling
+    Spell,
+    /// Any other XF86 keysym, with its value
+    Other(u32),
+}
+
+/// The kind of a media key
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum MediaCategory {
+    /// Volume and microphone keys
+    Audio,
+    /// Media playback keys, like Play or Next
+    Playback,
+    /// Web browser navigation keys
+    Browser,
+    /// Keys launching an application
+    Launcher,
+    /// Power and session keys
+    Power,
+    /// Brightness, video output and zoom keys
+    Display,
+    /// Keys toggling radios
+    Wireless,
+    /// Keys toggling input devices
+    Input,
+    /// Document editing and mail keys
+    Edit,
+    /// Other XF86 keys
+    Other,
+}
+
+// the keysym of each variant, except `Launch` and `Other`
+const MEDIA_KEYS: &'static [(u32, MediaKey)] = &[
+    (keysyms::XKB_KEY_XF86AudioRaiseVolume, MediaKey::VolumeUp),
+    (keysyms::XKB_KEY_XF86AudioLowerVolume, MediaKey::VolumeDown),
+    (keysyms::XKB_KEY_XF86AudioMute, MediaKey::Mute),
+    (keysyms::XKB_KEY_XF86AudioMicMute, MediaKey::MicMute),
+    (keysyms::XKB_KEY_XF86AudioPreset, MediaKey::AudioPreset),
+    (keysyms::XKB_KEY_XF86AudioPlay, MediaKey::Play),
+    (keysyms::XKB_KEY_XF86AudioPause, MediaKey::Pause),
+    (keysyms::XKB_KEY_XF86AudioStop, MediaKey::Stop),
+    (keysyms::XKB_KEY_XF86AudioPrev, MediaKey::Previous),
+    (keysyms::XKB_KEY_XF86AudioNext, MediaKey::Next),
+    (keysyms::XKB_KEY_XF86AudioRecord, MediaKey::Record),
+    (keysyms::XKB_KEY_XF86AudioRewind, MediaKey::Rewind),
+    (keysyms::XKB_KEY_XF86AudioForward, MediaKey::FastForward),
+    (keysyms::XKB_KEY_XF86AudioRepeat, MediaKey::Repeat),
+    (keysyms::XKB_KEY_XF86AudioRandomPlay, MediaKey::Shuffle),
+    (keysyms::XKB_KEY_XF86AudioCycleTrack, MediaKey::CycleTrack),
+    (keysyms::XKB_KEY_XF86Subtitle, MediaKey::Subtitle),
+    (keysyms::XKB_KEY_XF86Eject, MediaKey::Eject),
+    (keysyms::XKB_KEY_XF86Back, MediaKey::Back),
+    (keysyms::XKB_KEY_XF86Forward, MediaKey::Forward),
+    (keysyms::XKB_KEY_XF86Stop, MediaKey::BrowserStop),
+    (keysyms::XKB_KEY_XF86Refresh, MediaKey::Refresh),
+    (keysyms::XKB_KEY_XF86Reload, MediaKey::Refresh),
+    (keysyms::XKB_KEY_XF86HomePage, MediaKey::HomePage),
+    (keysyms::XKB_KEY_XF86Search, MediaKey::Search),
+    (keysyms::XKB_KEY_XF86Favorites, MediaKey::Favorites),
+    (keysyms::XKB_KEY_XF86AddFavorite, MediaKey::AddFavorite),
+    (keysyms::XKB_KEY_XF86History, MediaKey::History),
+    (keysyms::XKB_KEY_XF86OpenURL, MediaKey::OpenUrl),
+    (keysyms::XKB_KEY_XF86WWW, MediaKey::WebBrowser),
+    (keysyms::XKB_KEY_XF86Mail, MediaKey::Mail),
+    (keysyms::XKB_KEY_XF86Calculator, MediaKey::Calculator),
+    // misspelled duplicate of XF86Calculator
+    (keysyms::XKB_KEY_XF86Calculater, MediaKey::Calculator),
+    (keysyms::XKB_KEY_XF86Calendar, MediaKey::Calendar),
+    (keysyms::XKB_KEY_XF86MyComputer, MediaKey::MyComputer),
+    (keysyms::XKB_KEY_XF86Documents, MediaKey::Documents),
+    (keysyms::XKB_KEY_XF86Explorer, MediaKey::Explorer),
+    (keysyms::XKB_KEY_XF86Terminal, MediaKey::Terminal),
+    (keysyms::XKB_KEY_XF86Music, MediaKey::Music),
+    (keysyms::XKB_KEY_XF86AudioMedia, MediaKey::MediaPlayer),
+    (keysyms::XKB_KEY_XF86Pictures, MediaKey::Pictures),
+    (keysyms::XKB_KEY_XF86Video, MediaKey::Video),
+    (keysyms::XKB_KEY_XF86Messenger, MediaKey::Messenger),
+    (keysyms::XKB_KEY_XF86WebCam, MediaKey::WebCam),
+    (keysyms::XKB_KEY_XF86Phone, MediaKey::Phone),
+    (keysyms::XKB_KEY_XF86Game, MediaKey::Game),
+    (keysyms::XKB_KEY_XF86ControlPanel, MediaKey::ControlPanel),
+    (keysyms::XKB_KEY_XF86Assistant, MediaKey::Assistant),
+    (keysyms::XKB_KEY_XF86PowerOff, MediaKey::PowerOff),
+    (keysyms::XKB_KEY_XF86PowerDown, MediaKey::PowerOff),
+    (keysyms::XKB_KEY_XF86Suspend, MediaKey::Suspend),
+    (keysyms::XKB_KEY_XF86Standby, MediaKey::Suspend),
+    (keysyms::XKB_KEY_XF86Hibernate, MediaKey::Hibernate),
+    (keysyms::XKB_KEY_XF86Sleep, MediaKey::Sleep),
+    (keysyms::XKB_KEY_XF86WakeUp, MediaKey::WakeUp),
+    (keysyms::XKB_KEY_XF86LogOff, MediaKey::LogOff),
+    (keysyms::XKB_KEY_XF86ScreenSaver, MediaKey::ScreenSaver),
+    (keysyms::XKB_KEY_XF86Screensaver, MediaKey::ScreenSaver),
+    (keysyms::XKB_KEY_XF86Battery, MediaKey::Battery),
+    (keysyms::XKB_KEY_XF86MonBrightnessUp, MediaKey::BrightnessUp),
+    (keysyms::XKB_KEY_XF86MonBrightnessDown, MediaKey::BrightnessDown),
+    (keysyms::XKB_KEY_XF86MonBrightnessCycle, MediaKey::BrightnessCycle),
+    (keysyms::XKB_KEY_XF86BrightnessAuto, MediaKey::BrightnessAuto),
+    (keysyms::XKB_KEY_XF86KbdLightOnOff, MediaKey::KbdLightOnOff),
+    (keysyms::XKB_KEY_XF86KbdBrightnessUp, MediaKey::KbdBrightnessUp),
+    (keysyms::XKB_KEY_XF86KbdBrightnessDown, MediaKey::KbdBrightnessDown),
+    (keysyms::XKB_KEY_XF86Display, MediaKey::Display),
+    (keysyms::XKB_KEY_XF86DisplayOff, MediaKey::DisplayOff),
+    (keysyms::XKB_KEY_XF86RotationLockToggle, MediaKey::RotationLockToggle),
+    (keysyms::XKB_KEY_XF86FullScreen, MediaKey::FullScreen),
+    (keysyms::XKB_KEY_XF86ZoomIn, MediaKey::ZoomIn),
+    (keysyms::XKB_KEY_XF86ZoomOut, MediaKey::ZoomOut),
+    (keysyms::XKB_KEY_XF86ZoomReset, MediaKey::ZoomReset),
+    (keysyms::XKB_KEY_XF86WLAN, MediaKey::Wlan),
+    (keysyms::XKB_KEY_XF86Bluetooth, MediaKey::Bluetooth),
+    (keysyms::XKB_KEY_XF86UWB, MediaKey::Uwb),
+    (keysyms::XKB_KEY_XF86WWAN, MediaKey::Wwan),
+    (keysyms::XKB_KEY_XF86RFKill, MediaKey::RfKill),
+    (keysyms::XKB_KEY_XF86TouchpadToggle, MediaKey::TouchpadToggle),
+    (keysyms::XKB_KEY_XF86TouchpadOn, MediaKey::TouchpadOn),
+    (keysyms::XKB_KEY_XF86TouchpadOff, MediaKey::TouchpadOff),
+    (keysyms::XKB_KEY_XF86OnScreenKeyboard, MediaKey::OnScreenKeyboard),
+    (keysyms::XKB_KEY_XF86Copy, MediaKey::Copy),
+    (keysyms::XKB_KEY_XF86Cut, MediaKey::Cut),
+    (keysyms::XKB_KEY_XF86Paste, MediaKey::Paste),
+    (keysyms::XKB_KEY_XF86New, MediaKey::New),
+    (keysyms::XKB_KEY_XF86Open, MediaKey::Open),
+    (keysyms::XKB_KEY_XF86Close, MediaKey::Close),
+    (keysyms::XKB_KEY_XF86Save, MediaKey::Save),
+    (keysyms::XKB_KEY_XF86Send, MediaKey::Send),
+    (keysyms::XKB_KEY_XF86Reply, MediaKey::Reply),
+    (keysyms::XKB_KEY_XF86MailForward, MediaKey::MailForward),
+    (keysyms::XKB_KEY_XF86Spell, MediaKey::Spell),
+];
+
+impl MediaKey {
+    /// Get the media key of a keysym
+    ///
+    /// Returns `None` if the keysym is not an XF86 keysym. The XF86 keysyms of the
+    /// X server actions, like `XF86Switch_VT_1`, are not media keys either.
+    pub fn from_keysym(keysym: u32) -> Option<MediaKey> {
+        if keysym >= keysyms::XKB_KEY_XF86Launch0 && keysym <= keysyms::XKB_KEY_XF86LaunchF {
+            return Some(MediaKey::Launch((keysym - keysyms::XKB_KEY_XF86Launch0) as u8));
+        }
+        if let Some(&(_, key)) = MEDIA_KEYS.iter().find(|&&(sym, _)| sym == keysym) {
+            return Some(key);
+        }
+        // 0x1008fe00 to 0x1008feff are the X server actions
+        let server_action = keysym >= 0x1008_fe00 && keysym <= 0x1008_feff;
+        if keysym >= 0x1008_0001 && keysym <= 0x1008_ffff && !server_action {
+            Some(MediaKey::Other(keysym))
+        } else {
+            None
+        }
+    }
+
+    /// The keysym of this media key
+    ///
+    /// Keys with several keysyms, like `Refresh`, return the most common one.
+    pub fn keysym(&self) -> u32 {
+        match *self {
+            MediaKey::Launch(n) => keysyms::XKB_KEY_XF86Launch0 + u32::from(n),
+            MediaKey::Other(keysym) => keysym,
+            key => MEDIA_KEYS
+                .iter()
+                .find(|&&(_, k)| k == key)
+                .map(|&(sym, _)| sym)
+                .unwrap_or(0),
+        }
+    }
+
+    /// The kind of this media key
+    pub fn category(&self) -> MediaCategory {
+        match *self {
+            MediaKey::VolumeUp | MediaKey::VolumeDown | MediaKey::Mute | MediaKey::MicMute
+            | MediaKey::AudioPreset => MediaCategory::Audio,
+            MediaKey::Play | MediaKey::Pause | MediaKey::Stop | MediaKey::Previous | MediaKey::Next
+            | MediaKey::Record | MediaKey::Rewind | MediaKey::FastForward | MediaKey::Repeat
+            | MediaKey::Shuffle | MediaKey::CycleTrack | MediaKey::Subtitle | MediaKey::Eject => {
+                MediaCategory::Playback
+            }
+            MediaKey::Back | MediaKey::Forward | MediaKey::BrowserStop | MediaKey::Refresh
+            | MediaKey::HomePage | MediaKey::Search | MediaKey::Favorites | MediaKey::AddFavorite
+            | MediaKey::History | MediaKey::OpenUrl => MediaCategory::Browser,
+            MediaKey::WebBrowser | MediaKey::Mail | MediaKey::Calculator | MediaKey::Calendar
+            | MediaKey::MyComputer | MediaKey::Documents | MediaKey::Explorer | MediaKey::Terminal
+            | MediaKey::Music | MediaKey::MediaPlayer | MediaKey::Pictures | MediaKey::Video
+            | MediaKey::Messenger | MediaKey::WebCam | MediaKey::Phone | MediaKey::Game
+            | MediaKey::ControlPanel | MediaKey::Assistant | MediaKey::Launch(_) => MediaCategory::Launcher,
+            MediaKey::PowerOff | MediaKey::Suspend | MediaKey::Hibernate | MediaKey::Sleep
+            | MediaKey::WakeUp | MediaKey::LogOff | MediaKey::ScreenSaver | MediaKey::Battery => {
+                MediaCategory::Power
+            }
+            MediaKey::BrightnessUp | MediaKey::BrightnessDown | MediaKey::BrightnessCycle
+            | MediaKey::BrightnessAuto | MediaKey::KbdLightOnOff | MediaKey::KbdBrightnessUp
+            | MediaKey::KbdBrightnessDown | MediaKey::Display | MediaKey::DisplayOff
+            | MediaKey::RotationLockToggle | MediaKey::FullScreen | MediaKey::ZoomIn | MediaKey::ZoomOut
+            | MediaKey::ZoomReset => MediaCategory::Display,
+            MediaKey::Wlan | MediaKey::Bluetooth | MediaKey::Uwb | MediaKey::Wwan | MediaKey::RfKill => {
+                MediaCategory::Wireless
+            }
+            MediaKey::TouchpadToggle | MediaKey::TouchpadOn | MediaKey::TouchpadOff
+            | MediaKey::OnScreenKeyboard => MediaCategory::Input,
+            MediaKey::Copy | MediaKey::Cut | MediaKey::Paste | MediaKey::New | MediaKey::Open
+            | MediaKey::Close | MediaKey::Save | MediaKey::Send | MediaKey::Reply | MediaKey::MailForward
+            | MediaKey::Spell => MediaCategory::Edit,
+            MediaKey::Other(_) => MediaCategory::Other,
+        }
+    }
+}
@@ -0,0 +1,142 @@
+/// What to do with the synthesized events exceeding `KeyboardOptions::max_queued_events`
+#[derive(Copy, Clone, Debug)]
+pub enum OverflowPolicy {
+    /// Drop the oldest events, keeping the most recent ones
+    DropOldest,
+    /// Drop the newest events
+    DropNewest,
+    /// Drop the newest events, and call this function with the number of events dropped
+    Callback(fn(dropped: usize)),
+}
+
+impl Default for OverflowPolicy {
+    fn default() -> OverflowPolicy {
+        OverflowPolicy::DropOldest
+    }
+}
+
+impl OverflowPolicy {
+    /// Bound a queue of events to `max` events, returning how many were dropped
+    pub fn apply<T>(self, events: &mut Vec<T>, max: Option<usize>) -> usize {
+        let max = match max {
+            Some(max) if events.len() > max => max,
+            _ => return 0,
+        };
+        let dropped = events.len() - max;
+        match self {
+            OverflowPolicy::DropOldest => {
+                events.drain(..dropped);
+            }
+            OverflowPolicy::DropNewest => events.truncate(max),
+            OverflowPolicy::Callback(callback) => {
+                events.truncate(max);
+                callback(dropped);
+            }
+        }
+        dropped
+    }
+
+    /// Bound `count` events about to be generated to `max` events
+    ///
+    /// Returns how many of the oldest and of the newest should be dropped, so that
+    /// they are never generated.
+    pub fn bound(self, count: usize, max: Option<usize>) -> (usize, usize) {
+        let dropped = match max {
+            Some(max) if count > max => count - max,
+            _ => return (0, 0),
+        };
+        match self {
+            OverflowPolicy::DropOldest => (dropped, 0),
+            OverflowPolicy::DropNewest => (0, dropped),
+            OverflowPolicy::Callback(callback) => {
+                callback(dropped);
+                (0, dropped)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    lazy_static! {
+        // the events dropped by `Callback`, which only takes a function pointer
+        static ref CALLBACK_DROPPED: AtomicUsize = AtomicUsize::new(0);
+    }
+
+    fn record_dropped(dropped: usize) {
+        CALLBACK_DROPPED.fetch_add(dropped, Ordering::SeqCst);
+    }
+
+    fn events() -> Vec<u32> {
+        vec![1, 2, 3, 4, 5]
+    }
+
+    #[test]
+    fn apply_drop_oldest() {
+        let mut queue = events();
+        assert_eq!(OverflowPolicy::DropOldest.apply(&mut queue, Some(3)), 2);
+        assert_eq!(queue, vec![3, 4, 5]);
+        assert_eq!(OverflowPolicy::DropOldest.apply(&mut queue, Some(0)), 3);
+        assert!(queue.is_empty());
+    }
+
+    #[test]
+    fn apply_drop_newest() {
+        let mut queue = events();
+        assert_eq!(OverflowPolicy::DropNewest.apply(&mut queue, Some(3)), 2);
+        assert_eq!(queue, vec![1, 2, 3]);
+        assert_eq!(OverflowPolicy::DropNewest.apply(&mut queue, Some(0)), 3);
+        assert!(queue.is_empty());
+    }
+
+    #[test]
+    fn apply_unbounded() {
+        for &policy in &[OverflowPolicy::DropOldest, OverflowPolicy::DropNewest] {
+            let mut queue = events();
+            assert_eq!(policy.apply(&mut queue, None), 0);
+            assert_eq!(policy.apply(&mut queue, Some(5)), 0);
+            assert_eq!(queue, events());
+        }
+    }
+
+    #[test]
+    fn bound_drop_oldest() {
+        assert_eq!(OverflowPolicy::DropOldest.bound(5, Some(3)), (2, 0));
+        assert_eq!(OverflowPolicy::DropOldest.bound(5, Some(0)), (5, 0));
+        assert_eq!(OverflowPolicy::DropOldest.bound(5, Some(5)), (0, 0));
+        assert_eq!(OverflowPolicy::DropOldest.bound(5, None), (0, 0));
+    }
+
+    #[test]
+    fn bound_drop_newest() {
+        assert_eq!(OverflowPolicy::DropNewest.bound(5, Some(3)), (0, 2));
+        assert_eq!(OverflowPolicy::DropNewest.bound(5, Some(0)), (0, 5));
+        assert_eq!(OverflowPolicy::DropNewest.bound(5, Some(5)), (0, 0));
+        assert_eq!(OverflowPolicy::DropNewest.bound(5, None), (0, 0));
+    }
+
+    // a single test uses the callback, so that the count is not shared between threads
+    #[test]
+    fn callback() {
+        let policy = OverflowPolicy::Callback(record_dropped);
+        let mut queue = events();
+        assert_eq!(policy.apply(&mut queue, None), 0);
+        assert_eq!(CALLBACK_DROPPED.load(Ordering::SeqCst), 0);
+        assert_eq!(policy.apply(&mut queue, Some(3)), 2);
+        assert_eq!(queue, vec![1, 2, 3]);
+        assert_eq!(CALLBACK_DROPPED.load(Ordering::SeqCst), 2);
+        assert_eq!(policy.apply(&mut queue, Some(0)), 3);
+        assert!(queue.is_empty());
+        assert_eq!(CALLBACK_DROPPED.load(Ordering::SeqCst), 5);
+
+        assert_eq!(policy.bound(5, None), (0, 0));
+        assert_eq!(CALLBACK_DROPPED.load(Ordering::SeqCst), 5);
+        assert_eq!(policy.bound(5, Some(3)), (0, 2));
+        assert_eq!(CALLBACK_DROPPED.load(Ordering::SeqCst), 7);
+        assert_eq!(policy.bound(5, Some(0)), (0, 5));
+        assert_eq!(CALLBACK_DROPPED.load(Ordering::SeqCst), 12);
+    }
+}
@@ -0,0 +1,58 @@
+use keysym_names::keysym_name;
+use mapped_keyboard::{KeyEvent, Modifiers};
+use wayland_client::protocol::wl_keyboard::KeyState;
+
+/// A key event in a stable form, to be exchanged between processes
+///
+/// With the `serde` feature, it can be serialized, for example to send the key events
+/// of this crate to a remote input or macro daemon over a socket. It only holds plain
+/// data: the keysym is also given by name, so that the receiver needs neither the
+/// keymap nor libxkbcommon.
+///
+/// The format is stable: fields may be added in later versions, with a default value
+/// when they are missing, but none will be removed or renamed.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct PortableKeyEvent {
+    /// The serial of the event
+    pub serial: u32,
+    /// The timestamp of the event, in milliseconds
+    pub time: u32,
+    /// The raw evdev keycode of the key
+    pub rawkey: u32,
+    /// The keysym produced by the key
+    pub keysym: u32,
+    /// The name of the keysym, like `"a"` or `"Return"`, if it has one
+    pub keysym_name: Option<String>,
+    /// The index of the layout the key was translated with
+    pub layout: u32,
+    /// Whether the key was pressed, repeats included
+    pub pressed: bool,
+    /// The number of this repeat of a held key, 0 for the events of the compositor
+    pub repeat_count: u32,
+    /// The text produced by the key press, if any
+    pub utf8: Option<String>,
+    /// The active modifiers
+    pub modifiers: Modifiers,
+}
+
+#[cfg(feature = "serde")]
+serde_struct!(PortableKeyEvent {
+    serial, time, rawkey, keysym, keysym_name, layout, pressed, repeat_count, utf8, modifiers
+});
+
+impl<'a> From<&'a KeyEvent> for PortableKeyEvent {
+    fn from(event: &'a KeyEvent) -> PortableKeyEvent {
+        PortableKeyEvent {
+            serial: event.serial,
+            time: event.time,
+            rawkey: event.rawkey,
+            keysym: event.keysym,
+            keysym_name: keysym_name(event.keysym).map(|name| name.to_owned()),
+            layout: event.layout,
+            pressed: event.state == KeyState::Pressed,
+            repeat_count: event.repeat_count,
+            utf8: event.utf8.clone(),
+            modifiers: event.mods.modifiers,
+        }
+    }
+}
@@ -0,0 +1,121 @@
+use keysym_names::{keysym_from_name, keysym_name};
+use shortcut::{Shortcut, ShortcutKey};
+
+/// The shortcuts of an application, bound through the GlobalShortcuts portal
+///
+/// The GlobalShortcuts portal of xdg-desktop-portal lets the compositor trigger the
+/// shortcuts of an application even when none of its surfaces has the keyboard focus.
+/// This crate does not talk to D-Bus: the application calls the portal with its own
+/// connection, and this translates the shortcuts to the portal and its activations
+/// back, so that global and focused shortcuts share the same `Shortcut` type:
+///
+/// ```ignore
+/// let mut global = GlobalShortcuts::new();
+/// global.add("screenshot", "Take a screenshot", screenshot_shortcut.clone());
+/// // call org.freedesktop.portal.GlobalShortcuts.BindShortcuts with
+/// // global.bind_arguments(), then for each Activated signal:
+/// if let Some(shortcut) = global.activated(&shortcut_id) {
+///     handle_shortcut(shortcut);
+/// }
+/// ```
+///
+/// The compositor chooses the actual trigger, the shortcut is only a preference.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct GlobalShortcuts {
+    shortcuts: Vec<(String, String, Shortcut)>,
+}
+
+impl GlobalShortcuts {
+    /// An empty set of shortcuts
+    pub fn new() -> GlobalShortcuts {
+        GlobalShortcuts {
+            shortcuts: Vec::new(),
+        }
+    }
+
+    /// Add a shortcut, with its portal id and a description shown to the user
+    ///
+    /// A shortcut with the same id is replaced.
+    pub fn add<I: Into<String>, D: Into<String>>(&mut self, id: I, description: D, shortcut: Shortcut) {
+        let id = id.into();
+        self.shortcuts.retain(|&(ref other, _, _)| *other != id);
+        self.shortcuts.push((id, description.into(), shortcut));
+    }
+
+    /// The `shortcuts` argument of the `BindShortcuts` method, of D-Bus type `a(sa{sv})`
+    ///
+    /// Each shortcut gives its id, with its `description` and, when it can be written in
+    /// the format of the portal, its `preferred_trigger`. All values are strings.
+    pub fn bind_arguments(&self) -> Vec<(String, Vec<(&'static str, String)>)> {
+        self.shortcuts
+            .iter()
+            .map(|&(ref id, ref description, ref shortcut)| {
+                let mut options = vec![("description", description.clone())];
+                if let Some(trigger) = portal_trigger(shortcut) {
+                    options.push(("preferred_trigger", trigger));
+                }
+                (id.clone(), options)
+            })
+            .collect()
+    }
+
+    /// The shortcut triggered by an `Activated` signal, from its `shortcut_id`
+    pub fn activated(&self, shortcut_id: &str) -> Option<&Shortcut> {
+        self.shortcuts
+            .iter()
+            .find(|&&(ref id, _, _)| id == shortcut_id)
+            .map(|&(_, _, ref shortcut)| shortcut)
+    }
+}
+
+/// Write a shortcut as a trigger of the GlobalShortcuts portal, like `"CTRL+SHIFT+a"`
+///
+/// This is the format of the XDG shortcuts specification: modifiers followed by the
+/// name of the keysym, joined by `+`. Returns `None` for shortcuts on physical keys and
+/// for keysyms without a name.
+pub fn portal_trigger(shortcut: &Shortcut) -> Option<String> {
+    let keysym = match shortcut.key {
+        ShortcutKey::Keysym(keysym) => keysym,
+        ShortcutKey::Name(_) => return None,
+    };
+    let name = match keysym_name(keysym) {
+        Some(name) => name,
+        None => return None,
+    };
+    let mut parts = Vec::new();
+    for &(active, modifier) in &[
+        (shortcut.ctrl, "CTRL"),
+        (shortcut.alt, "ALT"),
+        (shortcut.shift, "SHIFT"),
+        (shortcut.logo, "LOGO"),
+    ] {
+        if active {
+            parts.push(modifier);
+        }
+    }
+    parts.push(name);
+    Some(parts.join("+"))
+}
+
+/// Read a trigger of the GlobalShortcuts portal, like `"CTRL+SHIFT+a"`
+///
+/// Modifiers are matched without case. Returns `None` if a modifier or the keysym is
+/// unknown, or if there is no keysym.
+pub fn shortcut_from_portal_trigger(trigger: &str) -> Option<Shortcut> {
+    let mut parts: Vec<&str> = trigger.split('+').collect();
+    let keysym = match parts.pop().and_then(keysym_from_name) {
+        Some(keysym) => keysym,
+        None => return None,
+    };
+    let mut shortcut = Shortcut::new(ShortcutKey::Keysym(keysym));
+    for modifier in parts {
+        match &modifier.to_uppercase()[..] {
+            "CTRL" | "CONTROL" => shortcut.ctrl = true,
+            "ALT" => shortcut.alt = true,
+            "SHIFT" => shortcut.shift = true,
+            "LOGO" | "SUPER" => shortcut.logo = true,
+            _ => return None,
+        }
+    }
+    Some(shortcut)
+}
@@ -0,0 +1,181 @@
+use ffi::registry::{self, rxkb_context_flags, XkbRegistry, XKBREGISTRY_OPTION};
+use std::ffi::{CStr, CString};
+use std::os::raw::c_char;
+
+/// The keyboard models, layouts and options known to the system
+///
+/// This is read from the XKB rules using libxkbregistry, and is meant for building
+/// keyboard settings dialogs. The names can be used in a `RMLVO`.
+///
+/// Only available with the `registry` cargo feature.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Registry {
+    /// The keyboard models
+    pub models: Vec<RegistryModel>,
+    /// The layouts and their variants
+    pub layouts: Vec<RegistryLayout>,
+    /// The groups of options
+    pub option_groups: Vec<RegistryOptionGroup>,
+}
+
+/// A keyboard model of the registry
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RegistryModel {
+    /// The name of the model, as used in the `model` field of a `RMLVO`
+    pub name: String,
+    /// A human-readable description of the model
+    pub description: Option<String>,
+    /// The vendor of the model
+    pub vendor: Option<String>,
+}
+
+/// A layout of the registry, or one of its variants
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RegistryLayout {
+    /// The name of the layout, as used in the `layout` field of a `RMLVO`
+    pub name: String,
+    /// The name of the variant, as used in the `variant` field of a `RMLVO`
+    ///
+    /// `None` for the layout itself.
+    pub variant: Option<String>,
+    /// A short name for the layout, like "us" or "de"
+    pub brief: Option<String>,
+    /// A human-readable description of the layout
+    pub description: Option<String>,
+}
+
+/// A group of related options of the registry
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RegistryOptionGroup {
+    /// The name of the group
+    pub name: String,
+    /// A human-readable description of the group
+    pub description: Option<String>,
+    /// Whether several options of this group can be used together
+    pub allows_multiple: bool,
+    /// The options of the group
+    pub options: Vec<RegistryOption>,
+}
+
+/// An option of the registry
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RegistryOption {
+    /// The name of the option, as used in the `options` field of a `RMLVO`
+    pub name: String,
+    /// A human-readable description of the option
+    pub description: Option<String>,
+}
+
+/// Possible errors when loading the registry
+#[derive(Debug)]
+pub enum RegistryError {
+    /// libxkbregistry is not available
+    RegistryNotFound,
+    /// The rules could not be found or parsed
+    BadRules,
+}
+
+unsafe fn string(ptr: *const c_char) -> Option<String> {
+    if ptr.is_null() {
+        None
+    } else {
+        Some(CStr::from_ptr(ptr).to_string_lossy().into_owned())
+    }
+}
+
+impl Registry {
+    /// Load the registry of the default rules, usually `evdev`
+    pub fn load() -> Result<Registry, RegistryError> {
+        Registry::load_rules(None)
+    }
+
+    /// Load the registry of some rules, or of the default ones if `None`
+    pub fn load_rules(rules: Option<&str>) -> Result<Registry, RegistryError> {
+        let lib = match XKBREGISTRY_OPTION.as_ref() {
+            Some(lib) => lib,
+            None => return Err(RegistryError::RegistryNotFound),
+        };
+        let rules = match rules.map(CString::new) {
+            Some(Ok(rules)) => Some(rules),
+            Some(Err(_)) => return Err(RegistryError::BadRules),
+            None => None,
+        };
+        unsafe {
+            let ctx = (lib.rxkb_context_new)(rxkb_context_flags::RXKB_CONTEXT_NO_FLAGS);
+            if ctx.is_null() {
+                return Err(RegistryError::RegistryNotFound);
+            }
+            let parsed = match rules {
+                Some(ref rules) => (lib.rxkb_context_parse)(ctx, rules.as_ptr()),
+                None => (lib.rxkb_context_parse_default_ruleset)(ctx),
+            };
+            let result = if parsed {
+                Ok(Registry::read(lib, ctx))
+            } else {
+                Err(RegistryError::BadRules)
+            };
+            (lib.rxkb_context_unref)(ctx);
+            result
+        }
+    }
+
+    unsafe fn read(lib: &XkbRegistry, ctx: *mut registry::rxkb_context) -> Registry {
+        let mut models = Vec::new();
+        let mut model = (lib.rxkb_model_first)(ctx);
+        while !model.is_null() {
+            if let Some(name) = string((lib.rxkb_model_get_name)(model)) {
+                models.push(RegistryModel {
+                    name: name,
+                    description: string((lib.rxkb_model_get_description)(model)),
+                    vendor: string((lib.rxkb_model_get_vendor)(model)),
+                });
+            }
+            model = (lib.rxkb_model_next)(model);
+        }
+
+        let mut layouts = Vec::new();
+        let mut layout = (lib.rxkb_layout_first)(ctx);
+        while !layout.is_null() {
+            if let Some(name) = string((lib.rxkb_layout_get_name)(layout)) {
+                layouts.push(RegistryLayout {
+                    name: name,
+                    variant: string((lib.rxkb_layout_get_variant)(layout)),
+                    brief: string((lib.rxkb_layout_get_brief)(layout)),
+                    description: string((lib.rxkb_layout_get_description)(layout)),
+                });
+            }
+            layout = (lib.rxkb_layout_next)(layout);
+        }
+
+        let mut option_groups = Vec::new();
+        let mut group = (lib.rxkb_option_group_first)(ctx);
+        while !group.is_null() {
+            if let Some(name) = string((lib.rxkb_option_group_get_name)(group)) {
+                let mut options = Vec::new();
+                let mut option = (lib.rxkb_option_first)(group);
+                while !option.is_null() {
+                    if let Some(name) = string((lib.rxkb_option_get_name)(option)) {
+                        options.push(RegistryOption {
+                            name: name,
+                            description: string((lib.rxkb_option_get_description)(option)),
+                        });
+                    }
+                    option = (lib.rxkb_option_next)(option);
+                }
+                option_groups.push(RegistryOptionGroup {
+                    name: name,
+                    description: string((lib.rxkb_option_group_get_description)(group)),
+                    allows_multiple: (lib.rxkb_option_group_allows_multiple)(group),
+                    options: options,
+                });
+            }
+            group = (lib.rxkb_option_group_next)(group);
+        }
+
+        Registry {
+            models: models,
+            layouts: layouts,
+            option_groups: option_groups,
+        }
+    }
+}
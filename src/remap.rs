@@ -0,0 +1,198 @@
+use mapped_keyboard::{HeldKey, StateMasks};
+
+// evdev keycodes of the remapped keys
+const KEY_ESC: u32 = 1;
+const KEY_LEFTCTRL: u32 = 29;
+const KEY_LEFTALT: u32 = 56;
+const KEY_CAPSLOCK: u32 = 58;
+const KEY_RIGHTALT: u32 = 100;
+const KEY_LEFTMETA: u32 = 125;
+const KEY_RIGHTMETA: u32 = 126;
+
+/// A common modifier remapping, applied on top of the keymap
+///
+/// These work whatever the source of the keymap, including keymaps provided
+/// by the compositor. The keys are translated as if they were the keys they are
+/// remapped to, and the modifiers state is adjusted accordingly.
+///
+/// The `rawkey` arguments of the callbacks still report the physical keys.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum ModifierRemap {
+    /// The Caps Lock key acts as a Control key
+    ///
+    /// Caps Lock is never locked.
+    CapsLockToControl,
+    /// The Caps Lock key acts as an Escape key
+    ///
+    /// Caps Lock is never locked.
+    CapsLockToEscape,
+    /// The left Alt and left Logo keys are swapped
+    ///
+    /// The right Alt and right Logo keys keep their modifiers.
+    SwapAltLogo,
+}
+
+/// The indices of the modifiers involved in remappings, in the current keymap
+#[derive(Copy, Clone, Debug)]
+pub struct RemapMods {
+    pub ctrl: u32,
+    pub caps: u32,
+    pub alt: u32,
+    pub logo: u32,
+}
+
+fn bit(index: u32) -> u32 {
+    if index < 32 {
+        1 << index
+    } else {
+        0
+    }
+}
+
+/// Get the keycode a physical key is translated as
+pub fn remap_keycode(remaps: &[ModifierRemap], keycode: u32) -> u32 {
+    for remap in remaps {
+        match (*remap, keycode) {
+            (ModifierRemap::CapsLockToControl, KEY_CAPSLOCK) => return KEY_LEFTCTRL,
+            (ModifierRemap::CapsLockToEscape, KEY_CAPSLOCK) => return KEY_ESC,
+            (ModifierRemap::SwapAltLogo, KEY_LEFTALT) => return KEY_LEFTMETA,
+            (ModifierRemap::SwapAltLogo, KEY_LEFTMETA) => return KEY_LEFTALT,
+            _ => {}
+        }
+    }
+    keycode
+}
+
+/// Adjust the modifiers masks sent by the compositor to the remappings
+pub fn remap_masks(remaps: &[ModifierRemap], mods: RemapMods, held: &[HeldKey], masks: StateMasks)
+                   -> StateMasks {
+    let mut masks = masks;
+    for remap in remaps {
+        match *remap {
+            ModifierRemap::CapsLockToControl | ModifierRemap::CapsLockToEscape => {
+                let caps = bit(mods.caps);
+                masks.depressed &= !caps;
+                masks.latched &= !caps;
+                masks.locked &= !caps;
+                let caps_held = held.iter().any(|k| k.keycode == KEY_CAPSLOCK);
+                if *remap == ModifierRemap::CapsLockToControl && caps_held {
+                    masks.depressed |= bit(mods.ctrl);
+                }
+            }
+            ModifierRemap::SwapAltLogo => {
+                // only the modifiers depressed by the left keys are swapped, so that
+                // right Alt, or AltGr when it is an Alt, stays what it is
+                let alt = bit(mods.alt);
+                let logo = bit(mods.logo);
+                let is_held = |keycode| held.iter().any(|k| k.keycode == keycode);
+                let from_left_alt = masks.depressed & alt != 0 && is_held(KEY_LEFTALT);
+                let from_left_logo = masks.depressed & logo != 0 && is_held(KEY_LEFTMETA);
+                let mut depressed = masks.depressed;
+                if from_left_alt && !is_held(KEY_RIGHTALT) {
+                    depressed &= !alt;
+                }
+                if from_left_logo && !is_held(KEY_RIGHTMETA) {
+                    depressed &= !logo;
+                }
+                if from_left_alt {
+                    depressed |= logo;
+                }
+                if from_left_logo {
+                    depressed |= alt;
+                }
+                masks.depressed = depressed;
+            }
+        }
+    }
+    masks
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // the indices of the modifiers in the usual keymaps
+    const MODS: RemapMods = RemapMods {
+        ctrl: 2,
+        caps: 1,
+        alt: 3,
+        logo: 6,
+    };
+    const CTRL: u32 = 1 << 2;
+    const CAPS: u32 = 1 << 1;
+    const ALT: u32 = 1 << 3;
+    const LOGO: u32 = 1 << 6;
+
+    fn held(keycodes: &[u32]) -> Vec<HeldKey> {
+        keycodes
+            .iter()
+            .map(|&keycode| HeldKey {
+                keycode: keycode,
+                keysym: 0,
+                time: Some(0),
+            })
+            .collect()
+    }
+
+    fn masks(depressed: u32, locked: u32) -> StateMasks {
+        StateMasks {
+            depressed: depressed,
+            locked: locked,
+            ..Default::default()
+        }
+    }
+
+    fn remap(remap: ModifierRemap, keycodes: &[u32], masks: StateMasks) -> StateMasks {
+        remap_masks(&[remap], MODS, &held(keycodes), masks)
+    }
+
+    #[test]
+    fn caps_lock_to_control() {
+        let remaps = [ModifierRemap::CapsLockToControl];
+        assert_eq!(remap_keycode(&remaps, KEY_CAPSLOCK), KEY_LEFTCTRL);
+        assert_eq!(remap_keycode(&remaps, KEY_LEFTALT), KEY_LEFTALT);
+        // Caps Lock held is a Control, and never locks
+        let held_caps = remap(ModifierRemap::CapsLockToControl, &[KEY_CAPSLOCK], masks(CAPS, CAPS));
+        assert_eq!(held_caps, masks(CTRL, 0));
+        let released = remap(ModifierRemap::CapsLockToControl, &[], masks(0, CAPS));
+        assert_eq!(released, masks(0, 0));
+    }
+
+    #[test]
+    fn caps_lock_to_escape() {
+        let remaps = [ModifierRemap::CapsLockToEscape];
+        assert_eq!(remap_keycode(&remaps, KEY_CAPSLOCK), KEY_ESC);
+        assert_eq!(remap_keycode(&remaps, KEY_LEFTCTRL), KEY_LEFTCTRL);
+        let held_caps = remap(ModifierRemap::CapsLockToEscape, &[KEY_CAPSLOCK], masks(CAPS, CAPS));
+        assert_eq!(held_caps, masks(0, 0));
+    }
+
+    #[test]
+    fn swap_alt_logo_keycodes() {
+        let remaps = [ModifierRemap::SwapAltLogo];
+        assert_eq!(remap_keycode(&remaps, KEY_LEFTALT), KEY_LEFTMETA);
+        assert_eq!(remap_keycode(&remaps, KEY_LEFTMETA), KEY_LEFTALT);
+        assert_eq!(remap_keycode(&remaps, KEY_RIGHTALT), KEY_RIGHTALT);
+        assert_eq!(remap_keycode(&remaps, KEY_RIGHTMETA), KEY_RIGHTMETA);
+        assert_eq!(remap_keycode(&remaps, KEY_CAPSLOCK), KEY_CAPSLOCK);
+    }
+
+    #[test]
+    fn swap_alt_logo_left_keys() {
+        assert_eq!(remap(ModifierRemap::SwapAltLogo, &[KEY_LEFTALT], masks(ALT, 0)), masks(LOGO, 0));
+        assert_eq!(remap(ModifierRemap::SwapAltLogo, &[KEY_LEFTMETA], masks(LOGO, 0)), masks(ALT, 0));
+        let both = remap(ModifierRemap::SwapAltLogo, &[KEY_LEFTALT, KEY_LEFTMETA], masks(ALT | LOGO, 0));
+        assert_eq!(both, masks(ALT | LOGO, 0));
+    }
+
+    #[test]
+    fn swap_alt_logo_right_keys_untouched() {
+        assert_eq!(remap(ModifierRemap::SwapAltLogo, &[KEY_RIGHTALT], masks(ALT, 0)), masks(ALT, 0));
+        assert_eq!(remap(ModifierRemap::SwapAltLogo, &[KEY_RIGHTMETA], masks(LOGO, 0)), masks(LOGO, 0));
+        // the right key keeps its modifier when the left one of the other is swapped into it
+        let right_alt = remap(ModifierRemap::SwapAltLogo, &[KEY_RIGHTALT, KEY_LEFTALT], masks(ALT, 0));
+        assert_eq!(right_alt, masks(ALT | LOGO, 0));
+        let right_logo = remap(ModifierRemap::SwapAltLogo, &[KEY_RIGHTMETA, KEY_LEFTMETA], masks(LOGO, 0));
+        assert_eq!(right_logo, masks(ALT | LOGO, 0));
+    }
+}
@@ -0,0 +1,284 @@
+use mapped_keyboard::ModifiersState;
+use std::time::{Duration, Instant};
+
+// used when the compositor does not send repeat information (wl_keyboard before version 4)
+pub const DEFAULT_REPEAT_INFO: (i32, i32) = (25, 600);
+
+// faster rates are clamped, so that the interval between repeats is at least 1ms
+pub const MAX_REPEAT_RATE: i32 = 1000;
+
+/// How key repeat behaves
+///
+/// Toolkits disagree on these, use `RepeatPolicy::default()` for the most common
+/// behavior.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct RepeatPolicy {
+    /// Whether pressing a key stops the repeat of the key previously held
+    ///
+    /// If `true` (the default), the new key starts repeating instead. If `false`,
+    /// the key already repeating keeps repeating and the new key does not repeat.
+    pub new_press_cancels: bool,
+    /// Whether repeats are translated again when the modifiers change
+    ///
+    /// If `true` (the default), holding `a` then pressing Shift repeats `A`. If
+    /// `false`, repeats produce the keysym and text of the original press.
+    pub retranslate_on_modifiers: bool,
+    /// Whether the repeat stops when the modifiers change
+    ///
+    /// Defaults to `false`.
+    pub stop_on_modifiers: bool,
+    /// Whether the repeats missed while the application stalled are dropped
+    ///
+    /// If `true` (the default), `KeyboardHandle::poll_repeat()` returns at most one
+    /// repeat, the last one due, so that a hiccup does not replay a burst of stale
+    /// repeats. Its `repeat_count` still counts the dropped ones. If `false`, every
+    /// repeat due is returned.
+    pub coalesce: bool,
+}
+
+impl Default for RepeatPolicy {
+    fn default() -> RepeatPolicy {
+        RepeatPolicy {
+            new_press_cancels: true,
+            retranslate_on_modifiers: true,
+            stop_on_modifiers: false,
+            coalesce: true,
+        }
+    }
+}
+
+/// The key currently repeating
+#[derive(Clone, Debug)]
+pub struct RepeatState {
+    pub keycode: u32,
+    pub serial: u32,
+    pub time: u32,
+    pub keysym: u32,
+    pub layout: u32,
+    pub utf8: Option<String>,
+    pub mods: ModifiersState,
+    pub next: Instant,
+    pub interval: Duration,
+    /// The number of repeats generated so far
+    pub count: u32,
+    pub rate: i32,
+    pub delay: i32,
+    // the timestamp of the repeat numbered `base_count + 1`, rebased when reconfigured
+    base_time: u32,
+    base_count: u32,
+    // the timestamp of the press or of the last repeat
+    last_time: u32,
+}
+
+// the interval between repeats at `rate` repeats per second
+fn repeat_interval(rate: i32) -> Duration {
+    let interval_us = 1_000_000 / rate as u64;
+    Duration::new(interval_us / 1_000_000, (interval_us % 1_000_000) as u32 * 1000)
+}
+
+impl RepeatState {
+    /// Start repeating a key pressed at `now`, with the given rate and delay
+    ///
+    /// Returns `None` if repeat is disabled, with a rate of 0. Rates above
+    /// `MAX_REPEAT_RATE` are clamped to it.
+    pub fn start(keycode: u32, serial: u32, time: u32, keysym: u32, layout: u32, utf8: Option<String>,
+                 mods: ModifiersState, (rate, delay): (i32, i32), now: Instant)
+                 -> Option<RepeatState> {
+        if rate <= 0 {
+            return None;
+        }
+        let rate = ::std::cmp::min(rate, MAX_REPEAT_RATE);
+        let delay = ::std::cmp::max(delay, 0);
+        Some(RepeatState {
+            keycode: keycode,
+            serial: serial,
+            time: time,
+            keysym: keysym,
+            layout: layout,
+            utf8: utf8,
+            mods: mods,
+            next: now + Duration::from_millis(delay as u64),
+            interval: repeat_interval(rate),
+            count: 0,
+            rate: rate,
+            delay: delay,
+            base_time: time.wrapping_add(delay as u32),
+            base_count: 0,
+            last_time: time,
+        })
+    }
+
+    /// Apply a new rate and delay, sent by the compositor while the key is held
+    ///
+    /// A key still waiting for its delay repeats after the new delay from its press,
+    /// and a key already repeating continues at the new rate from its last repeat.
+    /// Returns `false` if repeat is now disabled, with a rate of 0. Rates above
+    /// `MAX_REPEAT_RATE` are clamped to it.
+    pub fn reconfigure(&mut self, (rate, delay): (i32, i32)) -> bool {
+        if rate <= 0 {
+            return false;
+        }
+        let rate = ::std::cmp::min(rate, MAX_REPEAT_RATE);
+        let delay = ::std::cmp::max(delay, 0);
+        if rate == self.rate && delay == self.delay {
+            return true;
+        }
+        let interval = repeat_interval(rate);
+        if self.count == 0 {
+            let pressed = self.next - Duration::from_millis(self.delay as u64);
+            self.next = pressed + Duration::from_millis(delay as u64);
+            self.base_time = self.time.wrapping_add(delay as u32);
+        } else {
+            let last = self.next - self.interval;
+            self.next = last + interval;
+            self.base_time = self.last_time.wrapping_add((1000 / rate as u64) as u32);
+        }
+        self.base_count = self.count;
+        self.interval = interval;
+        self.rate = rate;
+        self.delay = delay;
+        true
+    }
+
+    /// Account for the next repeat, returning its timestamp
+    pub fn advance(&mut self) -> u32 {
+        let time = self.next_time();
+        self.count += 1;
+        self.next += self.interval;
+        self.last_time = time;
+        time
+    }
+
+    /// The number of repeats due at `now`
+    pub fn due(&self, now: Instant) -> u32 {
+        if self.next > now {
+            return 0;
+        }
+        let nanos = |d: Duration| d.as_secs() * 1_000_000_000 + u64::from(d.subsec_nanos());
+        let due = nanos(now - self.next) / nanos(self.interval) + 1;
+        ::std::cmp::min(due, u64::from(u32::max_value())) as u32
+    }
+
+    /// Account for the next `count` repeats without generating them
+    pub fn skip(&mut self, count: u32) {
+        if count == 0 {
+            return;
+        }
+        self.count += count - 1;
+        self.next += self.interval * (count - 1);
+        self.advance();
+    }
+
+    /// Skip the repeats due at `now` but the last one, returning how many were skipped
+    pub fn skip_stale(&mut self, now: Instant) -> u32 {
+        let skipped = self.due(now).saturating_sub(1);
+        self.skip(skipped);
+        skipped
+    }
+
+    /// The timestamp of the next repeat, in the time base of the key events
+    ///
+    /// It is computed from the timestamp of the key press, so that repeats are
+    /// evenly spaced whenever they are actually polled.
+    pub fn next_time(&self) -> u32 {
+        let elapsed = u64::from(self.count - self.base_count) * 1000 / self.rate as u64;
+        self.base_time.wrapping_add(elapsed as u32)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // a key pressed at timestamp 1000, repeating 25 times per second after 600ms
+    fn pressed(now: Instant) -> RepeatState {
+        RepeatState::start(30, 1, 1000, 0x61, 0, None, ModifiersState::default(), (25, 600), now).unwrap()
+    }
+
+    fn ms(ms: u64) -> Duration {
+        Duration::from_millis(ms)
+    }
+
+    #[test]
+    fn reconfigure_during_delay() {
+        let now = Instant::now();
+        let mut repeat = pressed(now);
+        assert!(repeat.reconfigure((50, 300)));
+        // the new delay counts from the press
+        assert_eq!(repeat.next, now + ms(300));
+        assert_eq!(repeat.next_time(), 1300);
+        assert_eq!(repeat.advance(), 1300);
+        assert_eq!(repeat.next, now + ms(320));
+        assert_eq!(repeat.next_time(), 1320);
+    }
+
+    #[test]
+    fn reconfigure_while_repeating() {
+        let now = Instant::now();
+        let mut repeat = pressed(now);
+        assert_eq!(repeat.advance(), 1600);
+        assert_eq!(repeat.advance(), 1640);
+        // the new rate counts from the last repeat
+        assert!(repeat.reconfigure((10, 600)));
+        assert_eq!(repeat.next, now + ms(740));
+        assert_eq!(repeat.next_time(), 1740);
+        assert_eq!(repeat.advance(), 1740);
+        assert_eq!(repeat.next_time(), 1840);
+        assert_eq!(repeat.count, 3);
+    }
+
+    #[test]
+    fn reconfigure_unchanged() {
+        let now = Instant::now();
+        let mut repeat = pressed(now);
+        repeat.advance();
+        assert!(repeat.reconfigure((25, 600)));
+        assert_eq!(repeat.next, now + ms(640));
+        assert_eq!(repeat.next_time(), 1640);
+    }
+
+    #[test]
+    fn reconfigure_disabled() {
+        let now = Instant::now();
+        let mut repeat = pressed(now);
+        repeat.advance();
+        assert!(!repeat.reconfigure((0, 600)));
+        assert!(RepeatState::start(30, 1, 1000, 0x61, 0, None, ModifiersState::default(), (0, 600), now)
+            .is_none());
+    }
+
+    #[test]
+    fn skip_without_generating() {
+        let now = Instant::now();
+        let mut repeat = pressed(now);
+        assert_eq!(repeat.due(now + ms(599)), 0);
+        assert_eq!(repeat.due(now + ms(600)), 1);
+        assert_eq!(repeat.due(now + ms(600 + 40 * 9)), 10);
+        repeat.skip(7);
+        assert_eq!(repeat.count, 7);
+        assert_eq!(repeat.next, now + ms(600 + 40 * 7));
+        assert_eq!(repeat.advance(), 1600 + 40 * 7);
+        // an hour of stall at the highest rate is skipped at once
+        assert!(repeat.reconfigure((MAX_REPEAT_RATE, 600)));
+        let later = repeat.next + Duration::from_secs(3600);
+        assert_eq!(repeat.skip_stale(later), 3_600_000);
+        assert_eq!(repeat.due(later), 1);
+    }
+
+    #[test]
+    fn timestamps_continuous() {
+        let now = Instant::now();
+        let mut repeat = pressed(now);
+        let mut last = 1000;
+        for &(step, info) in &[(0, (50, 200)), (3, (30, 500)), (5, (1000, 0)), (2, (7, 900))] {
+            for _ in 0..step {
+                let time = repeat.advance();
+                assert!(time > last);
+                last = time;
+            }
+            assert!(repeat.reconfigure(info));
+            // the next repeat never comes before the last one
+            assert!(repeat.next_time() > last);
+        }
+    }
+}
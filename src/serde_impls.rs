@@ -0,0 +1,198 @@
+//! Implementations of `Serialize` and `Deserialize` for the `serde` feature
+//!
+//! They are written with macros rather than derived, so that the feature does not pull
+//! in `serde_derive` and the proc-macro crates it builds on, which need a much newer
+//! compiler than the rest of the crate. Structs are serialized like the derived
+//! implementations would, as maps of their fields, and unit enums as their variant.
+
+use mapped_keyboard::Modifiers;
+use serde::de::{self, Deserialize, Deserializer, Unexpected};
+use serde::ser::{Serialize, Serializer};
+
+/// Implement `Serialize` and `Deserialize` for a struct with named fields
+///
+/// Every field must be listed, in declaration order.
+macro_rules! serde_struct {
+    ($name:ident { $($field:ident),* }) => {
+        impl ::serde::Serialize for $name {
+            fn serialize<S: ::serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                use ::serde::ser::SerializeStruct;
+                let fields: &[&str] = &[$(stringify!($field)),*];
+                let mut state = serializer.serialize_struct(stringify!($name), fields.len())?;
+                $(state.serialize_field(stringify!($field), &self.$field)?;)*
+                state.end()
+            }
+        }
+
+        impl<'de> ::serde::Deserialize<'de> for $name {
+            fn deserialize<D: ::serde::Deserializer<'de>>(deserializer: D) -> Result<$name, D::Error> {
+                use ::serde::de::{self, MapAccess, SeqAccess};
+
+                struct StructVisitor;
+
+                impl<'de> de::Visitor<'de> for StructVisitor {
+                    type Value = $name;
+
+                    fn expecting(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+                        write!(f, "struct {}", stringify!($name))
+                    }
+
+                    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<$name, A::Error> {
+                        let mut read = 0;
+                        $(
+                            let $field = match seq.next_element()? {
+                                Some(value) => value,
+                                None => return Err(de::Error::invalid_length(read, &self)),
+                            };
+                            read += 1;
+                        )*
+                        let _ = read;
+                        Ok($name { $($field: $field),* })
+                    }
+
+                    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<$name, A::Error> {
+                        $(let mut $field = None;)*
+                        while let Some(key) = map.next_key::<String>()? {
+                            match &key[..] {
+                                $(stringify!($field) => {
+                                    if $field.is_some() {
+                                        return Err(de::Error::duplicate_field(stringify!($field)));
+                                    }
+                                    $field = Some(map.next_value()?);
+                                })*
+                                // fields added by later versions
+                                _ => {
+                                    map.next_value::<de::IgnoredAny>()?;
+                                }
+                            }
+                        }
+                        $(
+                            let $field = match $field {
+                                Some(value) => value,
+                                None => return Err(de::Error::missing_field(stringify!($field))),
+                            };
+                        )*
+                        Ok($name { $($field: $field),* })
+                    }
+                }
+
+                const FIELDS: &'static [&'static str] = &[$(stringify!($field)),*];
+                deserializer.deserialize_struct(stringify!($name), FIELDS, StructVisitor)
+            }
+        }
+    };
+}
+
+/// Implement `Serialize` and `Deserialize` for an enum whose variants have no fields
+macro_rules! serde_unit_enum {
+    ($name:ident { $($variant:ident),* }) => {
+        impl ::serde::Serialize for $name {
+            fn serialize<S: ::serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                let variant = match *self {
+                    $($name::$variant => stringify!($variant)),*
+                };
+                serializer.serialize_unit_variant(stringify!($name), *self as u32, variant)
+            }
+        }
+
+        impl<'de> ::serde::Deserialize<'de> for $name {
+            fn deserialize<D: ::serde::Deserializer<'de>>(deserializer: D) -> Result<$name, D::Error> {
+                use ::serde::de::{self, EnumAccess, VariantAccess};
+
+                const VARIANTS: &'static [&'static str] = &[$(stringify!($variant)),*];
+
+                struct EnumVisitor;
+
+                impl<'de> de::Visitor<'de> for EnumVisitor {
+                    type Value = $name;
+
+                    fn expecting(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+                        write!(f, "enum {}", stringify!($name))
+                    }
+
+                    fn visit_enum<A: EnumAccess<'de>>(self, data: A) -> Result<$name, A::Error> {
+                        let (variant, access) = data.variant::<String>()?;
+                        access.unit_variant()?;
+                        match &variant[..] {
+                            $(stringify!($variant) => Ok($name::$variant),)*
+                            _ => Err(de::Error::unknown_variant(&variant, VARIANTS)),
+                        }
+                    }
+                }
+
+                deserializer.deserialize_enum(stringify!($name), VARIANTS, EnumVisitor)
+            }
+        }
+    };
+}
+
+// the derived implementation of bitflags serialized the modifiers as a struct holding
+// their bits, which is kept so that stored events still load
+struct ModifiersBits {
+    bits: u8,
+}
+
+serde_struct!(ModifiersBits { bits });
+
+impl Serialize for Modifiers {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        ModifiersBits { bits: self.bits() }.serialize(serializer)
+    }
+}
+
+// unlike the derived implementation, bits that are not a modifier are refused
+impl<'de> Deserialize<'de> for Modifiers {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Modifiers, D::Error> {
+        let bits = ModifiersBits::deserialize(deserializer)?.bits;
+        Modifiers::from_bits(bits).ok_or_else(|| {
+            de::Error::invalid_value(Unexpected::Unsigned(u64::from(bits)), &"a set of modifiers")
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use compose::ComposeStatus;
+    use keymap::KeyLocation;
+    use serde::de::value::{Error, MapDeserializer, StrDeserializer};
+    use serde::de::IntoDeserializer;
+
+    fn modifiers(bits: u8) -> Result<Modifiers, Error> {
+        let map = vec![("bits", bits)].into_iter();
+        Modifiers::deserialize(MapDeserializer::<_, Error>::new(map))
+    }
+
+    #[test]
+    fn modifiers_known_bits() {
+        assert_eq!(modifiers(0).unwrap(), Modifiers::empty());
+        assert_eq!(modifiers(5).unwrap(), Modifiers::CTRL | Modifiers::SHIFT);
+        assert_eq!(modifiers(63).unwrap(), Modifiers::all());
+    }
+
+    #[test]
+    fn modifiers_unknown_bits() {
+        assert!(modifiers(64).is_err());
+        assert!(modifiers(Modifiers::CTRL.bits() | 128).is_err());
+    }
+
+    #[test]
+    fn struct_fields() {
+        let map = vec![("level", 2), ("keycode", 30), ("added_later", 7), ("layout", 1)].into_iter();
+        let location = KeyLocation::deserialize(MapDeserializer::<_, Error>::new(map)).unwrap();
+        assert_eq!(location, KeyLocation { keycode: 30, layout: 1, level: 2 });
+
+        let map = vec![("keycode", 30), ("layout", 1)].into_iter();
+        assert!(KeyLocation::deserialize(MapDeserializer::<_, Error>::new(map)).is_err());
+        let map = vec![("keycode", 30), ("layout", 1), ("level", 2), ("level", 3)].into_iter();
+        assert!(KeyLocation::deserialize(MapDeserializer::<_, Error>::new(map)).is_err());
+    }
+
+    #[test]
+    fn unit_enum_variants() {
+        let variant: StrDeserializer<Error> = "Composed".into_deserializer();
+        assert_eq!(ComposeStatus::deserialize(variant).unwrap(), ComposeStatus::Composed);
+        let variant: StrDeserializer<Error> = "Composing ".into_deserializer();
+        assert!(ComposeStatus::deserialize(variant).is_err());
+    }
+}
@@ -0,0 +1,99 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::sync::{Arc, Mutex};
+
+/// Implementation data shared with the rest of the application
+///
+/// The implementation data is owned by the event queue, so state needed both by the
+/// callbacks and by the rest of the application is usually put in a
+/// `Rc<RefCell<T>>`. This wraps one, and borrows it without panicking:
+///
+/// ```ignore
+/// let data = Shared::new(AppState::new());
+/// register_kbd(evqh, &keyboard, implementation, data.clone())?;
+/// // in the callbacks
+/// key: |_, data, _, _, event| {
+///     data.with(|state| state.on_key(event));
+/// }
+/// ```
+///
+/// The callbacks run while the event queue is dispatched: a borrow taken outside of
+/// them must not be held across `EventQueue::dispatch()`, or `with()` returns `None`
+/// in the callbacks. Use `SyncShared` to share the data with other threads.
+pub struct Shared<T> {
+    inner: Rc<RefCell<T>>,
+}
+
+impl<T> Shared<T> {
+    /// Share some data
+    pub fn new(data: T) -> Shared<T> {
+        Shared::from_rc(Rc::new(RefCell::new(data)))
+    }
+
+    /// Use data already shared
+    pub fn from_rc(inner: Rc<RefCell<T>>) -> Shared<T> {
+        Shared { inner: inner }
+    }
+
+    /// The shared data, for the rest of the application
+    pub fn rc(&self) -> Rc<RefCell<T>> {
+        self.inner.clone()
+    }
+
+    /// Run `f` with the data borrowed mutably
+    ///
+    /// Returns `None` without running `f` if the data is already borrowed.
+    pub fn with<R, F: FnOnce(&mut T) -> R>(&self, f: F) -> Option<R> {
+        match self.inner.try_borrow_mut() {
+            Ok(mut data) => Some(f(&mut *data)),
+            Err(_) => None,
+        }
+    }
+}
+
+impl<T> Clone for Shared<T> {
+    fn clone(&self) -> Shared<T> {
+        Shared::from_rc(self.inner.clone())
+    }
+}
+
+/// Implementation data shared with the rest of the application and other threads
+///
+/// This is the `Arc<Mutex<T>>` counterpart of `Shared`. The lock is taken for the
+/// duration of `with()`, so another thread holding it delays the dispatching of the
+/// events. A lock poisoned by a panic is recovered.
+pub struct SyncShared<T> {
+    inner: Arc<Mutex<T>>,
+}
+
+impl<T> SyncShared<T> {
+    /// Share some data
+    pub fn new(data: T) -> SyncShared<T> {
+        SyncShared::from_arc(Arc::new(Mutex::new(data)))
+    }
+
+    /// Use data already shared
+    pub fn from_arc(inner: Arc<Mutex<T>>) -> SyncShared<T> {
+        SyncShared { inner: inner }
+    }
+
+    /// The shared data, for the rest of the application
+    pub fn arc(&self) -> Arc<Mutex<T>> {
+        self.inner.clone()
+    }
+
+    /// Run `f` with the data locked
+    pub fn with<R, F: FnOnce(&mut T) -> R>(&self, f: F) -> R {
+        let mut data = match self.inner.lock() {
+            Ok(data) => data,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        f(&mut *data)
+    }
+}
+
+impl<T> Clone for SyncShared<T> {
+    fn clone(&self) -> SyncShared<T> {
+        SyncShared::from_arc(self.inner.clone())
+    }
+}
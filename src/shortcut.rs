@@ -0,0 +1,180 @@
+use keymap::KeyCombo;
+use label::{self, LabelMods, LabelStyle};
+use mapped_keyboard::{Modifiers, ModifiersState};
+
+/// The key of a shortcut
+#[derive(Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum ShortcutKey {
+    /// A keysym, matched against the keysym produced by the key
+    Keysym(u32),
+    /// A physical key, by its XKB name
+    ///
+    /// The name can be given with or without its angle brackets, like `"<TLDE>"` or
+    /// `"TLDE"`. Such shortcuts do not depend on the layout, which is useful for games
+    /// and emulators.
+    Name(String),
+}
+
+/// How the key of a shortcut is matched against key presses
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum ShortcutMatching {
+    /// The key must produce the keysym of the shortcut, with exactly the modifiers
+    /// of the shortcut active
+    Keysym,
+    /// Like `Keysym`, but the modifiers used to reach the shift level of the keysym
+    /// are not counted
+    ///
+    /// For example, a Control + `plus` shortcut is triggered by Control + Shift + `=`
+    /// on a US layout. A shortcut also matches if its keysym is on the first level of
+    /// the key and its modifiers are active, so that Control + Shift + `a` triggers
+    /// Control + Shift + `a` even though the key produces `A`.
+    ///
+    /// This has no effect on shortcuts on physical keys.
+    Level,
+    /// Like `Level`, but the shortcut also matches if the key produces its keysym in
+    /// any other layout of the keymap
+    ///
+    /// For example, Control + `c` is triggered by the same key when a Russian layout
+    /// is active, if a US layout is configured too. In the other layouts, the keysym
+    /// of the current shift level or of the first level must match, with exactly the
+    /// modifiers of the shortcut active.
+    AnyLayout,
+}
+
+impl Default for ShortcutMatching {
+    fn default() -> ShortcutMatching {
+        ShortcutMatching::Keysym
+    }
+}
+
+/// A keyboard shortcut
+///
+/// A shortcut is triggered when its key is pressed while exactly its modifiers are
+/// active. Caps Lock and Num Lock are ignored. Triggered shortcuts are notified
+/// through the `shortcut` callback, after the `key` callback of the key.
+///
+/// See `ShortcutMatching` for how the modifiers used by the layout itself are handled.
+#[derive(Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Shortcut {
+    /// Whether the "control" modifier is required
+    pub ctrl: bool,
+    /// Whether the "alt" modifier is required
+    pub alt: bool,
+    /// Whether the "shift" modifier is required
+    pub shift: bool,
+    /// Whether the "logo" modifier is required
+    pub logo: bool,
+    /// The key of the shortcut
+    pub key: ShortcutKey,
+    /// How the key is matched
+    pub matching: ShortcutMatching,
+}
+
+impl Shortcut {
+    /// A shortcut on a key without any modifier
+    pub fn new(key: ShortcutKey) -> Shortcut {
+        Shortcut {
+            ctrl: false,
+            alt: false,
+            shift: false,
+            logo: false,
+            key: key,
+            matching: ShortcutMatching::Keysym,
+        }
+    }
+
+    /// A label for this shortcut, like "Ctrl+Alt+T"
+    ///
+    /// The key is named after the keysym of the shortcut, so Control + `slash` is
+    /// "Ctrl+/". Use `KeyboardHandle::shortcut_label()` to name the keys typing it
+    /// with the current keymap instead.
+    pub fn label(&self, style: &LabelStyle) -> String {
+        let key = match self.key {
+            ShortcutKey::Keysym(keysym) => label::key_label(keysym, style),
+            ShortcutKey::Name(ref name) => name.trim_matches(|c| c == '<' || c == '>').to_owned(),
+        };
+        label::label(self.label_mods(), &key, style)
+    }
+
+    /// A label for this shortcut, typed with `combo`
+    pub(crate) fn combo_label(&self, combo: &KeyCombo, style: &LabelStyle) -> String {
+        let mut mods = self.label_mods();
+        mods.ctrl |= combo.mods.ctrl();
+        mods.alt |= combo.mods.alt();
+        mods.shift |= combo.mods.shift();
+        mods.logo |= combo.mods.logo();
+        mods.alt_gr = combo.alt_gr;
+        label::label(mods, &label::key_label(combo.base_keysym, style), style)
+    }
+
+    fn label_mods(&self) -> LabelMods {
+        LabelMods {
+            ctrl: self.ctrl,
+            alt: self.alt,
+            shift: self.shift,
+            logo: self.logo,
+            alt_gr: false,
+        }
+    }
+
+    /// Check if a key press triggers this shortcut, with exactly its modifiers
+    ///
+    /// `keysym` is the keysym produced by the key, and `key_name` its XKB name. This
+    /// ignores `matching`.
+    pub fn matches(&self, mods: &ModifiersState, keysym: u32, key_name: Option<&str>) -> bool {
+        if self.ctrl != mods.ctrl() || self.alt != mods.alt() || self.shift != mods.shift()
+            || self.logo != mods.logo()
+        {
+            return false;
+        }
+        match self.key {
+            ShortcutKey::Keysym(sym) => sym == keysym,
+            ShortcutKey::Name(ref name) => {
+                let name = if name.starts_with('<') && name.ends_with('>') && name.len() >= 2 {
+                    &name[1..name.len() - 1]
+                } else {
+                    &name[..]
+                };
+                key_name == Some(name)
+            }
+        }
+    }
+
+    /// Check if a key press triggers this shortcut, in `ShortcutMatching::Level` mode
+    ///
+    /// `consumed` are the modifiers consumed by the layout to produce `keysym`, and
+    /// `on_first_level` tells if a keysym is on the first level of the pressed key.
+    pub(crate) fn matches_level<F>(&self, mods: &ModifiersState, consumed: &ModifiersState, keysym: u32,
+                                   key_name: Option<&str>, on_first_level: F)
+                                   -> bool
+    where
+        F: Fn(u32) -> bool,
+    {
+        // the lock modifiers are never ignored
+        let ignored = consumed.modifiers - (Modifiers::CAPS_LOCK | Modifiers::NUM_LOCK);
+        let unconsumed = ModifiersState {
+            modifiers: mods.modifiers - ignored,
+            status: mods.status,
+        };
+        if self.matches(&unconsumed, keysym, key_name) {
+            return true;
+        }
+        match self.key {
+            ShortcutKey::Keysym(sym) => on_first_level(sym) && self.matches(mods, sym, key_name),
+            ShortcutKey::Name(_) => false,
+        }
+    }
+
+    /// Check if a key press triggers this shortcut in one of the other layouts
+    ///
+    /// `other_layouts` are the keysyms of the key in each other layout, at the
+    /// current shift level and at the first level.
+    pub(crate) fn matches_other_layouts(&self, mods: &ModifiersState, other_layouts: &[(u32, u32)]) -> bool {
+        match self.key {
+            ShortcutKey::Keysym(_) => other_layouts
+                .iter()
+                .any(|&(sym, first)| self.matches(mods, sym, None) || self.matches(mods, first, None)),
+            ShortcutKey::Name(_) => false,
+        }
+    }
+}
@@ -0,0 +1,46 @@
+use compose::ComposeStatus;
+use mapped_keyboard::ModifiersState;
+
+/// The state of the lock LEDs of a keyboard
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct LockLeds {
+    /// The Caps Lock LED
+    pub caps_lock: bool,
+    /// The Num Lock LED
+    pub num_lock: bool,
+    /// The Scroll Lock LED
+    pub scroll_lock: bool,
+}
+
+#[cfg(feature = "serde")]
+serde_struct!(LockLeds { caps_lock, num_lock, scroll_lock });
+
+/// The state of a keyboard at a point in time
+///
+/// It is obtained from `KeyboardHandle::state_report()`. Unlike `DebugDump`, which is
+/// meant to be read by humans, it can be compared in tests and, with the `serde`
+/// feature, serialized for structured logging.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct StateReport {
+    /// The names of the layouts of the keymap, empty if no keymap is loaded
+    pub layouts: Vec<String>,
+    /// The index of the active layout
+    pub active_layout: Option<u32>,
+    /// The effective modifiers
+    pub modifiers: ModifiersState,
+    /// The lock LEDs, as the keymap defines them
+    pub leds: LockLeds,
+    /// The status of the compose engine, `None` if compose is not in use
+    pub compose: Option<ComposeStatus>,
+    /// The keysyms of the pending compose sequence
+    pub compose_pending: Vec<u32>,
+    /// The repeat rate and delay in use
+    pub repeat_info: (i32, i32),
+    /// Whether the key repeat is paused, see `RepeatHandle::pause()`
+    pub repeat_paused: bool,
+}
+
+#[cfg(feature = "serde")]
+serde_struct!(StateReport {
+    layouts, active_layout, modifiers, leds, compose, compose_pending, repeat_info, repeat_paused
+});
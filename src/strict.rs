@@ -0,0 +1,94 @@
+/// A violation of the `wl_keyboard` protocol by the compositor
+///
+/// They are reported through the `error` callback when `KeyboardOptions::strict` is
+/// set, which helps debugging misbehaving compositors. The events are handled all the
+/// same.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum ProtocolViolation {
+    /// An enter event was received while the keyboard already had focus
+    EnterWhileFocused,
+    /// A leave event was received while the keyboard had no focus
+    LeaveWithoutEnter,
+    /// A key event was received while the keyboard had no focus
+    KeyWithoutFocus,
+    /// A key event was received between an enter event and the modifiers event
+    /// that must follow it
+    KeyBeforeModifiers,
+    /// A key event was received before any keymap
+    KeyBeforeKeymap,
+    /// The serial of an event is older than the serial of a previous event
+    SerialDecreased {
+        /// The serial of the previous event
+        previous: u32,
+        /// The serial of this event
+        serial: u32,
+    },
+}
+
+/// The state of the protocol, as needed to detect violations
+#[derive(Clone, Debug, Default)]
+pub struct ProtocolTracker {
+    focused: bool,
+    modifiers_received: bool,
+    keymap_received: bool,
+    last_serial: Option<u32>,
+}
+
+impl ProtocolTracker {
+    pub fn keymap(&mut self) {
+        self.keymap_received = true;
+    }
+
+    pub fn enter(&mut self, serial: u32) -> Vec<ProtocolViolation> {
+        let mut violations = self.serial(serial);
+        if self.focused {
+            violations.push(ProtocolViolation::EnterWhileFocused);
+        }
+        self.focused = true;
+        self.modifiers_received = false;
+        violations
+    }
+
+    pub fn leave(&mut self, serial: u32) -> Vec<ProtocolViolation> {
+        let mut violations = self.serial(serial);
+        if !self.focused {
+            violations.push(ProtocolViolation::LeaveWithoutEnter);
+        }
+        self.focused = false;
+        violations
+    }
+
+    pub fn modifiers(&mut self, serial: u32) -> Vec<ProtocolViolation> {
+        let violations = self.serial(serial);
+        self.modifiers_received = true;
+        violations
+    }
+
+    pub fn key(&mut self, serial: u32) -> Vec<ProtocolViolation> {
+        let mut violations = self.serial(serial);
+        if !self.focused {
+            violations.push(ProtocolViolation::KeyWithoutFocus);
+        } else if !self.modifiers_received {
+            violations.push(ProtocolViolation::KeyBeforeModifiers);
+        }
+        if !self.keymap_received {
+            violations.push(ProtocolViolation::KeyBeforeKeymap);
+        }
+        violations
+    }
+
+    fn serial(&mut self, serial: u32) -> Vec<ProtocolViolation> {
+        let previous = self.last_serial;
+        self.last_serial = Some(serial);
+        match previous {
+            // serials wrap around, compare them as a signed difference
+            Some(previous) if (serial.wrapping_sub(previous) as i32) < 0 => {
+                vec![ProtocolViolation::SerialDecreased {
+                    previous: previous,
+                    serial: serial,
+                }]
+            }
+            _ => Vec::new(),
+        }
+    }
+}
@@ -0,0 +1,62 @@
+use ffi::keysyms;
+use mapped_keyboard::{is_modifier_keysym, HeldKey};
+
+/// Modifier keys that were held when the focus was lost, and are not held anymore
+/// when it comes back
+///
+/// Applications tracking modifiers from key events never see the release of these
+/// keys, which is the classic cause of a "stuck Alt" after Alt+Tab. It is notified
+/// through the `stuck_modifiers` callback, after the `enter` callback.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct StuckModifiers {
+    /// Whether the "control" modifier was held and is not anymore
+    pub ctrl: bool,
+    /// Whether the "alt" modifier was held and is not anymore
+    pub alt: bool,
+    /// Whether the "shift" modifier was held and is not anymore
+    pub shift: bool,
+    /// Whether the "logo" modifier was held and is not anymore
+    pub logo: bool,
+    /// The raw keycodes of the modifier keys released while the focus was lost
+    pub keys: Vec<u32>,
+}
+
+// the index of the flag of a modifier keysym, in ctrl, alt, shift, logo order
+fn modifier_flag(keysym: u32) -> Option<usize> {
+    match keysym {
+        keysyms::XKB_KEY_Control_L | keysyms::XKB_KEY_Control_R => Some(0),
+        keysyms::XKB_KEY_Alt_L | keysyms::XKB_KEY_Alt_R | keysyms::XKB_KEY_Meta_L
+        | keysyms::XKB_KEY_Meta_R => Some(1),
+        keysyms::XKB_KEY_Shift_L | keysyms::XKB_KEY_Shift_R => Some(2),
+        keysyms::XKB_KEY_Super_L | keysyms::XKB_KEY_Super_R | keysyms::XKB_KEY_Hyper_L
+        | keysyms::XKB_KEY_Hyper_R => Some(3),
+        _ => None,
+    }
+}
+
+impl StuckModifiers {
+    /// Compare the keys held when the focus was lost with the ones held when it came back
+    pub(crate) fn detect(before: &[HeldKey], now: &[HeldKey]) -> Option<StuckModifiers> {
+        let released: Vec<&HeldKey> = before
+            .iter()
+            .filter(|k| is_modifier_keysym(k.keysym) && !now.iter().any(|n| n.keycode == k.keycode))
+            .collect();
+        if released.is_empty() {
+            return None;
+        }
+        let mut flags = [false; 4];
+        for key in &released {
+            if let Some(flag) = modifier_flag(key.keysym) {
+                // the modifier may still be held with another key, like the other Shift
+                flags[flag] = !now.iter().any(|n| modifier_flag(n.keysym) == Some(flag));
+            }
+        }
+        Some(StuckModifiers {
+            ctrl: flags[0],
+            alt: flags[1],
+            shift: flags[2],
+            logo: flags[3],
+            keys: released.iter().map(|k| k.keycode).collect(),
+        })
+    }
+}
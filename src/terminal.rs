@@ -0,0 +1,331 @@
+use ffi::keysyms;
+use keymap::keysym_char;
+use mapped_keyboard::{is_modifier_keysym, KeyEvent, KeyOrigin, ModifiersState};
+use wayland_client::protocol::wl_keyboard::KeyState;
+
+bitflags!(
+    /// The progressive enhancements of the kitty keyboard protocol
+    ///
+    /// Applications push these flags with `CSI > flags u`, the terminal keeps track of
+    /// them and gives them in `TerminalModes::kitty_flags`.
+    #[derive(Default)]
+    pub struct KittyFlags: u32 {
+        /// Send keys that are ambiguous in the legacy encoding, like Escape or Alt+letter,
+        /// as `CSI u` sequences
+        const DISAMBIGUATE = 1;
+        /// Report repeats and releases
+        const REPORT_EVENT_TYPES = 2;
+        /// Report the shifted key along with the key
+        const REPORT_ALTERNATE_KEYS = 4;
+        /// Send all keys as `CSI u` sequences, including text and modifier keys
+        const REPORT_ALL_KEYS = 8;
+        /// Send the text of the keys in the sequences, with `REPORT_ALL_KEYS`
+        const REPORT_TEXT = 16;
+    }
+);
+
+/// The modes of a terminal that change how keys are encoded
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct TerminalModes {
+    /// Whether the cursor keys send `SS3` sequences (DECCKM)
+    pub application_cursor: bool,
+    /// Whether the keypad keys send `SS3` sequences instead of their text (DECKPAM)
+    pub application_keypad: bool,
+    /// Whether Alt sends an Escape before the text of the key
+    pub alt_sends_escape: bool,
+    /// The enhancements of the kitty keyboard protocol in use, empty for the legacy
+    /// xterm encoding
+    pub kitty_flags: KittyFlags,
+}
+
+impl Default for TerminalModes {
+    fn default() -> TerminalModes {
+        TerminalModes {
+            application_cursor: false,
+            application_keypad: false,
+            alt_sends_escape: true,
+            kitty_flags: KittyFlags::empty(),
+        }
+    }
+}
+
+// the final byte and number of the CSI sequences of the functional keys
+#[derive(Copy, Clone)]
+enum Functional {
+    // `CSI X`, or `SS3 X` in application cursor mode
+    Cursor(u8),
+    // `SS3 X` in the legacy encoding, `CSI X` in the kitty protocol
+    Pf(u8),
+    // `CSI n ~`
+    Tilde(u32),
+}
+
+fn functional(keysym: u32) -> Option<Functional> {
+    let functional = match keysym {
+        keysyms::XKB_KEY_Up | keysyms::XKB_KEY_KP_Up => Functional::Cursor(b'A'),
+        keysyms::XKB_KEY_Down | keysyms::XKB_KEY_KP_Down => Functional::Cursor(b'B'),
+        keysyms::XKB_KEY_Right | keysyms::XKB_KEY_KP_Right => Functional::Cursor(b'C'),
+        keysyms::XKB_KEY_Left | keysyms::XKB_KEY_KP_Left => Functional::Cursor(b'D'),
+        keysyms::XKB_KEY_Begin | keysyms::XKB_KEY_KP_Begin => Functional::Cursor(b'E'),
+        keysyms::XKB_KEY_End | keysyms::XKB_KEY_KP_End => Functional::Cursor(b'F'),
+        keysyms::XKB_KEY_Home | keysyms::XKB_KEY_KP_Home => Functional::Cursor(b'H'),
+        keysyms::XKB_KEY_Insert | keysyms::XKB_KEY_KP_Insert => Functional::Tilde(2),
+        keysyms::XKB_KEY_Delete | keysyms::XKB_KEY_KP_Delete => Functional::Tilde(3),
+        keysyms::XKB_KEY_Page_Up | keysyms::XKB_KEY_KP_Page_Up => Functional::Tilde(5),
+        keysyms::XKB_KEY_Page_Down | keysyms::XKB_KEY_KP_Page_Down => Functional::Tilde(6),
+        keysyms::XKB_KEY_F1 => Functional::Pf(b'P'),
+        keysyms::XKB_KEY_F2 => Functional::Pf(b'Q'),
+        keysyms::XKB_KEY_F3 => Functional::Pf(b'R'),
+        keysyms::XKB_KEY_F4 => Functional::Pf(b'S'),
+        keysyms::XKB_KEY_F5 => Functional::Tilde(15),
+        keysyms::XKB_KEY_F6 => Functional::Tilde(17),
+        keysyms::XKB_KEY_F7 => Functional::Tilde(18),
+        keysyms::XKB_KEY_F8 => Functional::Tilde(19),
+        keysyms::XKB_KEY_F9 => Functional::Tilde(20),
+        keysyms::XKB_KEY_F10 => Functional::Tilde(21),
+        keysyms::XKB_KEY_F11 => Functional::Tilde(23),
+        keysyms::XKB_KEY_F12 => Functional::Tilde(24),
+        _ => return None,
+    };
+    Some(functional)
+}
+
+// the codes of the kitty protocol for the keys without a legacy sequence
+fn kitty_code(keysym: u32) -> Option<u32> {
+    let code = match keysym {
+        keysyms::XKB_KEY_Escape => 27,
+        keysyms::XKB_KEY_Return => 13,
+        keysyms::XKB_KEY_Tab | keysyms::XKB_KEY_ISO_Left_Tab => 9,
+        keysyms::XKB_KEY_BackSpace => 127,
+        keysyms::XKB_KEY_Caps_Lock => 57358,
+        keysyms::XKB_KEY_Scroll_Lock => 57359,
+        keysyms::XKB_KEY_Num_Lock => 57360,
+        keysyms::XKB_KEY_Print => 57361,
+        keysyms::XKB_KEY_Pause => 57362,
+        keysyms::XKB_KEY_Menu => 57363,
+        sym if sym >= keysyms::XKB_KEY_F13 && sym <= keysyms::XKB_KEY_F35 => {
+            57376 + sym - keysyms::XKB_KEY_F13
+        }
+        sym if sym >= keysyms::XKB_KEY_KP_0 && sym <= keysyms::XKB_KEY_KP_9 => {
+            57399 + sym - keysyms::XKB_KEY_KP_0
+        }
+        keysyms::XKB_KEY_KP_Decimal => 57409,
+        keysyms::XKB_KEY_KP_Divide => 57410,
+        keysyms::XKB_KEY_KP_Multiply => 57411,
+        keysyms::XKB_KEY_KP_Subtract => 57412,
+        keysyms::XKB_KEY_KP_Add => 57413,
+        keysyms::XKB_KEY_KP_Enter => 57414,
+        keysyms::XKB_KEY_KP_Equal => 57415,
+        keysyms::XKB_KEY_KP_Separator => 57416,
+        keysyms::XKB_KEY_Shift_L => 57441,
+        keysyms::XKB_KEY_Control_L => 57442,
+        keysyms::XKB_KEY_Alt_L => 57443,
+        keysyms::XKB_KEY_Super_L => 57444,
+        keysyms::XKB_KEY_Hyper_L => 57445,
+        keysyms::XKB_KEY_Meta_L => 57446,
+        keysyms::XKB_KEY_Shift_R => 57447,
+        keysyms::XKB_KEY_Control_R => 57448,
+        keysyms::XKB_KEY_Alt_R => 57449,
+        keysyms::XKB_KEY_Super_R => 57450,
+        keysyms::XKB_KEY_Hyper_R => 57451,
+        keysyms::XKB_KEY_Meta_R => 57452,
+        keysyms::XKB_KEY_ISO_Level3_Shift => 57453,
+        keysyms::XKB_KEY_ISO_Level5_Shift => 57454,
+        _ => return None,
+    };
+    Some(code)
+}
+
+// the final byte of the application keypad sequences
+fn keypad_final(keysym: u32) -> Option<u8> {
+    match keysym {
+        keysyms::XKB_KEY_KP_Equal => Some(b'X'),
+        // the keysyms of these keys are chosen to map to the sequences
+        keysyms::XKB_KEY_KP_Enter => Some((keysym - 0xff40) as u8),
+        sym if sym >= keysyms::XKB_KEY_KP_Multiply && sym <= keysyms::XKB_KEY_KP_9 => {
+            Some((sym - 0xff40) as u8)
+        }
+        _ => None,
+    }
+}
+
+// the modifiers parameter of xterm, without the lock modifiers
+fn xterm_modifiers(mods: &ModifiersState) -> u32 {
+    1 + mods.shift() as u32 + 2 * mods.alt() as u32 + 4 * mods.ctrl() as u32 + 8 * mods.logo() as u32
+}
+
+fn csi(out: &mut Vec<u8>, params: &str, final_byte: u8) {
+    out.extend_from_slice(b"\x1b[");
+    out.extend_from_slice(params.as_bytes());
+    out.push(final_byte);
+}
+
+/// Encode a key event into the bytes a terminal sends to the application
+///
+/// Without `TerminalModes::kitty_flags`, the keys are encoded like xterm does, with
+/// the modifiers of cursor, editing and function keys as parameters. Otherwise they
+/// are encoded following the kitty keyboard protocol.
+///
+/// Returns `None` if the key sends nothing, like modifier keys and, unless reported
+/// by the kitty protocol, key releases.
+pub fn encode_key(event: &KeyEvent, modes: &TerminalModes) -> Option<Vec<u8>> {
+    if modes.kitty_flags.is_empty() {
+        encode_legacy(event, modes)
+    } else {
+        encode_kitty(event, modes)
+    }
+}
+
+fn encode_legacy(event: &KeyEvent, modes: &TerminalModes) -> Option<Vec<u8>> {
+    if event.state == KeyState::Released {
+        return None;
+    }
+    let mut out = Vec::new();
+    let mods = xterm_modifiers(&event.mods);
+    if let Some(functional) = functional(event.keysym) {
+        match functional {
+            _ if mods > 1 => {
+                let (number, final_byte) = match functional {
+                    Functional::Cursor(c) | Functional::Pf(c) => (1, c),
+                    Functional::Tilde(n) => (n, b'~'),
+                };
+                csi(&mut out, &format!("{};{}", number, mods), final_byte);
+            }
+            Functional::Cursor(c) if modes.application_cursor => out.extend_from_slice(&[0x1b, b'O', c]),
+            Functional::Cursor(c) => csi(&mut out, "", c),
+            Functional::Pf(c) => out.extend_from_slice(&[0x1b, b'O', c]),
+            Functional::Tilde(n) => csi(&mut out, &n.to_string(), b'~'),
+        }
+        return Some(out);
+    }
+    if modes.application_keypad {
+        if let Some(c) = keypad_final(event.keysym) {
+            out.extend_from_slice(&[0x1b, b'O', c]);
+            return Some(out);
+        }
+    }
+    if event.mods.alt() && modes.alt_sends_escape {
+        out.push(0x1b);
+    }
+    match event.keysym {
+        keysyms::XKB_KEY_ISO_Left_Tab => csi(&mut out, "", b'Z'),
+        keysyms::XKB_KEY_Tab if event.mods.shift() => csi(&mut out, "", b'Z'),
+        keysyms::XKB_KEY_BackSpace if event.mods.ctrl() => out.push(0x08),
+        keysyms::XKB_KEY_BackSpace => out.push(0x7f),
+        _ => match event.utf8 {
+            Some(ref text) if !text.is_empty() => out.extend_from_slice(text.as_bytes()),
+            _ => return None,
+        },
+    }
+    Some(out)
+}
+
+fn encode_kitty(event: &KeyEvent, modes: &TerminalModes) -> Option<Vec<u8>> {
+    let flags = modes.kitty_flags;
+    let all_keys = flags.contains(KittyFlags::REPORT_ALL_KEYS);
+    let event_type = match (event.state, event.origin) {
+        (KeyState::Released, _) => 3,
+        (KeyState::Pressed, KeyOrigin::Repeat) => 2,
+        (KeyState::Pressed, KeyOrigin::Device) => 1,
+    };
+    if event_type == 3 && !flags.contains(KittyFlags::REPORT_EVENT_TYPES) {
+        return None;
+    }
+    let mods = &event.mods;
+    // the lock modifiers are only reported along with all the keys, so that they do not
+    // change the text keys
+    let value = if all_keys {
+        xterm_modifiers(mods) + 64 * mods.caps_lock() as u32 + 128 * mods.num_lock() as u32
+    } else {
+        xterm_modifiers(mods)
+    };
+    if !all_keys && is_modifier_keysym(event.keysym) {
+        return None;
+    }
+    // the text keys without modifiers other than Shift are sent as text, like the
+    // plain Enter, Tab and Backspace, and so are their releases not reported
+    let plain = !(mods.ctrl() || mods.alt() || mods.logo());
+    if !all_keys && plain {
+        let legacy = match event.keysym {
+            keysyms::XKB_KEY_Return => Some(vec![b'\r']),
+            keysyms::XKB_KEY_Tab if !mods.shift() => Some(vec![b'\t']),
+            keysyms::XKB_KEY_BackSpace => Some(vec![0x7f]),
+            _ if functional(event.keysym).is_some() => None,
+            _ if event_type == 3 && keysym_char(event.keysym).is_some() => return None,
+            _ => match event.utf8 {
+                Some(ref text) if !text.is_empty() && !text.chars().any(|c| c.is_control()) => {
+                    Some(text.as_bytes().to_vec())
+                }
+                _ => None,
+            },
+        };
+        if legacy.is_some() {
+            return if event_type == 3 { None } else { legacy };
+        }
+    }
+    let mut modifiers = String::new();
+    if value > 1 || event_type > 1 {
+        modifiers.push_str(&value.to_string());
+        if event_type > 1 {
+            modifiers.push_str(&format!(":{}", event_type));
+        }
+    }
+    let mut out = Vec::new();
+    if let Some(functional) = functional(event.keysym) {
+        let (number, final_byte) = match functional {
+            // F3 would conflict with the cursor position report
+            Functional::Pf(b'R') => (13, b'~'),
+            Functional::Cursor(c) | Functional::Pf(c) => (1, c),
+            Functional::Tilde(n) => (n, b'~'),
+        };
+        let params = if modifiers.is_empty() {
+            if final_byte == b'~' {
+                number.to_string()
+            } else {
+                String::new()
+            }
+        } else {
+            format!("{};{}", number, modifiers)
+        };
+        csi(&mut out, &params, final_byte);
+        return Some(out);
+    }
+    let mut key = match kitty_code(event.keysym) {
+        Some(code) => code.to_string(),
+        None => {
+            let c = match keysym_char(event.keysym) {
+                Some(c) => c,
+                None => return None,
+            };
+            // the key is reported unshifted, which is approximated by the lowercase
+            let base = if mods.shift() { c.to_lowercase().next().unwrap_or(c) } else { c };
+            let mut key = (base as u32).to_string();
+            if flags.contains(KittyFlags::REPORT_ALTERNATE_KEYS) && base != c {
+                key.push_str(&format!(":{}", c as u32));
+            }
+            key
+        }
+    };
+    if all_keys && flags.contains(KittyFlags::REPORT_TEXT) && event_type != 3 {
+        if let Some(ref text) = event.utf8 {
+            let codepoints: Vec<String> = text
+                .chars()
+                .filter(|c| !c.is_control())
+                .map(|c| (c as u32).to_string())
+                .collect();
+            if !codepoints.is_empty() {
+                if modifiers.is_empty() {
+                    modifiers.push('1');
+                }
+                key = format!("{};{};{}", key, modifiers, codepoints.join(":"));
+                csi(&mut out, &key, b'u');
+                return Some(out);
+            }
+        }
+    }
+    if !modifiers.is_empty() {
+        key = format!("{};{}", key, modifiers);
+    }
+    csi(&mut out, &key, b'u');
+    Some(out)
+}
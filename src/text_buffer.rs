@@ -0,0 +1,113 @@
+use classify::NavKey;
+use mapped_keyboard::KeyEvent;
+use wayland_client::protocol::wl_keyboard::KeyState;
+
+/// A line of text edited with key events
+///
+/// It inserts the text of the key presses at the cursor, and handles BackSpace,
+/// Delete, Left, Right, Home and End. This is enough for simple prompts, examples
+/// and tests, not for a real text widget.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct TextBuffer {
+    text: String,
+    // byte offset, always on a char boundary
+    cursor: usize,
+}
+
+impl TextBuffer {
+    /// Create an empty buffer
+    pub fn new() -> TextBuffer {
+        TextBuffer::default()
+    }
+
+    /// Update the buffer with a key event, including repeats
+    ///
+    /// Returns whether the text or the cursor changed.
+    pub fn handle(&mut self, event: &KeyEvent) -> bool {
+        if event.state != KeyState::Pressed {
+            return false;
+        }
+        match event.nav {
+            Some(NavKey::Backspace) => match self.prev_boundary() {
+                Some(prev) => {
+                    self.text.drain(prev..self.cursor);
+                    self.cursor = prev;
+                    true
+                }
+                None => false,
+            },
+            Some(NavKey::Delete) => match self.next_boundary() {
+                Some(next) => {
+                    self.text.drain(self.cursor..next);
+                    true
+                }
+                None => false,
+            },
+            Some(NavKey::Left) => {
+                let prev = self.prev_boundary();
+                self.move_to(prev)
+            }
+            Some(NavKey::Right) => {
+                let next = self.next_boundary();
+                self.move_to(next)
+            }
+            Some(NavKey::Home) => self.move_to(Some(0)),
+            Some(NavKey::End) => {
+                let end = self.text.len();
+                self.move_to(Some(end))
+            }
+            Some(_) => false,
+            None => match event.utf8 {
+                Some(ref txt) if event.kind.is_printable() => {
+                    self.text.insert_str(self.cursor, txt);
+                    self.cursor += txt.len();
+                    true
+                }
+                _ => false,
+            },
+        }
+    }
+
+    /// The current text
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    /// The position of the cursor, as a byte offset in `text()`
+    pub fn cursor(&self) -> usize {
+        self.cursor
+    }
+
+    /// Empty the buffer
+    pub fn clear(&mut self) {
+        self.text.clear();
+        self.cursor = 0;
+    }
+
+    /// Empty the buffer, returning its text
+    pub fn take(&mut self) -> String {
+        self.cursor = 0;
+        ::std::mem::replace(&mut self.text, String::new())
+    }
+
+    fn prev_boundary(&self) -> Option<usize> {
+        self.text[..self.cursor].char_indices().next_back().map(|(idx, _)| idx)
+    }
+
+    fn next_boundary(&self) -> Option<usize> {
+        self.text[self.cursor..]
+            .chars()
+            .next()
+            .map(|c| self.cursor + c.len_utf8())
+    }
+
+    fn move_to(&mut self, position: Option<usize>) -> bool {
+        match position {
+            Some(position) if position != self.cursor => {
+                self.cursor = position;
+                true
+            }
+            _ => false,
+        }
+    }
+}
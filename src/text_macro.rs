@@ -0,0 +1,168 @@
+use mapped_keyboard::is_modifier_keysym;
+
+/// A key sequence expanding to some text
+///
+/// When the keysyms of `keysyms` are pressed in order, the text they would
+/// have produced is replaced by `text`, delivered through the `utf8` argument
+/// of the `key` callback of the last key of the sequence.
+///
+/// While a sequence is being typed, the text of its keys is held back. It is
+/// delivered as usual as soon as a key not continuing any sequence is pressed.
+/// Modifier keys do not interrupt a sequence.
+#[derive(Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct TextMacro {
+    /// The keysyms that must be pressed in order to trigger the macro
+    pub keysyms: Vec<u32>,
+    /// The text the sequence expands to
+    pub text: String,
+}
+
+/// Tracks the progress of the typed keys through the registered macros
+pub struct TextMacroState {
+    pending: Vec<u32>,
+    pending_text: String,
+}
+
+impl TextMacroState {
+    pub fn new() -> TextMacroState {
+        TextMacroState {
+            pending: Vec::new(),
+            pending_text: String::new(),
+        }
+    }
+
+    /// Feed a key press to the macros, returning the text it should produce
+    pub fn feed(&mut self, macros: &[TextMacro], keysym: u32, utf8: Option<String>) -> Option<String> {
+        if macros.is_empty() || is_modifier_keysym(keysym) {
+            return utf8;
+        }
+
+        self.pending.push(keysym);
+        if let Some(ref txt) = utf8 {
+            self.pending_text.push_str(txt);
+        }
+        match self.lookup(macros) {
+            Lookup::Complete(text) => {
+                self.pending.clear();
+                self.pending_text.clear();
+                return Some(text);
+            }
+            Lookup::Prefix => return None,
+            Lookup::None => {}
+        }
+
+        // the sequence is broken, flush the text held back so far
+        // and try starting a new sequence with this key
+        let mut out = ::std::mem::replace(&mut self.pending_text, String::new());
+        if let Some(ref txt) = utf8 {
+            let len = out.len() - txt.len();
+            out.truncate(len);
+        }
+        self.pending.clear();
+        self.pending.push(keysym);
+        match self.lookup(macros) {
+            Lookup::Complete(text) => {
+                self.pending.clear();
+                out.push_str(&text);
+            }
+            Lookup::Prefix => if let Some(txt) = utf8 {
+                self.pending_text = txt;
+            },
+            Lookup::None => {
+                self.pending.clear();
+                if let Some(txt) = utf8 {
+                    out.push_str(&txt);
+                }
+            }
+        }
+
+        if out.is_empty() {
+            None
+        } else {
+            Some(out)
+        }
+    }
+
+    fn lookup(&self, macros: &[TextMacro]) -> Lookup {
+        let mut prefix = false;
+        for m in macros.iter().filter(|m| !m.keysyms.is_empty()) {
+            if m.keysyms == self.pending {
+                return Lookup::Complete(m.text.clone());
+            }
+            if m.keysyms.starts_with(&self.pending) {
+                prefix = true;
+            }
+        }
+        if prefix {
+            Lookup::Prefix
+        } else {
+            Lookup::None
+        }
+    }
+}
+
+enum Lookup {
+    Complete(String),
+    Prefix,
+    None,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ffi::keysyms;
+
+    fn arrow() -> Vec<TextMacro> {
+        vec![
+            TextMacro {
+                keysyms: vec![keysyms::XKB_KEY_minus, keysyms::XKB_KEY_greater],
+                text: "→".to_owned(),
+            },
+        ]
+    }
+
+    fn feed(state: &mut TextMacroState, macros: &[TextMacro], keysym: u32, utf8: &str) -> Option<String> {
+        state.feed(macros, keysym, Some(utf8.to_owned()))
+    }
+
+    #[test]
+    fn expansion() {
+        let mut state = TextMacroState::new();
+        assert_eq!(feed(&mut state, &arrow(), keysyms::XKB_KEY_minus, "-"), None);
+        assert_eq!(feed(&mut state, &arrow(), keysyms::XKB_KEY_greater, ">"), Some("→".to_owned()));
+        // the next key starts afresh
+        assert_eq!(feed(&mut state, &arrow(), keysyms::XKB_KEY_greater, ">"), Some(">".to_owned()));
+    }
+
+    #[test]
+    fn modifiers_continue_sequence() {
+        let mut state = TextMacroState::new();
+        assert_eq!(feed(&mut state, &arrow(), keysyms::XKB_KEY_minus, "-"), None);
+        assert_eq!(state.feed(&arrow(), keysyms::XKB_KEY_Shift_L, None), None);
+        assert_eq!(feed(&mut state, &arrow(), keysyms::XKB_KEY_greater, ">"), Some("→".to_owned()));
+    }
+
+    #[test]
+    fn broken_sequence_flushed() {
+        let mut state = TextMacroState::new();
+        assert_eq!(feed(&mut state, &arrow(), keysyms::XKB_KEY_minus, "-"), None);
+        assert_eq!(feed(&mut state, &arrow(), keysyms::XKB_KEY_x, "x"), Some("-x".to_owned()));
+        assert_eq!(feed(&mut state, &arrow(), keysyms::XKB_KEY_x, "x"), Some("x".to_owned()));
+    }
+
+    #[test]
+    fn broken_sequence_restarted() {
+        let mut state = TextMacroState::new();
+        assert_eq!(feed(&mut state, &arrow(), keysyms::XKB_KEY_minus, "-"), None);
+        // the first minus is flushed, the second one may start the sequence
+        assert_eq!(feed(&mut state, &arrow(), keysyms::XKB_KEY_minus, "-"), Some("-".to_owned()));
+        assert_eq!(feed(&mut state, &arrow(), keysyms::XKB_KEY_greater, ">"), Some("→".to_owned()));
+    }
+
+    #[test]
+    fn no_macros() {
+        let mut state = TextMacroState::new();
+        assert_eq!(feed(&mut state, &[], keysyms::XKB_KEY_minus, "-"), Some("-".to_owned()));
+        assert_eq!(state.feed(&[], keysyms::XKB_KEY_Return, None), None);
+    }
+}
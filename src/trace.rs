@@ -0,0 +1,73 @@
+//! Internal instrumentation macros
+//!
+//! When the `tracing` feature is enabled, the span and event macros forward to the
+//! `tracing` crate, otherwise they expand to nothing. Likewise, allocation assertions
+//! only exist with the `alloc-assertions` feature, and the libxkbcommon objects are
+//! only counted with the `leak-check` feature.
+
+/// Enter a span for the rest of the current scope
+#[cfg(feature = "tracing")]
+macro_rules! kbd_span {
+    ($($args:tt)*) => { ::tracing::trace_span!($($args)*).entered() };
+}
+
+#[cfg(not(feature = "tracing"))]
+macro_rules! kbd_span {
+    ($($args:tt)*) => { () };
+}
+
+/// Emit an event
+#[cfg(feature = "tracing")]
+macro_rules! kbd_event {
+    ($($args:tt)*) => { ::tracing::trace!($($args)*) };
+}
+
+#[cfg(not(feature = "tracing"))]
+macro_rules! kbd_event {
+    ($($args:tt)*) => { () };
+}
+
+/// Assert that the rest of the current scope does not allocate
+#[cfg(feature = "alloc-assertions")]
+macro_rules! kbd_no_alloc {
+    ($section:expr) => { ::alloc_check::NoAlloc::new($section) };
+}
+
+#[cfg(not(feature = "alloc-assertions"))]
+macro_rules! kbd_no_alloc {
+    ($section:expr) => { () };
+}
+
+/// Create or reference a libxkbcommon object, counting it unless it is null
+#[cfg(feature = "leak-check")]
+macro_rules! xkb_acquire {
+    ($kind:ident, $acquire:expr) => {{
+        let object = $acquire;
+        if !object.is_null() {
+            ::leak_check::acquired(::leak_check::XkbObject::$kind);
+        }
+        object
+    }};
+}
+
+#[cfg(not(feature = "leak-check"))]
+macro_rules! xkb_acquire {
+    ($kind:ident, $acquire:expr) => { $acquire };
+}
+
+/// Release a reference to a libxkbcommon object, uncounting it unless it is null
+#[cfg(feature = "leak-check")]
+macro_rules! xkb_release {
+    ($kind:ident, $unref:expr, $object:expr) => {{
+        let object = $object;
+        if !object.is_null() {
+            ::leak_check::released(::leak_check::XkbObject::$kind);
+        }
+        ($unref)(object)
+    }};
+}
+
+#[cfg(not(feature = "leak-check"))]
+macro_rules! xkb_release {
+    ($kind:ident, $unref:expr, $object:expr) => { ($unref)($object) };
+}
@@ -0,0 +1,72 @@
+include!(concat!(env!("OUT_DIR"), "/unicode_names.rs"));
+
+/// A coarse Unicode category of a character
+///
+/// It is computed from the character properties of the standard library, which do
+/// not tell punctuation, symbols and marks apart.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum CharCategory {
+    /// An uppercase letter, like `A`
+    UppercaseLetter,
+    /// A lowercase letter, like `a`
+    LowercaseLetter,
+    /// A letter without case, like the kana
+    OtherLetter,
+    /// A digit or another numeric character, like `½`
+    Number,
+    /// A space character
+    Whitespace,
+    /// A control character, like the ones produced by Ctrl+letter
+    Control,
+    /// A punctuation mark, a symbol or a combining mark
+    Other,
+}
+
+/// The Unicode description of a character, see `char_info()`
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct CharInfo {
+    /// The character
+    pub character: char,
+    /// The Unicode name of the character, like `"LATIN SMALL LETTER E WITH ACUTE"`
+    ///
+    /// Only the characters having a keysym are named, which covers the characters
+    /// produced by keyboard layouts.
+    pub name: Option<&'static str>,
+    /// The category of the character
+    pub category: CharCategory,
+}
+
+impl CharInfo {
+    /// The code point of the character, for display like `U+00E9`
+    pub fn codepoint(&self) -> u32 {
+        self.character as u32
+    }
+}
+
+/// Describe a character, for input debugging tools and screen readers
+pub fn char_info(c: char) -> CharInfo {
+    let category = if c.is_control() {
+        CharCategory::Control
+    } else if c.is_whitespace() {
+        CharCategory::Whitespace
+    } else if c.is_uppercase() {
+        CharCategory::UppercaseLetter
+    } else if c.is_lowercase() {
+        CharCategory::LowercaseLetter
+    } else if c.is_alphabetic() {
+        CharCategory::OtherLetter
+    } else if c.is_numeric() {
+        CharCategory::Number
+    } else {
+        CharCategory::Other
+    };
+    let name = UNICODE_NAMES
+        .binary_search_by_key(&(c as u32), |&(codepoint, _)| codepoint)
+        .ok()
+        .map(|index| UNICODE_NAMES[index].1);
+    CharInfo {
+        character: c,
+        name: name,
+        category: category,
+    }
+}